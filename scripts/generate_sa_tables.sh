@@ -29,6 +29,9 @@ DB_TYPES="swissprot,trembl"
 # Constant string used to indicate temp files generated by Unipept
 UNIPEPT_TEMP_CONSTANT="unipept_temp"
 
+# How much memory are the sorting steps allowed to use? This parameter should be formatted like "2G" or "512M".
+SORT_MEMORY="2G"
+
 # Make sure that all temporary files are cleaned up if something goes wrong during execution of this script
 trap terminateAndExit SIGINT
 trap errorAndExit ERR
@@ -49,7 +52,8 @@ trap clean EXIT
 # in the first argument ($1). The function supports "swissprot" and "trembl".  #
 # For each database type, it attempts to download, decompress, and convert it  #
 # to a tabular format, storing the result in the uniprot_entries.tsv.lz4 file  #
-# as a result.                                                                 #
+# as a result. The sequences of these entries are also concatenated into the   #
+# protein text from which the suffix array is built.                           #
 #                                                                              #
 # Globals:                                                                     #
 #   CURRENT_LOCATION   - Current script directory                              #
@@ -63,6 +67,9 @@ trap clean EXIT
 #                                                                              #
 # Outputs:                                                                     #
 #   uniprot_entries.tsv.lz4 - Processed and compressed UniProt entries file    #
+#   protein_text.txt.lz4    - Compressed concatenation of all sequences        #
+#   protein_offsets.bin     - Maps positions in the protein text to entry ids  #
+#   protein_stats.tsv.lz4   - Sequence-level properties of every entry         #
 #                                                                              #
 # Returns:                                                                     #
 #   None                                                                       #
@@ -73,28 +80,65 @@ download_and_process_uniprot() {
   local temp_constant="$3"
   local output_dir="$4"
 
-  have "$output_dir/taxons.tsv.lz4" || return
+  have "$output_dir/taxons.tsv.lz4" "$output_dir/merged_taxa.tsv.lz4" || return
 
   log "Started generating the uniprot_entries file."
 
   download_uniprot "$db_types" \
   | "$CURRENT_LOCATION"/rust-utils/target/release/uniprot-parser \
       --taxa "$(luz "$output_dir/taxons.tsv.lz4")" \
+      --merged-taxa "$(luz "$output_dir/merged_taxa.tsv.lz4")" \
       --proteomes "$(lz "$temp_dir/$temp_constant/proteomes.tsv.lz4")" \
-      --uniprot-entries "$(lz "$output_dir/uniprot_entries.tsv.lz4")"
+      --uniprot-entries "$(lz "$output_dir/uniprot_entries.tsv.lz4")" \
+      --protein-text "$(lz "$output_dir/protein_text.txt.lz4")" \
+      --protein-offsets "$output_dir/protein_offsets.bin" \
+      --protein-stats "$(lz "$output_dir/protein_stats.tsv.lz4")"
 
   log "Finished generating the uniprot_entries file."
 }
 
+################################################################################
+# validate_protein_text                                                        #
+#                                                                              #
+# Checks that the protein text and its offsets file match the sequences and    #
+# ids of all UniProt entries, and aborts the script if they do not.            #
+#                                                                              #
+# Globals:                                                                     #
+#   CURRENT_LOCATION   - Current script directory                              #
+#                                                                              #
+# Arguments:                                                                   #
+#   $1 - Output directory containing the UniProt entries and protein text      #
+#                                                                              #
+# Returns:                                                                     #
+#   None                                                                       #
+################################################################################
+validate_protein_text() {
+  local output_dir="$1"
+
+  have "$output_dir/uniprot_entries.tsv.lz4" "$output_dir/protein_text.txt.lz4" "$output_dir/protein_offsets.bin" || return
+
+  log "Started validating the protein text."
+
+  "$CURRENT_LOCATION"/rust-utils/target/release/protein-text-validator \
+      --uniprot-entries "$(luz "$output_dir/uniprot_entries.tsv.lz4")" \
+      --protein-text "$(luz "$output_dir/protein_text.txt.lz4")" \
+      --protein-offsets "$output_dir/protein_offsets.bin"
+
+  log "Finished validating the protein text."
+}
+
 ################################################################################
 # compute_reference_proteomes                                                  #
 #                                                                              #
 # Processes and computes reference proteomes by joining proteome data with     #
 # reference proteome data. The resulting data is sorted, formatted, and        #
 # compressed into a file named proteomes.tsv.lz4 in the output directory.      #
+# Reference proteomes without any entries are listed in                        #
+# unmatched_reference_proteomes.txt in the output directory.                   #
 #                                                                              #
 # Globals:                                                                     #
-#   CMD_LZ4CAT - Command or path to the lz4cat binary                          #
+#   CURRENT_LOCATION - Directory containing the rust-utils binaries            #
+#   SORT_MEMORY      - Amount of memory the sorting steps are allowed to use   #
 #                                                                              #
 # Arguments:                                                                   #
 #   $1 - Temporary directory used to store intermediate files                  #
@@ -103,6 +147,7 @@ download_and_process_uniprot() {
 #                                                                              #
 # Outputs:                                                                     #
 #   proteomes.tsv.lz4 - Processed and compressed reference proteomes file      #
+#   unmatched_reference_proteomes.txt - Reference proteomes without entries    #
 #                                                                              #
 # Returns:                                                                     #
 #   None                                                                       #
@@ -118,34 +163,16 @@ compute_reference_proteomes() {
   log "Started computing reference proteomes."
 
   local work_dir="$temp_dir/$temp_constant"
-  local input_file="$work_dir/proteomes.tsv.lz4"
-  local reference_file="$work_dir/reference_proteomes.tsv.lz4"
-
-  # Create temp files in the specified directory
-  local temp1 temp2 temp3 temp4
-  temp1=$(mktemp --tmpdir="$work_dir")
-  temp2=$(mktemp --tmpdir="$work_dir")
-  temp3=$(mktemp --tmpdir="$work_dir")
-  temp4=$(mktemp --tmpdir="$work_dir")
 
-  # Step 1: Decompress and sort input
-  lz4cat "$input_file" | sort --temporary-directory "$work_dir" > "$temp1"
+  "$CURRENT_LOCATION"/rust-utils/target/release/reference-proteomes \
+    --proteomes "$(luz "$work_dir/proteomes.tsv.lz4")" \
+    --reference-proteomes "$(luz "$work_dir/reference_proteomes.tsv.lz4")" \
+    --output-file "$(lz "$output_dir/proteomes.tsv.lz4")" \
+    --unmatched-report "$output_dir/unmatched_reference_proteomes.txt" \
+    --sort-memory "$SORT_MEMORY" \
+    --temp-dir "$work_dir"
 
-  # Step 2: Collapse
-  collapse < "$temp1" > "$temp2"
-
-  # Step 3: Decompress reference proteomes
-  $CMD_LZ4CAT "$reference_file" > "$temp3"
-
-  # Step 4: Join
-  join --nocheck-order -a2 -e '' -t $'\t' -o "2.1 2.2 2.3 1.2" - "$temp3" < "$temp2" > "$temp4"
-
-  # Step 5: Number lines, clean format, compress output
-  cat -n "$temp4" | sed "s/^ *//" | $CMD_LZ4 > "$output_dir/proteomes.tsv.lz4"
-
-  # Cleanup
-  rm -f "$temp1" "$temp2" "$temp3" "$temp4"
-  
+  log "Found $(wc -l < "$output_dir/unmatched_reference_proteomes.txt") reference proteomes without entries."
   log "Finished computing reference proteomes."
 }
 
@@ -165,6 +192,7 @@ compute_reference_proteomes() {
 #   DB_TYPES     - Comma-separated list of database sources                    #
 #   OUTPUT_DIR   - Directory to save the output files                          #
 #   TEMP_DIR     - Temporary directory for intermediate files                  #
+#   SORT_MEMORY  - Amount of memory the sorting steps are allowed to use       #
 #                                                                              #
 # Arguments:                                                                   #
 #   Command-line arguments                                                     #
@@ -201,6 +229,10 @@ parse_arguments() {
         TEMP_DIR="$2"
         shift 2
         ;;
+      --sort-memory)
+        SORT_MEMORY="$2"
+        shift 2
+        ;;
       *)
         echo "Unknown argument: $1"
         echo ""
@@ -237,6 +269,7 @@ print_help() {
   echo "  --output-dir        Directory to save the output files (required)."
   echo "  --database-sources  Comma-separated list of database sources ('swissprot', 'trembl'), (optional, default: 'swissprot,trembl')."
   echo "  --temp-dir          Temporary directory for intermediate files (optional, default: '/tmp')."
+  echo "  --sort-memory       Amount of memory (e.g., '2G') for the sorting steps (optional, default: '$SORT_MEMORY')"
   echo "  --help              Prints this help message."
   echo ""
   echo "Examples:"
@@ -258,9 +291,10 @@ checkdep xmllint "libxml2-utils"
 
 parse_arguments "$@"
 checkDirectoryAndCreate "$TEMP_DIR/$UNIPEPT_TEMP_CONSTANT"
-build_binaries "taxdmp-parser" "uniprot-parser"
+build_binaries "taxdmp-parser" "uniprot-parser" "reference-proteomes" "protein-text-validator"
 create_taxon_tables "$TEMP_DIR" "$UNIPEPT_TEMP_CONSTANT" "$OUTPUT_DIR"
 download_and_process_uniprot "$DB_TYPES" "$TEMP_DIR" "$UNIPEPT_TEMP_CONSTANT" "$OUTPUT_DIR"
+validate_protein_text "$OUTPUT_DIR"
 fetch_ec_numbers "$OUTPUT_DIR"
 fetch_go_terms "$OUTPUT_DIR"
 fetch_interpro_entries "$OUTPUT_DIR"
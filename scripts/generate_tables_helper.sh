@@ -268,44 +268,6 @@ have() {
 	fi
 }
 
-################################################################################
-# collapse                                                                     #
-#                                                                              #
-# Read from stdin. Each input line consists of two tab-separated columns       #
-# (key, value). This function takes the key and collapses all values together  #
-# using a semicolon (;)                                                        #
-#                                                                              #
-# Globals:                                                                     #
-#   None                                                                       #
-#                                                                              #
-# Arguments:                                                                   #
-#   None                                                                       #
-#                                                                              #
-# Outputs:                                                                     #
-#   Collapsed pairs (key, [value])                                             #
-#                                                                              #
-# Returns:                                                                     #
-#   None                                                                       #
-################################################################################
-collapse() {
-# shellcheck disable=SC2016
-$CMD_AWK '
-  BEGIN { FS = "\t" }
-  {
-   if ($1 == prev) {
-     out = out ";" $2
-   } else {
-     if (NR > 1) print prev "\t" out
-     prev = $1
-     out = $2
-   }
-  }
-  END {
-   if (NR > 0) print prev "\t" out
-  }
-'
-}
-
 ################################################################################
 # build_binaries                                                               #
 #                                                                              #
@@ -442,9 +404,8 @@ download_uniprot() {
 ################################################################################
 # download_taxdmp                                                              #
 #                                                                              #
-# Downloads the taxdmp file required for generating taxon tables. This function#
-# attempts to fetch the file from a self-hosted source using the GitHub API.   #
-# If the self-hosted source is unavailable, a fallback URL is used.            #
+# Downloads the official NCBI taxdmp file required for generating taxon        #
+# tables.                                                                      #
 #                                                                              #
 # Arguments:                                                                   #
 #   None                                                                       #
@@ -458,24 +419,7 @@ download_uniprot() {
 download_taxdmp() {
   log "Starting the download of the taxdmp file."
 
-  # Check if our self-hosted version is available or not using the GitHub API
-  local latest_release_url="https://api.github.com/repos/unipept/unipept-database/releases/latest"
-  local taxon_release_asset_re="unipept/unipept-database/releases/download/[^/]+/taxdmp_v2.zip"
-
-  # Temporary disable the pipefail check (cause egrep can exit with code 1 if nothing is found).
-  set +eo pipefail
-  local self_hosted_url=$(curl -s "$latest_release_url" | egrep -o "$taxon_release_asset_re")
-  set -eo pipefail
-
-
-  if [ "$self_hosted_url" ]
-  then
-    log "Using self-hosted taxon dump."
-    local taxon_url="https://github.com/$self_hosted_url"
-  else
-    log "Using fallback taxon dump."
-    local taxon_url="https://ftp.ncbi.nlm.nih.gov/pub/taxonomy/taxdmp.zip"
-  fi
+  local taxon_url="https://ftp.ncbi.nlm.nih.gov/pub/taxonomy/taxdmp.zip"
 
   curl -L --create-dirs --silent --output "$TEMP_DIR/$UNIPEPT_TEMP_CONSTANT/taxdmp.zip" "$taxon_url"
 
@@ -487,9 +431,9 @@ download_taxdmp() {
 #                                                                              #
 # Generates the taxon and lineage tables required for the database by          #
 # downloading, parsing, processing, and filtering the data files. This function#
-# downloads the latest taxon dump, processes the necessary files, filters out  #
-# ranks not supported by Unipept, and generates the appropriate output files   #
-# in the specified directory.                                                  #
+# downloads the latest taxon dump, processes the necessary files and generates #
+# the appropriate output files in the specified directory. Ranks that have no  #
+# column in the lineages are treated as "no rank" by the taxdmp-parser.        #
 #                                                                              #
 # Globals:                                                                     #
 #   TEMP_DIR          - Directory used to store temporary files                #
@@ -505,6 +449,9 @@ download_taxdmp() {
 # Outputs:                                                                     #
 #   taxons.tsv.lz4                                                             #
 #   lineages.tsv.lz4                                                           #
+#   merged_taxa.tsv.lz4                                                        #
+#   taxon_names.tsv.lz4                                                        #
+#   taxon_validation.tsv.lz4                                                   #
 #                                                                              #
 # Returns:                                                                     #
 #   None                                                                       #
@@ -517,30 +464,24 @@ create_taxon_tables() {
 	local output_dir="$3"
 
   download_taxdmp
-  unzip -qq "$temp_dir/$temp_constant/taxdmp.zip" "names.dmp" "nodes.dmp" -d "$temp_dir/$temp_constant"
+  unzip -qq "$temp_dir/$temp_constant/taxdmp.zip" "names.dmp" "nodes.dmp" "merged.dmp" "delnodes.dmp" -d "$temp_dir/$temp_constant"
   rm "$temp_dir/$temp_constant/taxdmp.zip"
 
-  # Replace ranks not used by Unipept by "no rank". And replace the no_rank of viruses by domain.
-  sed -i'' -e 's/subcohort/no rank/' -e 's/cohort/no rank/' \
-    -e 's/subsection/no rank/' -e 's/section/no rank/' \
-    -e 's/series/no rank/' -e 's/biotype/no rank/' \
-    -e 's/serogroup/no rank/' -e 's/morph/no rank/' \
-    -e 's/genotype/no rank/' -e 's/subvariety/no rank/' \
-    -e 's/pathogroup/no rank/' -e 's/forma specialis/no rank/' \
-    -e 's/serotype/no rank/' -e 's/clade/no rank/' \
-    -e 's/isolate/no rank/' -e 's/infraclass/no rank/' \
-    -e 's/acellular root/domain/' -e 's/cellular root/no rank/' \
-    -e 's/parvorder/no rank/' "$temp_dir/$temp_constant/nodes.dmp"
-
   log "Parsing names.dmp and nodes.dmp files"
   mkdir -p "$output_dir"
   "$CURRENT_LOCATION"/rust-utils/target/release/taxdmp-parser \
     --names "$temp_dir/$temp_constant/names.dmp" \
     --nodes "$temp_dir/$temp_constant/nodes.dmp" \
     --taxa "$(lz "$output_dir/taxons.tsv.lz4")" \
-    --lineages "$(lz "$output_dir/lineages.tsv.lz4")"
-
-  rm "$temp_dir/$temp_constant/names.dmp" "$temp_dir/$temp_constant/nodes.dmp"
+    --lineages "$(lz "$output_dir/lineages.tsv.lz4")" \
+    --taxon-names "$(lz "$output_dir/taxon_names.tsv.lz4")" \
+    --validation-report "$(lz "$output_dir/taxon_validation.tsv.lz4")" \
+    --merged "$temp_dir/$temp_constant/merged.dmp" \
+    --delnodes "$temp_dir/$temp_constant/delnodes.dmp" \
+    --merged-taxa "$(lz "$output_dir/merged_taxa.tsv.lz4")"
+
+  rm "$temp_dir/$temp_constant/names.dmp" "$temp_dir/$temp_constant/nodes.dmp" \
+    "$temp_dir/$temp_constant/merged.dmp" "$temp_dir/$temp_constant/delnodes.dmp"
   log "Finished creating the taxon and lineage tables."
 }
 
@@ -47,6 +47,8 @@ CMD_SORT="sort --buffer-size=$SORT_MEMORY --parallel=4"
 PEPTIDE_MIN_LENGTH=5
 # What is the maximum length (inclusive) for tryptic peptides?"
 PEPTIDE_MAX_LENGTH=50
+# How should decoy proteins be generated (reverse, pseudo-reverse or shuffle)? No decoys are generated if empty.
+DECOY_STRATEGY=""
 
 # Make sure that all temporary files are cleaned up if something goes wrong during execution of this script
 trap terminateAndExit SIGINT
@@ -92,13 +94,14 @@ download_and_process_uniprot_kmer() {
   local temp_constant="$3"
   local output_dir="$4"
 
-  have "$output_dir/taxons.tsv.lz4" || return
+  have "$output_dir/taxons.tsv.lz4" "$output_dir/merged_taxa.tsv.lz4" || return
 
   log "Started generating the uniprot_entries file."
 
   download_uniprot "$db_types" \
   | "$CURRENT_LOCATION"/rust-utils/target/release/uniprot-parser \
       --taxa "$(luz "$output_dir/taxons.tsv.lz4")" \
+      --merged-taxa "$(luz "$output_dir/merged_taxa.tsv.lz4")" \
       --uniprot-entries "$(lz "$output_dir/uniprot_entries.tsv.lz4")"
 
   log "Finished generating the uniprot_entries file."
@@ -112,13 +115,10 @@ download_and_process_uniprot_kmer() {
 # For each database type, it attempts to download, decompress, and convert it  #
 # to a tabular format, storing the result in the uniprot_entries.tsv.lz4 file  #
 # as a result. Also generate a sequences table containing in-silico            #
-# tryptically digested peptides. The function also sorts and compresses the    #
-# peptide output.                                                              #
+# tryptically digested peptides.                                               #
 #                                                                              #
 # Globals:                                                                     #
 #   CURRENT_LOCATION   - Current script directory                              #
-#   CMD_LZ4CAT         - Command or path to the lz4cat binary                  #
-#   CMD_SORT           - Command or path to the sort utility                   #
 #   PEPTIDE_MIN_LENGTH - Minimum length for peptides                           #
 #   PEPTIDE_MAX_LENGTH - Maximum length for peptides                           #
 #                                                                              #
@@ -130,10 +130,14 @@ download_and_process_uniprot_kmer() {
 #   $4 - Output directory where the resulting files will be created            #
 #   $5 - Minimum length for tryptic peptides                                   #
 #   $6 - Maximum length for tryptic peptides                                   #
+#   $7 - Decoy generation strategy, or empty to not generate decoys            #
 #                                                                              #
 # Outputs:                                                                     #
-#   peptides-equalized.tsv.lz4 - Sorted and compressed peptide table           #
+#   peptides-out.tsv.lz4 - Compressed (unsorted) peptide table                 #
 #   uniprot_entries.tsv.lz4 - Processed and compressed UniProt entries file    #
+#   proteomes.tsv.lz4    - Proteomes of the UniProt entries                    #
+#   decoy_uniprot_entries.tsv.lz4 - Decoy entries (only if $7 is set)          #
+#   decoy_peptides.tsv.lz4        - Decoy peptides (only if $7 is set)         #
 #                                                                              #
 # Returns:                                                                     #
 #   None                                                                       #
@@ -145,8 +149,19 @@ download_and_process_uniprot_tryptic() {
   local output_dir="$4"
   local peptide_min_length="$5"
   local peptide_max_length="$6"
-
-  have "$output_dir/taxons.tsv.lz4" || return
+  local decoy_strategy="$7"
+
+  have "$output_dir/taxons.tsv.lz4" "$output_dir/merged_taxa.tsv.lz4" || return
+
+  # Decoys are written to their own tables, so they never reach the LCA and FA calculations
+  local decoy_args=()
+  if [[ -n "$decoy_strategy" ]]; then
+    decoy_args=(
+      --decoy-strategy "$decoy_strategy"
+      --decoy-entries "$(lz "$output_dir/decoy_uniprot_entries.tsv.lz4")"
+      --decoy-peptides "$(lz "$output_dir/decoy_peptides.tsv.lz4")"
+    )
+  fi
 
   log "Started generating the uniprot_entries file."
 
@@ -155,43 +170,41 @@ download_and_process_uniprot_tryptic() {
       --peptide-min "$peptide_min_length" \
   		--peptide-max "$peptide_max_length" \
       --taxa "$(luz "$output_dir/taxons.tsv.lz4")" \
+      --merged-taxa "$(luz "$output_dir/merged_taxa.tsv.lz4")" \
       --peptides "$(lz "$temp_dir/$temp_constant/peptides-out.tsv.lz4")" \
-      --uniprot-entries "$(lz "$output_dir/uniprot_entries.tsv.lz4")"
+      --proteomes "$(lz "$temp_dir/$temp_constant/proteomes.tsv.lz4")" \
+      --uniprot-entries "$(lz "$output_dir/uniprot_entries.tsv.lz4")" \
+      "${decoy_args[@]}"
 
   log "Finished generating the uniprot_entries file."
-
-  log "Started sorting peptides table"
-
-  $CMD_LZ4CAT "$temp_dir/$temp_constant/peptides-out.tsv.lz4" \
-   | LC_ALL=C $CMD_SORT -k2 \
-   | $CMD_LZ4 > "$temp_dir/$temp_constant/peptides-equalized.tsv.lz4"
-
-  log "Removing peptides-out.tsv.lz4"
-
-  rm "$temp_dir/$temp_constant/peptides-out.tsv.lz4"
-
-  log "Finished calculation of most tables with status $?"
 }
 
 ################################################################################
 # number_sequences                                                             #
 #                                                                              #
-# Creates a unique sequence numbering for peptides. The function takes an      #
-# input file of peptides, extracts two columns (representing sequences and     #
-# related data), sorts and merges them, and assigns unique numeric IDs to the  #
-# resulting sequences. The output is a compressed file containing the numbered #
-# sequences.                                                                   #
+# Creates a unique sequence numbering for peptides and substitutes the         #
+# sequences in the peptide table by their IDs. The combined dictionary of      #
+# original and equalized sequences is sorted and numbered, after which both    #
+# sequence columns of the peptide table are replaced by their IDs. All sorting #
+# is done by the sequence-numberer binary, with a bounded amount of memory.    #
 #                                                                              #
 # Globals:                                                                     #
-#   CMD_LZ4CAT - Command or path to the lz4cat binary                          #
-#   CMD_SORT   - Command or path to the sort utility                           #
+#   CURRENT_LOCATION - Current script directory                                #
+#   SORT_MEMORY      - Amount of memory the sorting steps are allowed to use   #
 #                                                                              #
 # Arguments:                                                                   #
 #   $1 - Temporary directory used to store intermediate files                  #
 #   $2 - Temporary constant to identify this script's files in the temp dir    #
 #                                                                              #
+# Inputs:                                                                      #
+#   peptides-out.tsv.lz4 - Input file with the unsorted peptide table          #
+#                                                                              #
 # Outputs:                                                                     #
-#   sequences.tsv.lz4 - Compressed file with numbered peptide sequences        #
+#   sequences.tsv.lz4             - Compressed file with numbered sequences    #
+#   peptides_by_equalized.tsv.lz4 - File with equalized sequences replaced by  #
+#                                   their IDs                                  #
+#   peptides_by_original.tsv.lz4  - File with both sequences replaced by their #
+#                                   IDs, sorted by original sequence           #
 #                                                                              #
 # Returns:                                                                     #
 #   None                                                                       #
@@ -199,74 +212,69 @@ download_and_process_uniprot_tryptic() {
 number_sequences() {
   local temp_dir="$1"
   local temp_constant="$2"
-  
-  have "$temp_dir/$temp_constant/peptides-equalized.tsv.lz4" || return
-	log "Started the numbering of sequences."
-
-  rm -f "p_eq" "p_or"
-	mkfifo "p_eq" "p_or"
 
-	$CMD_LZ4CAT "$temp_dir/$temp_constant/peptides-equalized.tsv.lz4" | cut -f 3 | $CMD_SORT | uniq > "p_or" &
-	$CMD_LZ4CAT "$temp_dir/$temp_constant/peptides-equalized.tsv.lz4" | cut -f 2 | uniq > "p_eq" &
+  have "$temp_dir/$temp_constant/peptides-out.tsv.lz4" || return
+  log "Started the numbering of sequences and the substitution of AA's by ID's."
 
-	$CMD_SORT -u -m "p_or" "p_eq" | cat -n \
-		| sed 's/^ *//' | $CMD_LZ4 - > "$temp_dir/$temp_constant/sequences.tsv.lz4"
+  "$CURRENT_LOCATION"/rust-utils/target/release/sequence-numberer \
+    --input-file "$(luz "$temp_dir/$temp_constant/peptides-out.tsv.lz4")" \
+    --sequences "$(lz "$temp_dir/$temp_constant/sequences.tsv.lz4")" \
+    --peptides-by-equalized "$(lz "$temp_dir/$temp_constant/peptides_by_equalized.tsv.lz4")" \
+    --peptides-by-original "$(lz "$temp_dir/$temp_constant/peptides_by_original.tsv.lz4")" \
+    --memory "$SORT_MEMORY" \
+    --temp-dir "$temp_dir/$temp_constant"
 
-	rm "p_eq" "p_or"
-
-	log "Finished the numbering of sequences with status $?."
+  rm "$temp_dir/$temp_constant/peptides-out.tsv.lz4"
+  log "Finished the numbering of sequences and the substitution of AA's by ID's."
 }
 
 ################################################################################
-# substitute_aas                                                               #
+# calculate_peptide_specificity                                                #
 #                                                                              #
-# Substitutes amino acid sequences in the peptide data by their corresponding  #
-# IDs from a sequences file. It performs two steps:                            #
-# 1. Replaces equalized peptide sequences by their IDs.                        #
-# 2. Replaces original peptide sequences by their IDs.                         #
+# Marks every peptide as protein-unique, proteome-unique and unique at the     #
+# species and genus rank, and lists the marker peptides of every taxon.        #
 #                                                                              #
 # Globals:                                                                     #
-#   CMD_LZ4CAT - Command or path to the lz4cat binary                          #
-#   CMD_SORT   - Command or path to the sort utility                           #
+#   CURRENT_LOCATION - Current script directory                                #
+#   SORT_MEMORY      - Amount of memory the sorting steps are allowed to use   #
 #                                                                              #
 # Arguments:                                                                   #
 #   $1 - Temporary directory used to store intermediate files                  #
 #   $2 - Temporary constant to identify this script's files in the temp dir    #
+#   $3 - Output directory where the resulting files will be created            #
 #                                                                              #
 # Inputs:                                                                      #
-#   peptides-equalized.tsv.lz4 - Input file with equalized peptide sequences    #
-#   sequences.tsv.lz4          - Input file with IDs for peptide sequences     #
-#                                                                              #
-# Outputs:                                                                     #
 #   peptides_by_equalized.tsv.lz4 - File with equalized sequences replaced by  #
 #                                   their IDs                                  #
-#   peptides_by_original.tsv.lz4  - File with original sequences replaced by   #
-#                                   their IDs                                  #
+#   sequences.tsv.lz4             - Compressed file with numbered sequences    #
+#   proteomes.tsv.lz4             - Proteomes of the UniProt entries           #
+#                                                                              #
+# Outputs:                                                                     #
+#   peptide_specificity.tsv.lz4 - Specificity flags of every peptide           #
+#   marker_peptides.tsv.lz4     - Marker peptides of every taxon               #
 #                                                                              #
 # Returns:                                                                     #
 #   None                                                                       #
 ################################################################################
-substitute_aas() {
+calculate_peptide_specificity() {
   local temp_dir="$1"
   local temp_constant="$2"
+  local output_dir="$3"
 
-  have "$temp_dir/$temp_constant/peptides-equalized.tsv.lz4" "$temp_dir/$temp_constant/sequences.tsv.lz4"
-
-  log "Started the substitution of equalized AA's by ID's for the peptides."
-  $CMD_LZ4CAT "$temp_dir/$temp_constant/peptides-equalized.tsv.lz4" \
-    | join -t '	' -o '1.1,2.1,1.3,1.4,1.5,1.6' -1 2 -2 2 - "$(luz "$temp_dir/$temp_constant/sequences.tsv.lz4")" \
-    | $CMD_LZ4 - > "$temp_dir/$temp_constant/peptides_by_equalized.tsv.lz4"
-
-  rm "$temp_dir/$temp_constant/peptides-equalized.tsv.lz4"
-  log "Finished the substitution of equalized AA's by ID's for the peptides with status $?."
-
-  log "Started the substitution of original AA's by ID's for the peptides."
-  $CMD_LZ4CAT "$temp_dir/$temp_constant/peptides_by_equalized.tsv.lz4" \
-    | LC_ALL=C $CMD_SORT -k 3b,3 \
-    | join -t '	' -o '1.1,1.2,2.1,1.4,1.5,1.6' -1 3 -2 2 - "$(luz "$temp_dir/$temp_constant/sequences.tsv.lz4")" \
-    | $CMD_LZ4 - > "$temp_dir/$temp_constant/peptides_by_original.tsv.lz4"
-
-  log "Finished the substitution of original AA's by ID's for the peptides with status $?."
+	have "$temp_dir/$temp_constant/peptides_by_equalized.tsv.lz4" "$temp_dir/$temp_constant/sequences.tsv.lz4" "$temp_dir/$temp_constant/proteomes.tsv.lz4" || return
+
+	log "Started the calculation of peptide specificity."
+	"$CURRENT_LOCATION"/rust-utils/target/release/peptide-specificity \
+		--peptides "$(luz "$temp_dir/$temp_constant/peptides_by_equalized.tsv.lz4")" \
+		--sequences "$(luz "$temp_dir/$temp_constant/sequences.tsv.lz4")" \
+		--lineages "$(luz "$output_dir/lineages.tsv.lz4")" \
+		--proteomes "$(luz "$temp_dir/$temp_constant/proteomes.tsv.lz4")" \
+		--uniprot-entries "$(luz "$output_dir/uniprot_entries.tsv.lz4")" \
+		--output-file "$(lz "$output_dir/peptide_specificity.tsv.lz4")" \
+		--markers-file "$(lz "$output_dir/marker_peptides.tsv.lz4")" \
+		--sort-memory "$SORT_MEMORY" \
+		--temp-dir "$temp_dir/$temp_constant"
+	log "Finished the calculation of peptide specificity."
 }
 
 ################################################################################
@@ -439,8 +447,7 @@ calculate_original_fas() {
 # directory.                                                                   #
 #                                                                              #
 # Globals:                                                                     #
-#   CMD_LZ4CAT - Command or path to the lz4cat binary                          #
-#   CMD_AWK    - Command or path to the awk utility                            #
+#   CURRENT_LOCATION - Directory containing the rust-utils binaries            #
 #                                                                              #
 # Arguments:                                                                   #
 #   $1 - Temporary directory containing intermediate files                     #
@@ -468,20 +475,13 @@ create_sequence_table() {
 	have "$temp_dir/$temp_constant/LCAs_original.tsv.lz4" "$temp_dir/$temp_constant/LCAs_equalized.tsv.lz4" "$temp_dir/$temp_constant/FAs_original.tsv.lz4" "$temp_dir/$temp_constant/FAs_equalized.tsv.lz4" "$temp_dir/$temp_constant/sequences.tsv.lz4" || return
 	log "Started the creation of the sequences table."
 	mkdir -p "$output_dir"
-	rm -f "olcas" "elcas" "ofas" "efas"
-	mkfifo "olcas" "elcas" "ofas" "efas"
-	$CMD_LZ4CAT "$temp_dir/$temp_constant/LCAs_original.tsv.lz4"  | $CMD_AWK '{ printf("%012d\t%s\n", $1, $2) }' > "olcas" &
-	$CMD_LZ4CAT "$temp_dir/$temp_constant/LCAs_equalized.tsv.lz4" | $CMD_AWK '{ printf("%012d\t%s\n", $1, $2) }' > "elcas" &
-	$CMD_LZ4CAT "$temp_dir/$temp_constant/FAs_original.tsv.lz4"   | $CMD_AWK '{ printf("%012d\t%s\n", $1, $2) }' > "ofas" &
-	$CMD_LZ4CAT "$temp_dir/$temp_constant/FAs_equalized.tsv.lz4"  | $CMD_AWK '{ printf("%012d\t%s\n", $1, $2) }' > "efas" &
-	$CMD_LZ4CAT "$temp_dir/$temp_constant/sequences.tsv.lz4"      | $CMD_AWK '{ printf("%012d\t%s\n", $1, $2) }' \
-		| join --nocheck-order -a1 -e '\N' -t '	' -o "1.1 1.2 2.2" - "olcas" \
-		| join --nocheck-order -a1 -e '\N' -t '	' -o "1.1 1.2 1.3 2.2" - "elcas" \
-		| join --nocheck-order -a1 -e '\N' -t '	' -o '1.1 1.2 1.3 1.4 2.2' - "ofas" \
-		| join --nocheck-order -a1 -e '\N' -t '	' -o '1.1 1.2 1.3 1.4 1.5 2.2' - "efas" \
-		| sed 's/^0*//' \
-		| $CMD_LZ4 - > "$output_dir/sequences.tsv.lz4"
-	rm "olcas" "elcas" "ofas" "efas"
+	"$CURRENT_LOCATION"/rust-utils/target/release/sequence-table-builder \
+		--sequences "$(luz "$temp_dir/$temp_constant/sequences.tsv.lz4")" \
+		--lcas-original "$(luz "$temp_dir/$temp_constant/LCAs_original.tsv.lz4")" \
+		--lcas-equalized "$(luz "$temp_dir/$temp_constant/LCAs_equalized.tsv.lz4")" \
+		--fas-original "$(luz "$temp_dir/$temp_constant/FAs_original.tsv.lz4")" \
+		--fas-equalized "$(luz "$temp_dir/$temp_constant/FAs_equalized.tsv.lz4")" \
+		--output-file "$(lz "$output_dir/sequences.tsv.lz4")"
 	log "Finished the creation of the sequences table."
 }
 
@@ -489,17 +489,17 @@ create_sequence_table() {
 # create_kmer_index                                                            #
 #                                                                              #
 # Creates a k-mer index based on UniProt entries and taxonomy data. The        #
-# function reads the compressed UniProt entries file and generates an index    #
-# for k-mers of the specified length. The index is written to a file in the    #
-# output directory.                                                            #
+# function reads the compressed UniProt entries file once, computes the LCA of #
+# every k-mer of the specified length and generates an index from these LCAs.  #
+# The index is written to a file in the output directory.                      #
 #                                                                              #
 # Globals:                                                                     #
-#   CMD_LZ4CAT - Command or path to the lz4cat binary                          #
-#   CMD_SORT   - Command or path to the sort utility                           #
+#   CURRENT_LOCATION - Location of the rust-utils binaries                     #
 #                                                                              #
 # Arguments:                                                                   #
 #   $1 - Output directory where k-mer index will be saved                      #
 #   $2 - Length (k) of the k-mers                                              #
+#   $3 - Directory for temporary files of the sorting step                     #
 #                                                                              #
 # Outputs:                                                                     #
 #   Writes a file named <kmer_length>-mer.index in the output directory        #
@@ -510,25 +510,19 @@ create_sequence_table() {
 create_kmer_index() {
   local output_dir="$1"
   local kmer_length="$2"
+  local temp_dir="$3"
 
-	have "$output_dir/uniprot_entries.tsv.lz4" "$output_dir/taxons.tsv.lz4" || return
+	have "$output_dir/uniprot_entries.tsv.lz4" "$output_dir/lineages.tsv.lz4" || return
 
 	log "Started the construction of the $kmer_length-mer index."
 
-	for prefix in A C D E F G H I K L M N P Q R S T V W Y; do
-		pv -N $prefix "$output_dir/uniprot_entries.tsv.lz4" \
-			| $CMD_LZ4CAT \
-			| cut -f4,7 \
-			| grep "^[0-9]*	[ACDEFGHIKLMNPQRSTVWY]*$" \
-			| umgap splitkmers -k"$kmer_length" \
-			| sed -n "s/^$prefix//p" \
-			| LC_ALL=C $CMD_SORT \
-			| sed "s/^/$prefix/"
-	done \
-			| umgap joinkmers "$(luz "$output_dir/taxons.tsv.lz4")" \
-			| cut -d'	' -f1,2 \
-			| umgap buildindex \
-			> "$output_dir/$kmer_length-mer.index"
+	"$CURRENT_LOCATION"/rust-utils/target/release/kmer-calculator \
+		--uniprot-entries "$(luz "$output_dir/uniprot_entries.tsv.lz4")" \
+		--lineages "$(luz "$output_dir/lineages.tsv.lz4")" \
+		--kmer-length "$kmer_length" \
+		--temp-dir "$temp_dir" \
+		| "$CURRENT_LOCATION"/rust-utils/target/release/index-builder \
+			--output-file "$output_dir/$kmer_length-mer.index"
 	log "Finished the construction of the $kmer_length-mer index."
 }
 
@@ -536,11 +530,11 @@ create_kmer_index() {
 # create_tryptic_index                                                         #
 #                                                                              #
 # Creates a tryptic peptide index based on sequences data. The function reads  #
-# the compressed sequences file, processes it to remove invalid entries, and   #
-# generates an index for tryptic peptides.                                     #
+# the compressed sequences file, skips peptides without an LCA, and generates  #
+# an index for tryptic peptides.                                               #
 #                                                                              #
 # Globals:                                                                     #
-#   CMD_LZ4CAT - Command or path to the lz4cat binary                          #
+#   CURRENT_LOCATION - Location of the rust-utils binaries                     #
 #                                                                              #
 # Arguments:                                                                   #
 #   $1 - Output directory where the tryptic index will be saved                #
@@ -560,12 +554,11 @@ create_tryptic_index() {
 	have "$output_dir/sequences.tsv.lz4" || return
 
 	log "Started the construction of the tryptic index."
-	pv "$output_dir/sequences.tsv.lz4" \
-		| $CMD_LZ4CAT \
-		| cut -f2,3 \
-		| grep -v "\\N" \
-		| umgap buildindex \
-		> "$output_dir/tryptic.index"
+	"$CURRENT_LOCATION"/rust-utils/target/release/index-builder \
+		--input-file "$(luz "$output_dir/sequences.tsv.lz4")" \
+		--key-column 2 \
+		--value-column 3 \
+		--output-file "$output_dir/tryptic.index"
 	log "Finished the construction of the tryptic index."
 }
 
@@ -716,6 +709,15 @@ parse_tryptic_arguments() {
         PEPTIDE_MAX_LENGTH="$2"
         shift 2
         ;;
+      --decoy-strategy)
+        if ! [[ "$2" =~ ^(reverse|pseudo-reverse|shuffle)$ ]]; then
+          echo "Error: --decoy-strategy must be 'reverse', 'pseudo-reverse' or 'shuffle'."
+          print_help
+          exit 1
+        fi
+        DECOY_STRATEGY="$2"
+        shift 2
+        ;;
       --help)
         print_help
         exit 0
@@ -768,6 +770,7 @@ print_help() {
   echo "Options for 'tryptic' mode:"
   echo "  --min-peptide-length Minimum length of tryptic peptides (optional, default: $PEPTIDE_MIN_LENGTH)"
   echo "  --max-peptide-length Maximum length of tryptic peptides (optional, default: $PEPTIDE_MAX_LENGTH)."
+  echo "  --decoy-strategy    Also generate decoy entries and peptides: 'reverse', 'pseudo-reverse' or 'shuffle' (optional)."
   echo ""
   echo "Examples:"
   echo "  $0 kmer --database-sources swissprot,trembl --output-dir /path/to/output --kmer-length 7"
@@ -795,23 +798,22 @@ checkdep uuidgen
 checkdep lz4
 checkdep pigz
 checkdep pv
-checkdep umgap "umgap crate (for umgap buildindex)"
 
 if [[ "$MODE" == "kmer" ]]; then
   parse_kmer_arguments "$@"
   checkDirectoryAndCreate "$TEMP_DIR/$UNIPEPT_TEMP_CONSTANT"
-  build_binaries "taxdmp-parser" "uniprot-parser"
+  build_binaries "taxdmp-parser" "uniprot-parser" "kmer-calculator" "index-builder"
   create_taxon_tables "$TEMP_DIR" "$UNIPEPT_TEMP_CONSTANT" "$OUTPUT_DIR"
   download_and_process_uniprot_kmer "$DB_TYPES" "$TEMP_DIR" "$UNIPEPT_TEMP_CONSTANT" "$OUTPUT_DIR"
-  create_kmer_index "$OUTPUT_DIR" "$KMER_LENGTH"
+  create_kmer_index "$OUTPUT_DIR" "$KMER_LENGTH" "$TEMP_DIR/$UNIPEPT_TEMP_CONSTANT"
 elif [[ "$MODE" == "tryptic" ]]; then
   parse_tryptic_arguments "$@"
   checkDirectoryAndCreate "$TEMP_DIR/$UNIPEPT_TEMP_CONSTANT"
-  build_binaries "taxdmp-parser" "uniprot-parser-tryptic" "function-calculator" "lca-calculator"
+  build_binaries "taxdmp-parser" "uniprot-parser-tryptic" "sequence-numberer" "sequence-table-builder" "function-calculator" "lca-calculator" "index-builder" "peptide-specificity"
   create_taxon_tables "$TEMP_DIR" "$UNIPEPT_TEMP_CONSTANT" "$OUTPUT_DIR"
-  download_and_process_uniprot_tryptic "$DB_TYPES" "$TEMP_DIR" "$UNIPEPT_TEMP_CONSTANT" "$OUTPUT_DIR" "$PEPTIDE_MIN_LENGTH" "$PEPTIDE_MAX_LENGTH"
+  download_and_process_uniprot_tryptic "$DB_TYPES" "$TEMP_DIR" "$UNIPEPT_TEMP_CONSTANT" "$OUTPUT_DIR" "$PEPTIDE_MIN_LENGTH" "$PEPTIDE_MAX_LENGTH" "$DECOY_STRATEGY"
   number_sequences "$TEMP_DIR" "$UNIPEPT_TEMP_CONSTANT"
-  substitute_aas "$TEMP_DIR" "$UNIPEPT_TEMP_CONSTANT"
+  calculate_peptide_specificity "$TEMP_DIR" "$UNIPEPT_TEMP_CONSTANT" "$OUTPUT_DIR"
   calculate_equalized_lcas "$TEMP_DIR" "$UNIPEPT_TEMP_CONSTANT" "$OUTPUT_DIR"
   calculate_original_lcas "$TEMP_DIR" "$UNIPEPT_TEMP_CONSTANT" "$OUTPUT_DIR"
   calculate_equalized_fas "$TEMP_DIR" "$UNIPEPT_TEMP_CONSTANT"
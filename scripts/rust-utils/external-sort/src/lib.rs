@@ -0,0 +1,489 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Lines, Seek, SeekFrom, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::thread::JoinHandle;
+
+use anyhow::{Context, Error, Result};
+use lz4_flex::frame::{FrameDecoder, FrameEncoder};
+
+/// Memory used by a buffered line on top of its content
+const LINE_OVERHEAD: usize = mem::size_of::<(usize, usize)>();
+/// Maximum amount of runs that are merged at once, which bounds the amount of open temporary
+/// files and the memory used by their read buffers
+const MAX_FAN_IN: usize = 64;
+
+/// How the values of the key column are compared
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum KeyOrder {
+    /// Compare keys byte by byte, like `LC_ALL=C sort`
+    Bytes,
+    /// Compare keys as (non-negative) integers, like `sort -n`
+    Numeric,
+}
+
+/// Sorts lines of tab-separated text on one of their columns, using at most a fixed amount of
+/// memory. Lines are compared first on the key column and then byte by byte on the full line.
+///
+/// Buffered lines are stored back to back in a single string, so pushing a line does not allocate.
+/// When the buffered lines exceed their share of the memory budget, they are handed to a
+/// background thread that sorts them and spills them to an lz4-compressed temporary file in
+/// `temp_dir`, while the next chunk is being buffered. These runs are merged with a k-way merge
+/// when the sorter is finished. Whenever `MAX_FAN_IN` runs of the same size have been written,
+/// they are first merged into a single larger run, so no more than a bounded amount of runs is
+/// ever open or merged at once.
+///
+/// The sorter itself only reads plain text: lz4-compressed tables are streamed through it by
+/// decompressing them into a named pipe first (see `luz` in `generate_tables_helper.sh`).
+pub struct ExternalSorter {
+    key_column: usize,
+    order: KeyOrder,
+    chunk_size: usize,
+    threads: usize,
+    temp_dir: PathBuf,
+    /// Content of the buffered lines
+    buffer: String,
+    /// Start and end of every buffered line in `buffer`
+    lines: Vec<(usize, usize)>,
+    pending: VecDeque<JoinHandle<Result<File>>>,
+    /// Runs grouped by the amount of merge passes they went through
+    runs: Vec<Vec<File>>,
+    fan_in: usize,
+}
+
+impl ExternalSorter {
+    /// Create a sorter on the (0-based) `key_column` that uses `threads` threads to generate runs.
+    /// Each of these threads, and the thread that buffers new lines, gets an equal share of `memory`
+    pub fn new(
+        key_column: usize,
+        order: KeyOrder,
+        memory: usize,
+        threads: usize,
+        temp_dir: &Path,
+    ) -> Self {
+        let threads = threads.max(1);
+
+        ExternalSorter {
+            key_column,
+            order,
+            chunk_size: memory / (threads + 1),
+            threads,
+            temp_dir: temp_dir.to_path_buf(),
+            buffer: String::new(),
+            lines: Vec::new(),
+            pending: VecDeque::new(),
+            runs: Vec::new(),
+            fan_in: MAX_FAN_IN,
+        }
+    }
+
+    pub fn push(&mut self, line: &str) -> Result<()> {
+        self.push_with(|buffer| {
+            buffer.push_str(line);
+            Ok(())
+        })
+    }
+
+    /// Push a line that is written directly into the buffer of the sorter by `write`, e.g. with
+    /// `write!`. The line must not contain a newline.
+    pub fn push_with<F: FnOnce(&mut String) -> fmt::Result>(&mut self, write: F) -> Result<()> {
+        let start = self.buffer.len();
+        write(&mut self.buffer).context("Error formatting line")?;
+        self.lines.push((start, self.buffer.len()));
+
+        if self.buffer.len() + self.lines.len() * LINE_OVERHEAD >= self.chunk_size {
+            self.spill()?;
+        }
+
+        Ok(())
+    }
+
+    /// Push all lines of a reader into the sorter
+    pub fn push_all<B: BufRead>(&mut self, reader: B) -> Result<()> {
+        for line in reader.lines() {
+            self.push(&line.context("Error reading line from input")?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Hand the buffered lines to a thread that sorts them and writes them to a new run file
+    fn spill(&mut self) -> Result<()> {
+        // Wait for a thread to become available
+        while self.pending.len() >= self.threads {
+            self.join_oldest()?;
+        }
+
+        let content = mem::take(&mut self.buffer);
+        let mut lines = mem::take(&mut self.lines);
+        let key_column = self.key_column;
+        let order = self.order;
+        let temp_dir = self.temp_dir.clone();
+
+        self.pending.push_back(thread::spawn(move || {
+            sort_lines(&content, &mut lines, key_column, order);
+            write_run(lines.iter().map(|&(s, e)| Ok(&content[s..e])), &temp_dir)
+        }));
+
+        Ok(())
+    }
+
+    fn join_oldest(&mut self) -> Result<()> {
+        if let Some(handle) = self.pending.pop_front() {
+            let run = handle
+                .join()
+                .map_err(|_| Error::msg("Thread generating a sorted run panicked"))??;
+            self.add_run(0, run)?;
+        }
+
+        Ok(())
+    }
+
+    /// Add a run that went through `level` merge passes, and merge all runs of that level into
+    /// a single run of the next level once there are `fan_in` of them
+    fn add_run(&mut self, level: usize, run: File) -> Result<()> {
+        if self.runs.len() <= level {
+            self.runs.resize_with(level + 1, Vec::new);
+        }
+        self.runs[level].push(run);
+
+        if self.runs[level].len() >= self.fan_in {
+            let runs = mem::take(&mut self.runs[level]);
+            let merged = write_run(
+                merge_runs(runs, self.key_column, self.order)?,
+                &self.temp_dir,
+            )?;
+            self.add_run(level + 1, merged)?;
+        }
+
+        Ok(())
+    }
+
+    /// Stop accepting lines and return an iterator over all lines in sorted order
+    pub fn finish(mut self) -> Result<SortedLines> {
+        let key_column = self.key_column;
+        let order = self.order;
+
+        // Everything fit in memory, no need to go through the disk
+        if self.pending.is_empty() && self.runs.is_empty() {
+            sort_lines(&self.buffer, &mut self.lines, key_column, order);
+            return Ok(SortedLines::Memory {
+                content: self.buffer,
+                lines: self.lines.into_iter(),
+            });
+        }
+
+        if !self.lines.is_empty() {
+            self.spill()?;
+        }
+        while !self.pending.is_empty() {
+            self.join_oldest()?;
+        }
+
+        // Merge the smallest runs first until few enough are left for the final merge
+        let mut runs: VecDeque<File> = self.runs.into_iter().flatten().collect();
+        while runs.len() > self.fan_in {
+            let merged = write_run(
+                merge_runs(runs.drain(..self.fan_in).collect(), key_column, order)?,
+                &self.temp_dir,
+            )?;
+            runs.push_back(merged);
+        }
+
+        merge_runs(runs.into(), key_column, order)
+    }
+}
+
+/// Sort the (start, end) ranges of lines in `content` on the lines they refer to
+fn sort_lines(content: &str, lines: &mut [(usize, usize)], key_column: usize, order: KeyOrder) {
+    lines.sort_unstable_by(|&(a_start, a_end), &(b_start, b_end)| {
+        compare_lines(
+            &content[a_start..a_end],
+            &content[b_start..b_end],
+            key_column,
+            order,
+        )
+    });
+}
+
+/// Start a k-way merge of sorted runs
+fn merge_runs(files: Vec<File>, key_column: usize, order: KeyOrder) -> Result<SortedLines> {
+    let mut runs = Vec::with_capacity(files.len());
+    let mut heap = BinaryHeap::with_capacity(files.len());
+
+    for (run, file) in files.into_iter().enumerate() {
+        let mut lines = BufReader::new(FrameDecoder::new(file)).lines();
+        if let Some(line) = lines.next() {
+            let line = line.context("Error reading line from temporary file")?;
+            heap.push(HeapItem {
+                line,
+                run,
+                key_column,
+                order,
+            });
+        }
+        runs.push(lines);
+    }
+
+    Ok(SortedLines::Merge { runs, heap })
+}
+
+/// Write sorted lines to an lz4-compressed temporary file, and rewind it so it can be read back
+fn write_run<S: AsRef<str>, I: Iterator<Item = Result<S>>>(
+    lines: I,
+    temp_dir: &Path,
+) -> Result<File> {
+    let file = tempfile::tempfile_in(temp_dir)
+        .with_context(|| format!("Unable to create temporary file in {}", temp_dir.display()))?;
+    let mut writer = BufWriter::new(FrameEncoder::new(file));
+
+    for line in lines {
+        let line = line?;
+        writer
+            .write_all(line.as_ref().as_bytes())
+            .context("Error writing to temporary file")?;
+        writer
+            .write_all(b"\n")
+            .context("Error writing to temporary file")?;
+    }
+
+    let mut file = writer
+        .into_inner()
+        .context("Error flushing temporary file")?
+        .finish()
+        .context("Error finishing lz4 frame of temporary file")?;
+    file.seek(SeekFrom::Start(0))
+        .context("Error rewinding temporary file")?;
+
+    Ok(file)
+}
+
+/// Lines coming out of an ExternalSorter, in sorted order
+pub enum SortedLines {
+    Memory {
+        content: String,
+        lines: std::vec::IntoIter<(usize, usize)>,
+    },
+    Merge {
+        runs: Vec<Lines<BufReader<FrameDecoder<File>>>>,
+        heap: BinaryHeap<HeapItem>,
+    },
+}
+
+impl Iterator for SortedLines {
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            SortedLines::Memory { content, lines } => lines
+                .next()
+                .map(|(start, end)| Ok(content[start..end].to_string())),
+            SortedLines::Merge { runs, heap } => {
+                let item = heap.pop()?;
+
+                // Refill the heap with the next line of the run we just took a line from
+                match runs[item.run].next() {
+                    Some(Ok(line)) => heap.push(HeapItem {
+                        line,
+                        run: item.run,
+                        key_column: item.key_column,
+                        order: item.order,
+                    }),
+                    Some(Err(e)) => {
+                        return Some(Err(
+                            Error::new(e).context("Error reading line from temporary file")
+                        ));
+                    }
+                    None => {}
+                }
+
+                Some(Ok(item.line))
+            }
+        }
+    }
+}
+
+/// A line in the k-way merge, ordered so that the BinaryHeap (a max-heap) pops the smallest line first
+pub struct HeapItem {
+    line: String,
+    run: usize,
+    key_column: usize,
+    order: KeyOrder,
+}
+
+impl Ord for HeapItem {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare_lines(&other.line, &self.line, self.key_column, self.order)
+    }
+}
+
+impl PartialOrd for HeapItem {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for HeapItem {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for HeapItem {}
+
+/// Get the value of the (0-based) column of a tab-separated line
+/// Lines with less columns have an empty key
+pub fn key(line: &str, column: usize) -> &str {
+    line.split('\t').nth(column).unwrap_or("")
+}
+
+/// Iterate over the lines of `reader`, sorted by `sorter` if one is given and in their original
+/// order otherwise
+/// This lets tools that expect sorted input sort it themselves when asked to.
+pub fn read_lines<'a, R: BufRead + 'a>(
+    reader: R,
+    sorter: Option<ExternalSorter>,
+) -> Result<Box<dyn Iterator<Item = Result<String>> + 'a>> {
+    match sorter {
+        Some(mut sorter) => {
+            sorter.push_all(reader)?;
+            Ok(Box::new(sorter.finish()?))
+        }
+        None => Ok(Box::new(reader.lines().map(|l| l.map_err(Error::new)))),
+    }
+}
+
+/// Compare two keys in the given order
+pub fn compare_keys(a: &str, b: &str, order: KeyOrder) -> Ordering {
+    match order {
+        KeyOrder::Bytes => a.as_bytes().cmp(b.as_bytes()),
+        // Without leading zeroes, a longer number is always larger
+        KeyOrder::Numeric => {
+            let a = a.trim_start_matches('0');
+            let b = b.trim_start_matches('0');
+            a.len()
+                .cmp(&b.len())
+                .then_with(|| a.as_bytes().cmp(b.as_bytes()))
+        }
+    }
+}
+
+fn compare_lines(a: &str, b: &str, column: usize, order: KeyOrder) -> Ordering {
+    compare_keys(key(a, column), key(b, column), order).then_with(|| a.as_bytes().cmp(b.as_bytes()))
+}
+
+/// Parse a memory size such as "512M" or "2G" (the format accepted by GNU sort) into bytes
+/// A number without a suffix is interpreted as kibibytes, like GNU sort does
+pub fn parse_memory_size(size: &str) -> Result<usize> {
+    let size = size.trim();
+    let (number, multiplier) = match size.chars().last() {
+        Some('b') | Some('B') => (&size[..size.len() - 1], 1),
+        Some('k') | Some('K') => (&size[..size.len() - 1], 1 << 10),
+        Some('m') | Some('M') => (&size[..size.len() - 1], 1 << 20),
+        Some('g') | Some('G') => (&size[..size.len() - 1], 1 << 30),
+        Some('t') | Some('T') => (&size[..size.len() - 1], 1 << 40),
+        _ => (size, 1 << 10),
+    };
+
+    let number: usize = number
+        .parse()
+        .with_context(|| format!("Unable to parse memory size {}", size))?;
+
+    Ok(number * multiplier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sort(lines: &[&str], key_column: usize, order: KeyOrder, memory: usize) -> Vec<String> {
+        let mut sorter = ExternalSorter::new(key_column, order, memory, 2, &std::env::temp_dir());
+        for line in lines {
+            sorter.push(line).unwrap();
+        }
+
+        sorter
+            .finish()
+            .unwrap()
+            .collect::<Result<Vec<String>>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_sort_in_memory() {
+        let got = sort(&["3\tb", "1\tc", "2\ta"], 1, KeyOrder::Bytes, 1 << 20);
+        assert_eq!(got, vec!["2\ta", "3\tb", "1\tc"]);
+    }
+
+    #[test]
+    fn test_sort_with_runs() {
+        let lines: Vec<String> = (0..1000).rev().map(|i| format!("x\t{}", i)).collect();
+        let lines: Vec<&str> = lines.iter().map(|l| l.as_str()).collect();
+
+        // A tiny memory budget forces every few lines to be spilled to a run
+        let got = sort(&lines, 1, KeyOrder::Numeric, 512);
+        let want: Vec<String> = (0..1000).map(|i| format!("x\t{}", i)).collect();
+        assert_eq!(got, want);
+    }
+
+    #[test]
+    fn test_sort_with_merge_passes() {
+        let lines: Vec<String> = (0..1000).rev().map(|i| format!("x\t{}", i)).collect();
+
+        // With a fan-in of 3, runs are merged while lines are pushed and again before the final
+        // merge
+        let mut sorter = ExternalSorter::new(1, KeyOrder::Numeric, 512, 2, &std::env::temp_dir());
+        sorter.fan_in = 3;
+        for line in &lines {
+            sorter.push(line).unwrap();
+        }
+        sorter.join_oldest().unwrap();
+        assert!(sorter.runs.len() > 2);
+
+        let got = sorter
+            .finish()
+            .unwrap()
+            .collect::<Result<Vec<String>>>()
+            .unwrap();
+        let want: Vec<String> = (0..1000).map(|i| format!("x\t{}", i)).collect();
+        assert_eq!(got, want);
+    }
+
+    #[test]
+    fn test_push_with() {
+        use std::fmt::Write;
+
+        let mut sorter = ExternalSorter::new(0, KeyOrder::Numeric, 64, 1, &std::env::temp_dir());
+        for i in (0..100).rev() {
+            sorter
+                .push_with(|line| write!(line, "{}\tx{}", i, i % 7))
+                .unwrap();
+        }
+
+        let got = sorter
+            .finish()
+            .unwrap()
+            .collect::<Result<Vec<String>>>()
+            .unwrap();
+        let want: Vec<String> = (0..100).map(|i| format!("{}\tx{}", i, i % 7)).collect();
+        assert_eq!(got, want);
+    }
+
+    #[test]
+    fn test_compare_keys_numeric() {
+        assert_eq!(compare_keys("9", "10", KeyOrder::Numeric), Ordering::Less);
+        assert_eq!(compare_keys("9", "10", KeyOrder::Bytes), Ordering::Greater);
+        assert_eq!(compare_keys("007", "7", KeyOrder::Numeric), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_parse_memory_size() {
+        assert_eq!(parse_memory_size("2G").unwrap(), 2 << 30);
+        assert_eq!(parse_memory_size("512M").unwrap(), 512 << 20);
+        assert_eq!(parse_memory_size("100").unwrap(), 100 << 10);
+    }
+}
@@ -1,16 +1,53 @@
 use anyhow::{Context, Result};
-use std::collections::HashMap;
-use std::io::BufRead;
+use std::collections::BTreeMap;
+use std::io::{BufWriter, Write, stdout};
 use std::path::PathBuf;
 
 use clap::Parser;
+use external_sort::{ExternalSorter, KeyOrder, parse_memory_size, read_lines};
+use functional_annotation::{FunctionalAnnotation, SEPARATOR};
 use utils::{now_str, open_read};
 
 fn main() -> Result<()> {
     let args = Cli::parse();
 
     let reader = open_read(&args.input_file)?;
+    let sorter = if args.sort_input {
+        eprintln!("[{}] Sorting input", now_str());
+        Some(ExternalSorter::new(
+            0,
+            KeyOrder::Bytes,
+            parse_memory_size(&args.sort_memory)?,
+            args.sort_threads,
+            &args.temp_dir,
+        ))
+    } else {
+        None
+    };
+    let lines = read_lines(reader, sorter)?;
+
+    let mut writer = BufWriter::new(stdout().lock());
+    let invalid = calculate_functions(lines, &mut writer)?;
+    writer.flush().context("Error writing to output")?;
+
+    if invalid > 0 {
+        eprintln!(
+            "[{}] Left out {} functional annotations with an invalid id",
+            now_str(),
+            invalid
+        );
+    }
+
+    Ok(())
+}
 
+/// Aggregate the functional annotations of every peptide in `lines`, which have to be sorted on
+/// the peptide, and write them to `writer`
+/// Returns the amount of annotations that were left out because their id is invalid.
+fn calculate_functions<I: Iterator<Item = Result<String>>, W: Write>(
+    lines: I,
+    writer: &mut W,
+) -> Result<u64> {
     let mut current_pept: String = String::new();
 
     let mut num_prot: u32 = 0;
@@ -18,22 +55,25 @@ fn main() -> Result<()> {
     let mut num_annotated_ec: u32 = 0;
     let mut num_annotated_ip: u32 = 0;
     let mut done: u64 = 0;
+    let mut invalid: u64 = 0;
 
-    let mut m: HashMap<String, u32> = HashMap::new();
+    // Sorted on the annotation, so the output does not depend on the order of the input
+    let mut m: BTreeMap<String, u32> = BTreeMap::new();
 
-    for line in reader.lines() {
+    for line in lines {
         let line = line.context("Error reading input file")?;
         let row: Vec<&str> = line.split('\t').collect();
         if row[0] != current_pept {
             if !current_pept.is_empty() && !m.is_empty() {
                 write_entry(
+                    writer,
                     current_pept,
                     num_prot,
                     num_annotated_go,
                     num_annotated_ec,
                     num_annotated_ip,
                     &m,
-                );
+                )?;
             }
 
             m.clear();
@@ -47,25 +87,24 @@ fn main() -> Result<()> {
         num_prot += 1;
 
         if row.len() > 1 {
-            let terms = row[1].split(';').map(String::from);
             let mut has_ec = false;
             let mut has_go = false;
             let mut has_ip = false;
 
-            for term in terms {
-                if term.is_empty() {
+            for annotation in row[1].split(SEPARATOR).filter(|a| !a.is_empty()) {
+                // Annotations with an invalid id are counted and left out
+                let Ok(annotation) = annotation.parse::<FunctionalAnnotation>() else {
+                    invalid += 1;
                     continue;
-                }
+                };
 
-                if term.starts_with('G') {
-                    has_go = true;
-                } else if term.starts_with('E') {
-                    has_ec = true;
-                } else {
-                    has_ip = true;
+                match annotation {
+                    FunctionalAnnotation::Ec(_) => has_ec = true,
+                    FunctionalAnnotation::Go(_) => has_go = true,
+                    FunctionalAnnotation::InterPro(_) => has_ip = true,
                 }
 
-                *m.entry(term).or_insert(0) += 1;
+                *m.entry(annotation.to_string()).or_insert(0) += 1;
             }
 
             if has_go {
@@ -81,42 +120,46 @@ fn main() -> Result<()> {
 
         done += 1;
 
-        if done % 1000000 == 0 {
+        if done.is_multiple_of(1000000) {
             eprintln!("[{}] FA {} rows", now_str(), done);
         }
     }
 
     if !m.is_empty() {
         write_entry(
+            writer,
             current_pept,
             num_prot,
             num_annotated_go,
             num_annotated_ec,
             num_annotated_ip,
             &m,
-        );
+        )?;
     }
 
-    Ok(())
+    Ok(invalid)
 }
 
-fn write_entry(
+fn write_entry<W: Write>(
+    writer: &mut W,
     current_peptide: String,
     num_prot: u32,
     num_go: u32,
     num_ec: u32,
     num_ip: u32,
-    m: &HashMap<String, u32>,
-) {
+    m: &BTreeMap<String, u32>,
+) -> Result<()> {
     let data = m
         .iter()
         .map(|(key, value)| format!(r#""{key}":{value}"#))
         .collect::<Vec<String>>()
         .join(",");
 
-    println!(
+    writeln!(
+        writer,
         "{current_peptide}\t{{\"num\":{{\"all\":{num_prot},\"EC\":{num_ec},\"GO\":{num_go},\"IPR\":{num_ip}}},\"data\":{{{data}}}}}\n"
-    );
+    )
+    .context("Error writing to output")
 }
 
 #[derive(Parser, Debug)]
@@ -124,4 +167,58 @@ struct Cli {
     /// TODO
     #[clap(long)]
     input_file: PathBuf,
+
+    /// Sort the input file on peptide, instead of expecting it to be sorted already
+    #[clap(long)]
+    sort_input: bool,
+
+    /// Amount of memory the sorting step is allowed to use (e.g. "2G")
+    #[clap(long, default_value = "2G")]
+    sort_memory: String,
+
+    /// Number of threads used to sort chunks of the input
+    #[clap(long, default_value_t = 1)]
+    sort_threads: usize,
+
+    /// Directory in which temporary files of the sorting step are stored
+    #[clap(long, default_value = "/tmp")]
+    temp_dir: PathBuf,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calculate(input: &str, memory: Option<usize>) -> String {
+        let dir = tempfile::tempdir().unwrap();
+        let sorter =
+            memory.map(|memory| ExternalSorter::new(0, KeyOrder::Bytes, memory, 1, dir.path()));
+
+        let mut output = Vec::new();
+        calculate_functions(read_lines(input.as_bytes(), sorter).unwrap(), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_sort_input() {
+        let sorted = "\
+AAK\tEC:1.1.1.1;GO:0046782
+AAK\tGO:0046782
+CCK\t
+DDK\tIPR:IPR007031
+";
+        let shuffled = "\
+DDK\tIPR:IPR007031
+AAK\tGO:0046782
+CCK\t
+AAK\tEC:1.1.1.1;GO:0046782
+";
+
+        let want = calculate(sorted, None);
+        assert!(want.starts_with(
+            "AAK\t{\"num\":{\"all\":2,\"EC\":1,\"GO\":2,\"IPR\":0},\"data\":{\"EC:1.1.1.1\":1,\"GO:0046782\":2}}\n"
+        ));
+        assert_eq!(calculate(shuffled, Some(1 << 20)), want);
+        assert_eq!(calculate(shuffled, Some(16)), want);
+    }
 }
@@ -0,0 +1,180 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{Context, Error, Result};
+
+/// Separator between the annotations in a serialized list
+pub const SEPARATOR: char = ';';
+
+/// A functional annotation of a protein
+///
+/// The canonical serialization of an annotation is its database prefix, a colon and its id
+/// (e.g. `EC:1.1.1.1`, `GO:0046782` or `IPR:IPR007031`). Lists of annotations are serialized in
+/// the order of this enum (and then by id), joined by semicolons and without empty segments.
+/// Note that this changed the `fa` column of the uniprot_entries table, which used to contain
+/// three groups (EC, GO and InterPro) with an empty segment for every missing group. Readers
+/// that have to handle both layouts can use `parse_list`, which skips empty segments.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone)]
+pub enum FunctionalAnnotation {
+    /// An EC number, without prefix (e.g. `1.1.1.1`, `3.4.-.-` or `1.14.14.n1`)
+    Ec(String),
+    /// The 7 digits of a GO term (e.g. `0046782`)
+    Go(String),
+    /// An InterPro entry (e.g. `IPR007031`)
+    InterPro(String),
+}
+
+impl FunctionalAnnotation {
+    /// Create an annotation from an EC number as it is written in a UniProtKB entry (`1.1.1.1`)
+    pub fn ec(number: &str) -> Result<Self> {
+        let parts: Vec<&str> = number.split('.').collect();
+        let valid = parts.len() == 4
+            && parts.iter().enumerate().all(|(i, part)| {
+                *part == "-"
+                    || is_digits(part, 1..=3)
+                    // Preliminary EC numbers have an "n" in front of their serial number
+                    || (i == 3 && part.strip_prefix('n').is_some_and(|p| is_digits(p, 1..=3)))
+            });
+
+        if !valid {
+            return Err(Error::msg(format!("Invalid EC number {}", number)));
+        }
+
+        Ok(FunctionalAnnotation::Ec(number.to_string()))
+    }
+
+    /// Create an annotation from a GO term as it is written in a UniProtKB entry (`GO:0046782`)
+    pub fn go(term: &str) -> Result<Self> {
+        match term.strip_prefix("GO:") {
+            Some(digits) if is_digits(digits, 7..=7) => {
+                Ok(FunctionalAnnotation::Go(digits.to_string()))
+            }
+            _ => Err(Error::msg(format!("Invalid GO term {}", term))),
+        }
+    }
+
+    /// Create an annotation from an InterPro entry as it is written in a UniProtKB entry (`IPR007031`)
+    pub fn interpro(entry: &str) -> Result<Self> {
+        match entry.strip_prefix("IPR") {
+            Some(digits) if is_digits(digits, 6..=6) => {
+                Ok(FunctionalAnnotation::InterPro(entry.to_string()))
+            }
+            _ => Err(Error::msg(format!("Invalid InterPro entry {}", entry))),
+        }
+    }
+
+    /// The prefix of this type of annotation in its serialization
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            FunctionalAnnotation::Ec(_) => "EC",
+            FunctionalAnnotation::Go(_) => "GO",
+            FunctionalAnnotation::InterPro(_) => "IPR",
+        }
+    }
+
+    /// Parse a serialized list of annotations, ignoring empty segments
+    pub fn parse_list(s: &str) -> Result<Vec<Self>> {
+        s.split(SEPARATOR)
+            .filter(|a| !a.is_empty())
+            .map(|a| a.parse())
+            .collect()
+    }
+
+    /// Serialize a list of annotations in canonical order, leaving out duplicates
+    pub fn join(annotations: &[Self]) -> String {
+        let mut annotations: Vec<&Self> = annotations.iter().collect();
+        annotations.sort();
+        annotations.dedup();
+
+        annotations
+            .iter()
+            .map(|a| a.to_string())
+            .collect::<Vec<String>>()
+            .join(&SEPARATOR.to_string())
+    }
+}
+
+impl fmt::Display for FunctionalAnnotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FunctionalAnnotation::Ec(id)
+            | FunctionalAnnotation::Go(id)
+            | FunctionalAnnotation::InterPro(id) => write!(f, "{}:{}", self.prefix(), id),
+        }
+    }
+}
+
+impl FromStr for FunctionalAnnotation {
+    type Err = Error;
+
+    /// Parse the canonical serialization of an annotation
+    fn from_str(s: &str) -> Result<Self> {
+        let (prefix, id) = s
+            .split_once(':')
+            .with_context(|| format!("Functional annotation {} has no prefix", s))?;
+
+        match prefix {
+            "EC" => FunctionalAnnotation::ec(id),
+            "GO" => FunctionalAnnotation::go(s),
+            "IPR" => FunctionalAnnotation::interpro(id),
+            _ => Err(Error::msg(format!(
+                "Unknown type of functional annotation {}",
+                s
+            ))),
+        }
+    }
+}
+
+fn is_digits(s: &str, length: std::ops::RangeInclusive<usize>) -> bool {
+    length.contains(&s.len()) && s.bytes().all(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validation() {
+        assert!(FunctionalAnnotation::ec("1.1.1.1").is_ok());
+        assert!(FunctionalAnnotation::ec("3.4.-.-").is_ok());
+        assert!(FunctionalAnnotation::ec("1.14.14.n1").is_ok());
+        assert!(FunctionalAnnotation::ec("1.1.1").is_err());
+        assert!(FunctionalAnnotation::ec("1.n1.1.1").is_err());
+
+        assert!(FunctionalAnnotation::go("GO:0046782").is_ok());
+        assert!(FunctionalAnnotation::go("GO:046782").is_err());
+
+        assert!(FunctionalAnnotation::interpro("IPR007031").is_ok());
+        assert!(FunctionalAnnotation::interpro("PF007031").is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let annotations = vec![
+            FunctionalAnnotation::interpro("IPR007031").unwrap(),
+            FunctionalAnnotation::go("GO:0046782").unwrap(),
+            FunctionalAnnotation::ec("1.1.1.1").unwrap(),
+            FunctionalAnnotation::go("GO:0046782").unwrap(),
+        ];
+
+        let serialized = FunctionalAnnotation::join(&annotations);
+        assert_eq!(serialized, "EC:1.1.1.1;GO:0046782;IPR:IPR007031");
+        assert_eq!(
+            FunctionalAnnotation::parse_list(&serialized).unwrap(),
+            vec![
+                annotations[2].clone(),
+                annotations[1].clone(),
+                annotations[0].clone()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_list_ignores_empty_segments() {
+        let parsed = FunctionalAnnotation::parse_list(";GO:0046782;").unwrap();
+        assert_eq!(
+            parsed,
+            vec![FunctionalAnnotation::Go("0046782".to_string())]
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use std::io::{BufRead, Write};
+
+use anyhow::{Context, Error, Result};
+use fst::MapBuilder;
+
+/// Statistics of a built index
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct IndexStats {
+    pub keys: u64,
+    pub skipped: u64,
+}
+
+/// Build a UMGAP-compatible index from a stream of tab-separated lines
+/// The index is a finite state transducer mapping every key (a k-mer or tryptic peptide) onto its
+/// taxon id, which is the format produced by `umgap buildindex`. The keys must be sorted
+/// bytewise and unique. Lines with a missing (`\N`) or empty taxon are skipped.
+pub fn build_index<R: BufRead, W: Write>(
+    reader: R,
+    writer: W,
+    key_column: usize,
+    value_column: usize,
+) -> Result<IndexStats> {
+    let mut builder = MapBuilder::new(writer).context("Unable to start building the index")?;
+    let mut stats = IndexStats::default();
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.context("Error reading line from input")?;
+        if line.is_empty() {
+            continue;
+        }
+
+        let columns: Vec<&str> = line.split('\t').collect();
+        let key = columns
+            .get(key_column)
+            .with_context(|| format!("Line {} has no column {}", i + 1, key_column + 1))?;
+        let value = columns
+            .get(value_column)
+            .with_context(|| format!("Line {} has no column {}", i + 1, value_column + 1))?;
+
+        if value.is_empty() || *value == "\\N" {
+            stats.skipped += 1;
+            continue;
+        }
+
+        let value: u64 = value
+            .parse()
+            .with_context(|| format!("Unable to parse taxon id {} on line {}", value, i + 1))?;
+
+        builder.insert(key, value).map_err(|e| {
+            Error::new(e).context(format!(
+                "Unable to insert {} on line {}, keys must be sorted and unique",
+                key,
+                i + 1
+            ))
+        })?;
+        stats.keys += 1;
+    }
+
+    builder.finish().context("Unable to finish the index")?;
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fst::Map;
+
+    #[test]
+    fn test_build_index_round_trip() {
+        let input = "AAAK\t2\nAALK\t\\N\nACDK\t562\nMLR\t9606\n";
+
+        let mut bytes = Vec::new();
+        let stats = build_index(input.as_bytes(), &mut bytes, 0, 1).unwrap();
+        assert_eq!(
+            stats,
+            IndexStats {
+                keys: 3,
+                skipped: 1
+            }
+        );
+
+        let map = Map::new(bytes).unwrap();
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.get("AAAK"), Some(2));
+        assert_eq!(map.get("ACDK"), Some(562));
+        assert_eq!(map.get("MLR"), Some(9606));
+        assert_eq!(map.get("AALK"), None);
+        assert_eq!(map.get("MLRK"), None);
+    }
+
+    #[test]
+    fn test_build_index_columns() {
+        let input = "1\tAAAK\t2\n2\tMLR\t\\N\n";
+
+        let mut bytes = Vec::new();
+        build_index(input.as_bytes(), &mut bytes, 1, 2).unwrap();
+
+        let map = Map::new(bytes).unwrap();
+        assert_eq!(map.get("AAAK"), Some(2));
+        assert_eq!(map.get("MLR"), None);
+    }
+
+    #[test]
+    fn test_build_index_unsorted() {
+        let input = "MLR\t2\nAAAK\t2\n";
+        assert!(build_index(input.as_bytes(), Vec::new(), 0, 1).is_err());
+    }
+}
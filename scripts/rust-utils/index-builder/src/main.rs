@@ -0,0 +1,54 @@
+use std::io::{BufRead, BufWriter, Write, stdout};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result, ensure};
+use clap::Parser;
+use index_builder::build_index;
+use utils::{now_str, open_read, open_sin, open_write};
+
+fn main() -> Result<()> {
+    let args = Cli::parse();
+    ensure!(
+        args.key_column > 0 && args.value_column > 0,
+        "Columns are numbered starting from 1"
+    );
+
+    let reader: Box<dyn BufRead> = match &args.input_file {
+        Some(pb) => Box::new(open_read(pb).context("Unable to open input file")?),
+        None => Box::new(open_sin()),
+    };
+    let writer: Box<dyn Write> = match &args.output_file {
+        Some(pb) => Box::new(open_write(pb).context("Unable to open output file")?),
+        None => Box::new(BufWriter::new(stdout().lock())),
+    };
+
+    eprintln!("[{}] Building index", now_str());
+    let stats = build_index(reader, writer, args.key_column - 1, args.value_column - 1)?;
+
+    eprintln!(
+        "[{}] Finished building index with {} keys ({} lines without taxon skipped)",
+        now_str(),
+        stats.keys,
+        stats.skipped
+    );
+    Ok(())
+}
+
+#[derive(Parser, Debug)]
+struct Cli {
+    /// Sorted tab-separated input (reads from stdin if omitted)
+    #[clap(long)]
+    input_file: Option<PathBuf>,
+
+    /// File to write the index to (writes to stdout if omitted)
+    #[clap(long)]
+    output_file: Option<PathBuf>,
+
+    /// 1-based column containing the k-mers or peptides
+    #[clap(long, default_value_t = 1)]
+    key_column: usize,
+
+    /// 1-based column containing the taxon ids
+    #[clap(long, default_value_t = 2)]
+    value_column: usize,
+}
@@ -0,0 +1,92 @@
+/// Check if a residue is one of the 20 standard amino acids
+pub fn is_standard_residue(residue: u8) -> bool {
+    matches!(
+        residue,
+        b'A' | b'C'
+            | b'D'
+            | b'E'
+            | b'F'
+            | b'G'
+            | b'H'
+            | b'I'
+            | b'K'
+            | b'L'
+            | b'M'
+            | b'N'
+            | b'P'
+            | b'Q'
+            | b'R'
+            | b'S'
+            | b'T'
+            | b'V'
+            | b'W'
+            | b'Y'
+    )
+}
+
+/// Extract the k-mers of a protein sequence, without copying them
+/// K-mers that contain a non-standard residue are skipped. A k-mer that occurs more than once in
+/// the sequence is also returned more than once.
+pub fn extract_kmers(sequence: &str, k: usize) -> impl Iterator<Item = &str> {
+    // Amount of standard residues directly in front of (and including) the current position
+    let mut valid_run = 0;
+
+    sequence
+        .bytes()
+        .enumerate()
+        .filter_map(move |(i, residue)| {
+            if is_standard_residue(residue) {
+                valid_run += 1;
+            } else {
+                valid_run = 0;
+            }
+
+            // The k-mer only consists of ASCII characters, so these are valid char boundaries
+            (k > 0 && valid_run >= k).then(|| &sequence[i + 1 - k..=i])
+        })
+}
+
+/// Append a k-mer to a buffer, replacing every isoleucine by a leucine if `equate_il` is set
+pub fn write_kmer(buffer: &mut String, kmer: &str, equate_il: bool) {
+    if equate_il {
+        buffer.extend(kmer.chars().map(|c| if c == 'I' { 'L' } else { c }));
+    } else {
+        buffer.push_str(kmer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kmers(sequence: &str, k: usize) -> Vec<&str> {
+        extract_kmers(sequence, k).collect()
+    }
+
+    #[test]
+    fn test_extract_kmers() {
+        assert_eq!(kmers("MKLAA", 3), vec!["MKL", "KLA", "LAA"]);
+        assert_eq!(kmers("MKLMKL", 3), vec!["MKL", "KLM", "LMK", "MKL"]);
+        assert!(kmers("MK", 3).is_empty());
+        assert!(kmers("MK", 0).is_empty());
+    }
+
+    #[test]
+    fn test_extract_kmers_skips_non_standard_residues() {
+        assert_eq!(kmers("MKXLAAB", 3), vec!["LAA"]);
+    }
+
+    #[test]
+    fn test_write_kmer_equates_il() {
+        let mut buffer = String::new();
+        for kmer in extract_kmers("IAIA", 3) {
+            write_kmer(&mut buffer, kmer, true);
+            buffer.push(' ');
+        }
+        assert_eq!(buffer, "LAL ALA ");
+
+        buffer.clear();
+        write_kmer(&mut buffer, "IAI", false);
+        assert_eq!(buffer, "IAI");
+    }
+}
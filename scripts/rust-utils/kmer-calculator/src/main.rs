@@ -0,0 +1,142 @@
+mod kmers;
+
+use std::io::{BufRead, BufWriter, Write, stdout};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use external_sort::{ExternalSorter, KeyOrder, SortedLines, key, parse_memory_size};
+use lca_calculator::taxonomy::Taxonomy;
+use utils::{now_str, open_read};
+
+use crate::kmers::{extract_kmers, write_kmer};
+
+// Columns of the uniprot_entries table
+const TAXON_COLUMN: usize = 3;
+const SEQUENCE_COLUMN: usize = 6;
+
+fn main() -> Result<()> {
+    let args = Cli::parse();
+
+    eprintln!("[{}] Reading taxonomy", now_str());
+    let taxonomy = Taxonomy::build(&args.lineages).context("Unable to build taxonomy")?;
+
+    eprintln!("[{}] Extracting {}-mers", now_str(), args.kmer_length);
+    let mut sorter = ExternalSorter::new(
+        0,
+        KeyOrder::Bytes,
+        parse_memory_size(&args.sort_memory)?,
+        args.sort_threads,
+        &args.temp_dir,
+    );
+
+    let reader = open_read(&args.uniprot_entries).context("Unable to open UniProt entries file")?;
+    for (i, line) in reader.lines().enumerate() {
+        if i % 1000000 == 0 && i != 0 {
+            eprintln!("[{}] {} entries", now_str(), i);
+        }
+
+        let line = line.context("Error reading line from UniProt entries file")?;
+        let taxon_id = key(&line, TAXON_COLUMN);
+        taxon_id
+            .parse::<i32>()
+            .with_context(|| format!("Unable to parse taxon id of entry {}", key(&line, 1)))?;
+
+        // Every k-mer is written straight into the buffer of the sorter
+        for kmer in extract_kmers(key(&line, SEQUENCE_COLUMN), args.kmer_length) {
+            sorter.push_with(|buffer| {
+                write_kmer(buffer, kmer, args.equate_il);
+                buffer.push('\t');
+                buffer.push_str(taxon_id);
+                Ok(())
+            })?;
+        }
+    }
+
+    eprintln!("[{}] Calculating {}-mer LCAs", now_str(), args.kmer_length);
+    write_kmer_lcas(sorter.finish()?, &taxonomy)?;
+
+    eprintln!(
+        "[{}] Finished calculating {}-mer LCAs",
+        now_str(),
+        args.kmer_length
+    );
+    Ok(())
+}
+
+/// Group the sorted (k-mer, taxon) pairs per k-mer and write the LCA of every k-mer to stdout
+fn write_kmer_lcas(lines: SortedLines, taxonomy: &Taxonomy) -> Result<()> {
+    let mut writer = BufWriter::new(stdout().lock());
+    let mut current_kmer = String::new();
+    let mut taxa: Vec<i32> = Vec::new();
+
+    for line in lines {
+        let line = line?;
+        let (kmer, taxon_id) = line
+            .split_once('\t')
+            .context("Unable to split k-mer line on tabs")?;
+        let taxon_id: i32 = taxon_id
+            .parse()
+            .with_context(|| format!("Unable to parse taxon id {}", taxon_id))?;
+
+        if kmer != current_kmer {
+            if !current_kmer.is_empty() {
+                writeln!(
+                    &mut writer,
+                    "{}\t{}",
+                    current_kmer,
+                    taxonomy.calculate_lca(&taxa)
+                )
+                .context("Error writing k-mer LCA")?;
+            }
+
+            current_kmer = kmer.to_string();
+            taxa.clear();
+        }
+
+        taxa.push(taxon_id);
+    }
+
+    if !current_kmer.is_empty() {
+        writeln!(
+            &mut writer,
+            "{}\t{}",
+            current_kmer,
+            taxonomy.calculate_lca(&taxa)
+        )
+        .context("Error writing k-mer LCA")?;
+    }
+
+    writer.flush().context("Error flushing k-mer LCAs")
+}
+
+#[derive(Parser, Debug)]
+struct Cli {
+    /// Path to the uniprot_entries.tsv file (generated by uniprot-parser)
+    #[clap(long)]
+    uniprot_entries: PathBuf,
+
+    /// Path to the lineages.tsv file (generated by taxdmp-parser)
+    #[clap(long)]
+    lineages: PathBuf,
+
+    /// Length of the k-mers
+    #[clap(long, default_value_t = 9)]
+    kmer_length: usize,
+
+    /// Replace every isoleucine by a leucine before extracting k-mers
+    #[clap(long)]
+    equate_il: bool,
+
+    /// Amount of memory the sorting step is allowed to use (e.g. "2G")
+    #[clap(long, default_value = "2G")]
+    sort_memory: String,
+
+    /// Number of threads used to sort chunks of the k-mers
+    #[clap(long, default_value_t = 1)]
+    sort_threads: usize,
+
+    /// Directory in which temporary files of the sorting step are stored
+    #[clap(long, default_value = "/tmp")]
+    temp_dir: PathBuf,
+}
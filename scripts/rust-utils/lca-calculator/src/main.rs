@@ -1,10 +1,10 @@
-mod taxonomy;
-
-use crate::taxonomy::Taxonomy;
 use anyhow::{Context, Result};
 use clap::Parser;
+use external_sort::{ExternalSorter, KeyOrder, parse_memory_size, read_lines};
+use lca_calculator::taxonomy::Taxonomy;
+use std::io::{BufWriter, Write, stdout};
 use std::path::PathBuf;
-use utils::now_str;
+use utils::{now_str, open_sin};
 
 fn main() -> Result<()> {
     let args = Cli::parse();
@@ -12,8 +12,24 @@ fn main() -> Result<()> {
     eprintln!("[{}] Reading taxonomy", now_str());
     let tax = Taxonomy::build(&args.input_file).context("Unable to build taxonomy")?;
 
+    let sorter = if args.sort_input {
+        eprintln!("[{}] Sorting sequences", now_str());
+        Some(ExternalSorter::new(
+            0,
+            KeyOrder::Bytes,
+            parse_memory_size(&args.sort_memory)?,
+            args.sort_threads,
+            &args.temp_dir,
+        ))
+    } else {
+        None
+    };
+    let lines = read_lines(open_sin(), sorter)?;
+
     eprintln!("[{}] Reading sequences", now_str());
-    tax.calculate_lcas()
+    let mut writer = BufWriter::new(stdout().lock());
+    tax.calculate_lcas(lines, &mut writer)?;
+    writer.flush().context("Error writing to output")
 }
 
 #[derive(Parser, Debug)]
@@ -21,4 +37,61 @@ struct Cli {
     /// TODO
     #[clap(long)]
     input_file: PathBuf,
+
+    /// Sort the sequences read from stdin, instead of expecting them to be sorted already
+    #[clap(long)]
+    sort_input: bool,
+
+    /// Amount of memory the sorting step is allowed to use (e.g. "2G")
+    #[clap(long, default_value = "2G")]
+    sort_memory: String,
+
+    /// Number of threads used to sort chunks of the input
+    #[clap(long, default_value_t = 1)]
+    sort_threads: usize,
+
+    /// Directory in which temporary files of the sorting step are stored
+    #[clap(long, default_value = "/tmp")]
+    temp_dir: PathBuf,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Taxa 5 and 6 are both children of taxon 2
+    fn taxonomy(dir: &std::path::Path) -> Taxonomy {
+        let lineage = |id: i32, parent: i32| {
+            let mut columns = vec![id.to_string(), parent.to_string(), id.to_string()];
+            columns.resize(ncbi::RANKS, "\\N".to_string());
+            columns.join("\t")
+        };
+
+        let pb = dir.join("lineages.tsv");
+        std::fs::write(&pb, format!("{}\n{}\n", lineage(5, 2), lineage(6, 2))).unwrap();
+        Taxonomy::build(&pb).unwrap()
+    }
+
+    fn calculate(input: &str, memory: Option<usize>) -> String {
+        let dir = tempfile::tempdir().unwrap();
+        let sorter =
+            memory.map(|memory| ExternalSorter::new(0, KeyOrder::Bytes, memory, 1, dir.path()));
+
+        let mut output = Vec::new();
+        taxonomy(dir.path())
+            .calculate_lcas(read_lines(input.as_bytes(), sorter).unwrap(), &mut output)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_sort_input() {
+        let sorted = "AAK\t5\nAAK\t6\nCCK\t6\nDDK\t5\nDDK\t5\n";
+        let shuffled = "DDK\t5\nAAK\t6\nCCK\t6\nDDK\t5\nAAK\t5\n";
+
+        let want = calculate(sorted, None);
+        assert_eq!(want, "AAK\t2\nCCK\t6\nDDK\t5\n");
+        assert_eq!(calculate(shuffled, Some(1 << 20)), want);
+        assert_eq!(calculate(shuffled, Some(16)), want);
+    }
 }
@@ -1,10 +1,10 @@
 use std::collections::HashMap;
-use std::io::BufRead;
+use std::io::{BufRead, Write};
 use std::path::PathBuf;
 
 use anyhow::{Context, Result};
 use ncbi::{RANKS, Rank};
-use utils::{now_str, open_read, open_sin};
+use utils::{now_str, open_read};
 
 const NULL_STRING: &str = "\\N";
 const SEPARATOR: &str = "\t";
@@ -51,18 +51,22 @@ impl Taxonomy {
         Ok(Taxonomy { taxonomy })
     }
 
-    pub fn calculate_lcas(&self) -> Result<()> {
-        let reader = open_sin();
-
+    /// Calculate the LCA of every sequence in `lines`, which have to be sorted on the sequence, and
+    /// write every sequence with its LCA to `writer`
+    pub fn calculate_lcas<I: Iterator<Item = Result<String>>, W: Write>(
+        &self,
+        lines: I,
+        writer: &mut W,
+    ) -> Result<()> {
         let mut current_sequence = String::new();
         let mut taxa: Vec<i32> = Vec::new();
 
-        for (i, line) in reader.lines().enumerate() {
+        for (i, line) in lines.enumerate() {
             if i % 10000000 == 0 && i != 0 {
                 eprintln!("[{}] {}", now_str(), i);
             }
 
-            let line = line.context("error reading line from stdin")?;
+            let line = line.context("error reading line from input")?;
 
             let (sequence, taxon_id) =
                 line.split_once(SEPARATOR).context("error splitting line")?;
@@ -73,7 +77,7 @@ impl Taxonomy {
 
             if current_sequence.is_empty() || current_sequence != sequence {
                 if !current_sequence.is_empty() {
-                    self.handle_lca(&current_sequence, self.calculate_lca(&taxa));
+                    self.handle_lca(writer, &current_sequence, self.calculate_lca(&taxa))?;
                 }
 
                 current_sequence = sequence.to_string();
@@ -83,11 +87,12 @@ impl Taxonomy {
             taxa.push(taxon_id);
         }
 
-        self.handle_lca(&current_sequence, self.calculate_lca(&taxa));
-        Ok(())
+        self.handle_lca(writer, &current_sequence, self.calculate_lca(&taxa))
     }
 
-    fn calculate_lca(&self, taxa: &[i32]) -> i32 {
+    /// Calculate the lowest common ancestor of the given taxa, based on their lineages
+    /// Taxa without a lineage are ignored
+    pub fn calculate_lca(&self, taxa: &[i32]) -> i32 {
         let mut lca = 1;
         // -1 for each rank to account for the root that's not explicitly part of the lineage array
         let genus_rank_idx = Rank::Genus.index() - 1;
@@ -95,7 +100,7 @@ impl Taxonomy {
 
         let lineages: Vec<&Vec<i32>> = taxa
             .iter()
-            .map(|x| &self.taxonomy[*x as usize])
+            .filter_map(|x| self.taxonomy.get(*x as usize))
             .filter(|x| !x.is_empty())
             .collect();
 
@@ -134,8 +139,23 @@ impl Taxonomy {
         lca
     }
 
-    fn handle_lca(&self, sequence: &String, lca: i32) {
-        println!("{}\t{}", sequence, lca);
+    /// Get the ancestor of a taxon at the given rank, or None if the taxon is unknown or has no
+    /// ancestor at that rank
+    /// Ancestors that are not valid taxa are stored as negative ids in the lineages table; this
+    /// returns their actual id.
+    pub fn rank_ancestor(&self, taxon: i32, rank: Rank) -> Option<i32> {
+        if rank == Rank::NoRank {
+            return None;
+        }
+
+        // -1 to account for the root that's not explicitly part of the lineage array
+        let ancestor = *self.taxonomy.get(taxon as usize)?.get(rank.index() - 1)?;
+
+        (ancestor != 0).then_some(ancestor.abs())
+    }
+
+    fn handle_lca<W: Write>(&self, writer: &mut W, sequence: &String, lca: i32) -> Result<()> {
+        writeln!(writer, "{}\t{}", sequence, lca).context("Error writing to output")
     }
 }
 
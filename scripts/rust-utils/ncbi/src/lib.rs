@@ -3,7 +3,7 @@ use strum_macros::{Display, EnumCount, EnumIter, EnumString};
 pub const RANKS: usize = 29;
 
 #[rustfmt::skip]
-#[derive(PartialEq, Eq, Debug, Clone, Copy, Display, EnumString, EnumCount, EnumIter)]
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Display, EnumString, EnumCount, EnumIter)]
 pub enum Rank {
     #[strum(serialize="no rank")]                     NoRank,
     #[strum(serialize="domain")]                      Domain,
@@ -36,27 +36,80 @@ pub enum Rank {
     #[strum(serialize="forma")]                       Forma,
 }
 
+/// Ranks used in the NCBI taxonomy that do not have a column in the lineages. Taxa with one of
+/// these ranks are treated as if they have no rank when building lineages.
+pub const UNRANKED_RANKS: &[&str] = &[
+    "clade",
+    "cellular root",
+    "infraclass",
+    "cohort",
+    "subcohort",
+    "parvorder",
+    "section",
+    "subsection",
+    "series",
+    "forma specialis",
+    "subvariety",
+    "serogroup",
+    "serotype",
+    "isolate",
+    "biotype",
+    "genotype",
+    "morph",
+    "pathogroup",
+];
+
 impl Rank {
     pub fn index(&self) -> usize {
         *self as usize
     }
+
+    /// Map a rank from the NCBI taxonomy onto the rank used in lineages, or None if the rank is
+    /// unknown. Viruses are placed directly under an "acellular root", which acts as their domain.
+    pub fn from_ncbi(rank: &str) -> Option<Rank> {
+        match rank {
+            "acellular root" | "superkingdom" => Some(Rank::Domain),
+            r if UNRANKED_RANKS.contains(&r) => Some(Rank::NoRank),
+            r => r.parse().ok(),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Taxon {
     pub name: String,
+    /// The rank that is used to build lineages
     pub rank: Rank,
+    /// The rank exactly as it occurs in the NCBI taxonomy
+    pub rank_name: String,
     pub parent: usize,
     pub valid: bool,
 }
 
 impl Taxon {
-    pub fn new(name: String, rank: Rank, parent: usize, valid: bool) -> Self {
+    pub fn new(name: String, rank: Rank, rank_name: String, parent: usize, valid: bool) -> Self {
         Taxon {
             name,
             rank,
+            rank_name,
             parent,
             valid,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rank_from_ncbi() {
+        assert_eq!(Rank::from_ncbi("species"), Some(Rank::Species));
+        assert_eq!(Rank::from_ncbi("no rank"), Some(Rank::NoRank));
+        assert_eq!(Rank::from_ncbi("clade"), Some(Rank::NoRank));
+        assert_eq!(Rank::from_ncbi("cellular root"), Some(Rank::NoRank));
+        assert_eq!(Rank::from_ncbi("acellular root"), Some(Rank::Domain));
+        assert_eq!(Rank::from_ncbi("superkingdom"), Some(Rank::Domain));
+        assert_eq!(Rank::from_ncbi("hyperclass"), None);
+    }
+}
@@ -0,0 +1,493 @@
+mod specificity;
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Lines, Seek, SeekFrom, Write};
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Error, Result};
+use clap::Parser;
+use external_sort::{ExternalSorter, KeyOrder, SortedLines, compare_keys, key, parse_memory_size};
+use lca_calculator::taxonomy::Taxonomy;
+use ncbi::Rank;
+use utils::{now_str, open_read, open_write};
+
+use crate::specificity::{Specificity, classify, proteome_unique};
+
+const NULL_STRING: &str = "\\N";
+
+// Columns of the peptides_by_equalized table written by sequence-numberer
+const SEQUENCE_ID_COLUMN: usize = 1;
+const ENTRY_COLUMN: usize = 3;
+const TAXON_COLUMN: usize = 5;
+
+// Column of the output file that holds the proteome-unique flag
+const PROTEOME_UNIQUE_COLUMN: usize = 4;
+
+fn main() -> Result<()> {
+    let args = Cli::parse();
+    let memory = parse_memory_size(&args.sort_memory)?;
+    let with_proteomes = args.proteomes.is_some() && args.uniprot_entries.is_some();
+    // Linking peptides to proteomes takes a few sorting steps, of which at most three are alive
+    // at the same time
+    let share = if with_proteomes { memory / 3 } else { memory };
+
+    eprintln!("[{}] Reading taxonomy", now_str());
+    let taxonomy = Taxonomy::build(&args.lineages).context("Unable to build taxonomy")?;
+
+    let mut entry_proteomes = match (&args.proteomes, &args.uniprot_entries) {
+        (Some(proteomes), Some(entries)) => {
+            eprintln!("[{}] Linking entries to proteomes", now_str());
+            Some(sort_entry_proteomes(
+                proteomes,
+                entries,
+                share,
+                &args.temp_dir,
+            )?)
+        }
+        _ => None,
+    };
+    // Entry and sequence id of every peptide, sorted on entry id
+    let mut peptide_entries = entry_proteomes
+        .as_ref()
+        .map(|_| ExternalSorter::new(0, KeyOrder::Numeric, share, 1, &args.temp_dir));
+
+    let mut sequences = SequenceLookup {
+        lines: open_read(&args.sequences)
+            .context("Unable to open sequences file")?
+            .lines(),
+    };
+    // The proteome-unique flags are only known once all peptides have been read, so the output
+    // is first written to a temporary file in that case
+    let mut output = if with_proteomes {
+        BufWriter::new(
+            tempfile::tempfile_in(&args.temp_dir)
+                .context("Unable to create temporary output file")?,
+        )
+    } else {
+        open_write(&args.output_file).context("Unable to open output file")?
+    };
+    let mut markers = ExternalSorter::new(0, KeyOrder::Numeric, share, 1, &args.temp_dir);
+
+    eprintln!("[{}] Classifying peptides", now_str());
+    let reader = open_read(&args.peptides).context("Unable to open peptides file")?;
+    let mut current: Option<u64> = None;
+    let mut entries: Vec<i64> = Vec::new();
+    let mut taxa: Vec<i32> = Vec::new();
+
+    let mut handle_group = |sequence_id: u64, entries: &[i64], taxa: &[i32]| -> Result<()> {
+        let specificity = classify(entries, taxa, &args.ranks, |taxon, rank| {
+            taxonomy.rank_ancestor(taxon, rank)
+        });
+        let sequence = sequences.get(sequence_id)?;
+
+        write_specificity(&mut output, sequence_id, &sequence, &specificity)?;
+        for (rank, taxon) in args.ranks.iter().zip(&specificity.rank_taxa) {
+            if let Some(taxon) = taxon {
+                markers.push_with(|line| write!(line, "{}\t{}\t{}", taxon, rank, sequence))?;
+            }
+        }
+        if let Some(peptide_entries) = &mut peptide_entries {
+            for entry in entries {
+                peptide_entries.push_with(|line| write!(line, "{}\t{}", entry, sequence_id))?;
+            }
+        }
+
+        Ok(())
+    };
+
+    for line in reader.lines() {
+        let line = line.context("Error reading line from peptides file")?;
+        let sequence_id: u64 = parse_column(&line, SEQUENCE_ID_COLUMN)?;
+
+        if current.is_some_and(|c| c != sequence_id) {
+            // Safe to unwrap, we just checked it
+            handle_group(current.unwrap(), &entries, &taxa)?;
+            entries.clear();
+            taxa.clear();
+        }
+
+        current = Some(sequence_id);
+        entries.push(parse_column(&line, ENTRY_COLUMN)?);
+        taxa.push(parse_column(&line, TAXON_COLUMN)?);
+    }
+
+    if let Some(sequence_id) = current {
+        handle_group(sequence_id, &entries, &taxa)?;
+    }
+    output.flush().context("Error flushing output file")?;
+
+    eprintln!("[{}] Writing marker peptides", now_str());
+    let mut markers_output =
+        open_write(&args.markers_file).context("Unable to open marker peptides file")?;
+    for line in markers.finish()? {
+        writeln!(&mut markers_output, "{}", line?)
+            .context("Error writing to marker peptides file")?;
+    }
+    markers_output
+        .flush()
+        .context("Error flushing marker peptides file")?;
+
+    if let (Some(entry_proteomes), Some(peptide_entries)) =
+        (entry_proteomes.take(), peptide_entries.take())
+    {
+        eprintln!("[{}] Marking proteome-unique peptides", now_str());
+        let peptide_proteomes = sort_peptide_proteomes(
+            entry_proteomes,
+            peptide_entries.finish()?,
+            share,
+            &args.temp_dir,
+        )?;
+
+        let mut classified = output
+            .into_inner()
+            .context("Error flushing temporary output file")?;
+        classified
+            .seek(SeekFrom::Start(0))
+            .context("Error rewinding temporary output file")?;
+
+        let mut writer = open_write(&args.output_file).context("Unable to open output file")?;
+        write_proteome_unique(BufReader::new(classified), peptide_proteomes, &mut writer)?;
+        writer.flush().context("Error flushing output file")?;
+    }
+
+    eprintln!("[{}] Finished classifying peptides", now_str());
+    Ok(())
+}
+
+/// Link every UniProt entry to the proteomes it belongs to, based on the proteomes table
+/// (proteome and accession number) and the uniprot_entries table
+/// Returns lines of entry id and proteome, sorted on entry id.
+fn sort_entry_proteomes(
+    proteomes_pb: &PathBuf,
+    entries_pb: &PathBuf,
+    memory: usize,
+    temp_dir: &Path,
+) -> Result<SortedLines> {
+    let mut by_accession = ExternalSorter::new(1, KeyOrder::Bytes, memory, 1, temp_dir);
+    by_accession.push_all(open_read(proteomes_pb).context("Unable to open proteomes file")?)?;
+
+    let mut entries = ExternalSorter::new(0, KeyOrder::Bytes, memory, 1, temp_dir);
+    let reader = open_read(entries_pb).context("Unable to open UniProt entries file")?;
+    for line in reader.lines() {
+        let line = line.context("Error reading line from UniProt entries file")?;
+        entries.push_with(|buffer| write!(buffer, "{}\t{}", key(&line, 1), key(&line, 0)))?;
+    }
+
+    let mut proteomes = by_accession.finish()?.peekable();
+    let mut linked = ExternalSorter::new(0, KeyOrder::Numeric, memory, 1, temp_dir);
+    let mut group = Group::default();
+    for line in entries.finish()? {
+        let line = line?;
+        let (accession, entry) = line
+            .split_once('\t')
+            .context("Unable to split entry line on tabs")?;
+
+        for proteome in group.get(&mut proteomes, 1, accession, KeyOrder::Bytes)? {
+            linked.push_with(|buffer| write!(buffer, "{}\t{}", entry, key(proteome, 0)))?;
+        }
+    }
+
+    linked.finish()
+}
+
+/// Join the proteomes of every entry onto the peptides of that entry. Both inputs have to be
+/// sorted on entry id.
+/// Returns lines of sequence id, entry id and proteome (empty if the entry has no proteomes),
+/// sorted on sequence id.
+fn sort_peptide_proteomes(
+    entry_proteomes: SortedLines,
+    peptide_entries: SortedLines,
+    memory: usize,
+    temp_dir: &Path,
+) -> Result<SortedLines> {
+    let mut entry_proteomes = entry_proteomes.peekable();
+    let mut linked = ExternalSorter::new(0, KeyOrder::Numeric, memory, 1, temp_dir);
+    let mut group = Group::default();
+
+    for line in peptide_entries {
+        let line = line?;
+        let (entry, sequence_id) = line
+            .split_once('\t')
+            .context("Unable to split peptide entry line on tabs")?;
+
+        let proteomes = group.get(&mut entry_proteomes, 0, entry, KeyOrder::Numeric)?;
+        if proteomes.is_empty() {
+            linked.push_with(|buffer| write!(buffer, "{}\t{}\t", sequence_id, entry))?;
+        }
+        for proteome in proteomes {
+            linked.push_with(|buffer| {
+                write!(buffer, "{}\t{}\t{}", sequence_id, entry, key(proteome, 1))
+            })?;
+        }
+    }
+
+    linked.finish()
+}
+
+/// Fill in the proteome-unique flag of every classified peptide, based on the proteomes of its
+/// entries. Both inputs have to be sorted on sequence id.
+fn write_proteome_unique<R: BufRead, W: Write>(
+    classified: R,
+    peptide_proteomes: SortedLines,
+    writer: &mut W,
+) -> Result<()> {
+    let mut peptide_proteomes = peptide_proteomes.peekable();
+    let mut group = Group::default();
+
+    for line in classified.lines() {
+        let line = line.context("Error reading temporary output file")?;
+        let sequence_id = key(&line, 0);
+
+        let mut entry_proteomes: HashMap<&str, Vec<&str>> = HashMap::new();
+        for linked in group.get(&mut peptide_proteomes, 0, sequence_id, KeyOrder::Numeric)? {
+            let proteomes = entry_proteomes.entry(key(linked, 1)).or_default();
+            let proteome = key(linked, 2);
+            if !proteome.is_empty() {
+                proteomes.push(proteome);
+            }
+        }
+
+        let flag = if proteome_unique(&entry_proteomes) {
+            "1"
+        } else {
+            "0"
+        };
+        writeln!(
+            writer,
+            "{}",
+            replace_column(&line, PROTEOME_UNIQUE_COLUMN, flag)
+        )
+        .context("Error writing to output file")?;
+    }
+
+    Ok(())
+}
+
+/// The lines of a sorted stream that share the same key, cached so the group can be requested
+/// again for the next line of the other side of a merge-join
+#[derive(Default)]
+struct Group {
+    key: Option<String>,
+    lines: Vec<String>,
+}
+
+impl Group {
+    /// Get the lines of which the key in `column` equals `wanted`, skipping all lines with a
+    /// smaller key. Keys have to be requested in ascending order.
+    fn get(
+        &mut self,
+        lines: &mut Peekable<SortedLines>,
+        column: usize,
+        wanted: &str,
+        order: KeyOrder,
+    ) -> Result<&[String]> {
+        if self.key.as_deref() != Some(wanted) {
+            self.key = Some(wanted.to_string());
+            self.lines.clear();
+
+            loop {
+                let line = match lines.peek() {
+                    None => break,
+                    Some(Ok(line)) => line,
+                    Some(Err(_)) => return Err(lines.next().unwrap().unwrap_err()),
+                };
+
+                match compare_keys(key(line, column), wanted, order) {
+                    std::cmp::Ordering::Less => {}
+                    std::cmp::Ordering::Equal => self.lines.push(line.clone()),
+                    std::cmp::Ordering::Greater => break,
+                }
+                lines.next();
+            }
+        }
+
+        Ok(&self.lines)
+    }
+}
+
+fn replace_column(line: &str, column: usize, value: &str) -> String {
+    line.split('\t')
+        .enumerate()
+        .map(|(i, v)| if i == column { value } else { v })
+        .collect::<Vec<&str>>()
+        .join("\t")
+}
+
+/// Write a line with the sequence id, the sequence, the amount of proteins, whether the peptide
+/// is protein-unique and proteome-unique and the unique taxon for every rank
+fn write_specificity<W: Write>(
+    writer: &mut W,
+    sequence_id: u64,
+    sequence: &str,
+    specificity: &Specificity,
+) -> Result<()> {
+    let flag = |value: bool| if value { "1" } else { "0" };
+
+    write!(
+        writer,
+        "{}\t{}\t{}\t{}\t{}",
+        sequence_id,
+        sequence,
+        specificity.proteins,
+        flag(specificity.protein_unique()),
+        specificity.proteome_unique.map_or(NULL_STRING, flag)
+    )
+    .context("Error writing to output file")?;
+
+    for taxon in &specificity.rank_taxa {
+        match taxon {
+            Some(taxon) => write!(writer, "\t{}", taxon),
+            None => write!(writer, "\t{}", NULL_STRING),
+        }
+        .context("Error writing to output file")?;
+    }
+
+    writeln!(writer).context("Error writing to output file")
+}
+
+/// Looks up sequences by id in the sequences table, of which the ids are strictly increasing
+/// Ids have to be requested in increasing order as well.
+struct SequenceLookup {
+    lines: Lines<BufReader<File>>,
+}
+
+impl SequenceLookup {
+    fn get(&mut self, id: u64) -> Result<String> {
+        for line in self.lines.by_ref() {
+            let line = line.context("Error reading line from sequences file")?;
+            let current: u64 = parse_column(&line, 0)?;
+
+            if current == id {
+                return Ok(key(&line, 1).to_string());
+            }
+            if current > id {
+                break;
+            }
+        }
+
+        Err(Error::msg(format!(
+            "Sequence {} does not occur in the sequences file",
+            id
+        )))
+    }
+}
+
+fn parse_column<T: std::str::FromStr>(line: &str, column: usize) -> Result<T> {
+    let value = key(line, column);
+    value
+        .parse()
+        .map_err(|_| Error::msg(format!("Unable to parse {} in line {}", value, line)))
+}
+
+#[derive(Parser, Debug)]
+struct Cli {
+    /// Path to the peptides_by_equalized.tsv file (generated by sequence-numberer)
+    #[clap(long)]
+    peptides: PathBuf,
+
+    /// Path to the sequences.tsv file (generated by sequence-numberer)
+    #[clap(long)]
+    sequences: PathBuf,
+
+    /// Path to the lineages.tsv file (generated by taxdmp-parser)
+    #[clap(long)]
+    lineages: PathBuf,
+
+    /// Path to the proteomes file (generated by uniprot-parser), used to mark proteome-unique
+    /// peptides
+    #[clap(long, requires = "uniprot_entries")]
+    proteomes: Option<PathBuf>,
+
+    /// Path to the uniprot_entries.tsv file, used to link proteomes to entries
+    #[clap(long, requires = "proteomes")]
+    uniprot_entries: Option<PathBuf>,
+
+    /// Ranks at which peptides are checked for uniqueness (comma-separated)
+    #[clap(long, value_delimiter = ',', default_value = "species,genus")]
+    ranks: Vec<Rank>,
+
+    /// Output file with a line for every peptide: sequence id, sequence, amount of proteins,
+    /// protein-unique, proteome-unique (\N without proteomes) and the taxon in which the
+    /// peptide is unique (or \N) for every rank
+    #[clap(long)]
+    output_file: PathBuf,
+
+    /// Output file with the marker peptides of every taxon: taxon id, rank and sequence,
+    /// sorted by taxon id
+    #[clap(long)]
+    markers_file: PathBuf,
+
+    /// Amount of memory the sorting steps are allowed to use (e.g. "2G")
+    #[clap(long, default_value = "2G")]
+    sort_memory: String,
+
+    /// Directory in which temporary files are stored
+    #[clap(long, default_value = "/tmp")]
+    temp_dir: PathBuf,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proteome_unique_join() {
+        let dir = tempfile::tempdir().unwrap();
+        let pb = |name: &str| dir.path().join(name);
+        // Entry 1 (P1) is part of proteomes UP1 and UP2, entries 2 (P2) and 5 (P5) of UP1 and
+        // entry 3 (P3) of UP3. Entry 4 (P4) has no proteome.
+        std::fs::write(
+            pb("proteomes.tsv"),
+            "UP3\tP3\nUP1\tP1\nUP2\tP1\nUP1\tP2\nUP1\tP5\n",
+        )
+        .unwrap();
+        std::fs::write(pb("entries.tsv"), "1\tP1\n2\tP2\n3\tP3\n4\tP4\n5\tP5\n").unwrap();
+
+        for memory in [64, 1 << 20] {
+            let entry_proteomes =
+                sort_entry_proteomes(&pb("proteomes.tsv"), &pb("entries.tsv"), memory, dir.path())
+                    .unwrap();
+
+            // Sequence 10 occurs in entries 1 and 2, 20 in 1 and 3, 30 in 3, 40 in 2 and 4 and 50
+            // in 2 and 5
+            let mut peptide_entries =
+                ExternalSorter::new(0, KeyOrder::Numeric, memory, 1, dir.path());
+            for line in [
+                "1\t10", "2\t10", "1\t20", "3\t20", "3\t30", "2\t40", "4\t40", "2\t50", "5\t50",
+            ] {
+                peptide_entries.push(line).unwrap();
+            }
+
+            let peptide_proteomes = sort_peptide_proteomes(
+                entry_proteomes,
+                peptide_entries.finish().unwrap(),
+                memory,
+                dir.path(),
+            )
+            .unwrap();
+
+            let classified = "\
+10\tAAK\t2\t0\t\\N
+20\tCCK\t2\t0\t\\N
+30\tDDK\t1\t1\t\\N
+40\tEEK\t2\t0\t\\N
+50\tFFK\t2\t0\t\\N
+";
+            let mut output = Vec::new();
+            write_proteome_unique(classified.as_bytes(), peptide_proteomes, &mut output).unwrap();
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                "\
+10\tAAK\t2\t0\t0
+20\tCCK\t2\t0\t0
+30\tDDK\t1\t1\t1
+40\tEEK\t2\t0\t0
+50\tFFK\t2\t0\t1
+"
+            );
+        }
+    }
+}
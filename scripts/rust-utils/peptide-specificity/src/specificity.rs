@@ -0,0 +1,139 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+use ncbi::Rank;
+
+/// How specific a single peptide is for the proteins, proteomes and taxa it occurs in
+#[derive(Debug, PartialEq, Eq)]
+pub struct Specificity {
+    /// Amount of distinct proteins that contain the peptide
+    pub proteins: usize,
+    /// Whether the peptide occurs in exactly one proteome, or None if proteomes are unknown
+    pub proteome_unique: Option<bool>,
+    /// For every requested rank, the only taxon at that rank in which the peptide occurs
+    pub rank_taxa: Vec<Option<i32>>,
+}
+
+impl Specificity {
+    pub fn protein_unique(&self) -> bool {
+        self.proteins == 1
+    }
+}
+
+/// Determine the specificity of a peptide from the entries and taxa it occurs in
+///
+/// A peptide is unique at a rank if all of its taxa have the same ancestor at that rank. Whether
+/// it is proteome-unique is left unknown, see `proteome_unique`.
+pub fn classify<F>(entries: &[i64], taxa: &[i32], ranks: &[Rank], rank_ancestor: F) -> Specificity
+where
+    F: Fn(i32, Rank) -> Option<i32>,
+{
+    let proteins = entries.iter().collect::<HashSet<_>>().len();
+
+    let rank_taxa = ranks
+        .iter()
+        .map(|&rank| unique_ancestor(taxa, |taxon| rank_ancestor(taxon, rank)))
+        .collect();
+
+    Specificity {
+        proteins,
+        proteome_unique: None,
+        rank_taxa,
+    }
+}
+
+/// Check whether a peptide occurs in exactly one proteome, given the proteomes of every entry
+/// it occurs in
+/// An entry without proteomes makes it impossible to tell, so the peptide is not
+/// proteome-unique in that case.
+pub fn proteome_unique<E, P: Eq + Hash>(entry_proteomes: &HashMap<E, Vec<P>>) -> bool {
+    let mut found: HashSet<&P> = HashSet::new();
+    for proteomes in entry_proteomes.values() {
+        if proteomes.is_empty() {
+            return false;
+        }
+        found.extend(proteomes);
+    }
+
+    found.len() == 1
+}
+
+/// Get the ancestor that all taxa share, or None if a taxon has no such ancestor or the
+/// ancestors differ
+fn unique_ancestor<F: Fn(i32) -> Option<i32>>(taxa: &[i32], ancestor: F) -> Option<i32> {
+    let mut result = None;
+
+    for &taxon in taxa {
+        let current = ancestor(taxon)?;
+        if result.is_some_and(|r| r != current) {
+            return None;
+        }
+        result = Some(current);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Two species (11 and 12) of genus 10, and species 21 of genus 20
+    fn ancestor(taxon: i32, rank: Rank) -> Option<i32> {
+        match (taxon, rank) {
+            (11 | 12 | 21, Rank::Species) => Some(taxon),
+            (11 | 12, Rank::Genus) => Some(10),
+            (21, Rank::Genus) => Some(20),
+            _ => None,
+        }
+    }
+
+    const RANKS: &[Rank] = &[Rank::Species, Rank::Genus];
+
+    #[test]
+    fn test_classify_ranks() {
+        let species = classify(&[1, 2], &[11, 11], RANKS, ancestor);
+        assert_eq!(species.rank_taxa, vec![Some(11), Some(10)]);
+        assert!(!species.protein_unique());
+        assert_eq!(species.proteome_unique, None);
+
+        let genus = classify(&[1, 2], &[11, 12], RANKS, ancestor);
+        assert_eq!(genus.rank_taxa, vec![None, Some(10)]);
+
+        let shared = classify(&[1, 2], &[11, 21], RANKS, ancestor);
+        assert_eq!(shared.rank_taxa, vec![None, None]);
+
+        // A taxon without ancestor at a rank makes the peptide not unique at that rank
+        let unknown = classify(&[1, 2], &[11, 5], RANKS, ancestor);
+        assert_eq!(unknown.rank_taxa, vec![None, None]);
+    }
+
+    #[test]
+    fn test_classify_proteins() {
+        let single = classify(&[1, 1], &[11, 11], RANKS, ancestor);
+        assert!(single.protein_unique());
+        assert_eq!(single.proteome_unique, None);
+
+        let multiple = classify(&[1, 2], &[11, 11], RANKS, ancestor);
+        assert!(!multiple.protein_unique());
+    }
+
+    #[test]
+    fn test_proteome_unique() {
+        let unique = |proteomes: &[(i64, Vec<u32>)]| {
+            proteome_unique(&proteomes.iter().cloned().collect::<HashMap<_, _>>())
+        };
+
+        assert!(unique(&[(1, vec![7]), (2, vec![7])]));
+        assert!(unique(&[(1, vec![7, 7])]));
+        assert!(!unique(&[(1, vec![7]), (3, vec![8])]));
+
+        // Every proteome of every entry counts, so adding an entry never makes a peptide unique
+        assert!(!unique(&[(1, vec![7, 8])]));
+        assert!(!unique(&[(1, vec![7, 8]), (2, vec![7])]));
+
+        // An entry without proteomes makes it impossible to tell
+        assert!(!unique(&[(1, vec![7]), (4, vec![])]));
+        assert!(!unique(&[]));
+    }
+}
@@ -0,0 +1,46 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use tables_generator::protein_text::validate_protein_text;
+use utils::{now_str, open_read};
+
+fn main() -> Result<()> {
+    let args = Cli::parse();
+
+    let entries =
+        open_read(&args.uniprot_entries).context("Unable to open UniProt entries file")?;
+    let text = open_read(&args.protein_text).context("Unable to open protein text")?;
+    let offsets =
+        open_read(&args.protein_offsets).context("Unable to open protein offsets file")?;
+
+    eprintln!("[{}] Validating protein text", now_str());
+    let proteins = validate_protein_text(entries, text, offsets, args.equate_il)
+        .context("Protein text does not match the UniProt entries")?;
+
+    eprintln!(
+        "[{}] Protein text and offsets match all {} UniProt entries",
+        now_str(),
+        proteins
+    );
+    Ok(())
+}
+
+#[derive(Parser, Debug)]
+struct Cli {
+    /// Path to the uniprot_entries.tsv file (generated by uniprot-parser)
+    #[clap(long)]
+    uniprot_entries: PathBuf,
+
+    /// Path to the protein text (generated by uniprot-parser)
+    #[clap(long)]
+    protein_text: PathBuf,
+
+    /// Path to the protein offsets file (generated by uniprot-parser)
+    #[clap(long)]
+    protein_offsets: PathBuf,
+
+    /// Whether isoleucines were replaced by leucines in the protein text
+    #[clap(long)]
+    equate_il: bool,
+}
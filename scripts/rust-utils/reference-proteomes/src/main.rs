@@ -0,0 +1,64 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use external_sort::parse_memory_size;
+use tables_generator::reference_proteomes::build_reference_proteomes;
+use utils::{now_str, open_write};
+
+fn main() -> Result<()> {
+    let args = Cli::parse();
+
+    eprintln!("[{}] Building reference proteomes table", now_str());
+    let unmatched = build_reference_proteomes(
+        &args.proteomes,
+        &args.reference_proteomes,
+        &args.output_file,
+        parse_memory_size(&args.sort_memory)?,
+        &args.temp_dir,
+    )?;
+
+    eprintln!(
+        "[{}] {} reference proteomes had no entries in this build",
+        now_str(),
+        unmatched.len()
+    );
+
+    if let Some(pb) = &args.unmatched_report {
+        let mut writer = open_write(pb).context("Unable to open unmatched report file")?;
+        for upid in &unmatched {
+            writeln!(&mut writer, "{}", upid).context("Error writing unmatched report")?;
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Parser, Debug)]
+struct Cli {
+    /// Path to the proteomes file (proteome and accession number) written by the uniprot-parser
+    #[clap(long)]
+    proteomes: PathBuf,
+
+    /// Path to the reference proteomes (upid, organism id and protein count)
+    #[clap(long)]
+    reference_proteomes: PathBuf,
+
+    /// Path to the proteomes table that is written, numbered in the order of the reference
+    /// proteomes
+    #[clap(long)]
+    output_file: PathBuf,
+
+    /// Path to a file in which the reference proteomes without any entries are listed
+    #[clap(long)]
+    unmatched_report: Option<PathBuf>,
+
+    /// Amount of memory the sorting step is allowed to use (e.g. "2G")
+    #[clap(long, default_value = "2G")]
+    sort_memory: String,
+
+    /// Directory in which temporary files of the sorting step are stored
+    #[clap(long, default_value = "/tmp")]
+    temp_dir: PathBuf,
+}
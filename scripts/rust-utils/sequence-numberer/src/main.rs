@@ -0,0 +1,397 @@
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::iter::Peekable;
+use std::path::PathBuf;
+
+use anyhow::{Context, Error, Result};
+use clap::Parser;
+use external_sort::{ExternalSorter, KeyOrder, SortedLines, key, parse_memory_size};
+use utils::{now_str, open_read, open_write};
+
+// Columns of the peptide table written by PeptideTableWriter
+const EQUALIZED_COLUMN: usize = 1;
+const ORIGINAL_COLUMN: usize = 2;
+// Only present if the peptides were written with stable ids
+const SEQUENCE_ID_COLUMN: usize = 6;
+
+fn main() -> Result<()> {
+    let args = Cli::parse();
+
+    let collisions = number_peptides(
+        &args.input_file,
+        &args.sequences,
+        &args.peptides_by_equalized,
+        &args.peptides_by_original,
+        parse_memory_size(&args.memory)?,
+        args.threads,
+        &args.temp_dir,
+    )?;
+    if collisions > 0 {
+        eprintln!(
+            "[{}] Found {} sequence ids that are shared by distinct sequences",
+            now_str(),
+            collisions
+        );
+    }
+
+    eprintln!("[{}] Finished numbering sequences", now_str());
+    Ok(())
+}
+
+/// Number all sequences of a peptide table and write the peptides with their sequences replaced
+/// by these numbers, sorted on the equalized and on the original sequence
+/// If the peptides have a sequence_id column (stable ids), every id that is derived from more
+/// than one original sequence is reported, and the amount of such ids is returned.
+fn number_peptides(
+    input_pb: &PathBuf,
+    sequences_pb: &PathBuf,
+    by_equalized_pb: &PathBuf,
+    by_original_pb: &PathBuf,
+    memory: usize,
+    threads: usize,
+    temp_dir: &PathBuf,
+) -> Result<u64> {
+    // The sorted lines of the first two sorters are consumed while the third one is filled, so
+    // all three of them share the memory budget, together with the sorter of the sequence ids
+    let share = memory / 4;
+    let mut by_equalized =
+        ExternalSorter::new(EQUALIZED_COLUMN, KeyOrder::Bytes, share, threads, temp_dir);
+    let mut originals = ExternalSorter::new(0, KeyOrder::Bytes, share, threads, temp_dir);
+    let mut sequence_ids = ExternalSorter::new(0, KeyOrder::Numeric, share, threads, temp_dir);
+
+    eprintln!("[{}] Reading peptides", now_str());
+    let reader = open_read(input_pb).context("Unable to open input file")?;
+    for line in reader.lines() {
+        let line = line.context("Error reading line from input file")?;
+        originals.push(key(&line, ORIGINAL_COLUMN))?;
+        if let Some(sequence_id) = line.split('\t').nth(SEQUENCE_ID_COLUMN) {
+            sequence_ids.push_with(|buffer| {
+                write!(buffer, "{}\t{}", sequence_id, key(&line, ORIGINAL_COLUMN))
+            })?;
+        }
+        by_equalized.push(&line)?;
+    }
+
+    eprintln!("[{}] Checking sequence ids", now_str());
+    let collisions = report_collisions(sequence_ids.finish()?)?;
+
+    eprintln!("[{}] Numbering sequences", now_str());
+    let mut by_original =
+        ExternalSorter::new(ORIGINAL_COLUMN, KeyOrder::Bytes, share, threads, temp_dir);
+    let dictionary = number_sequences(
+        by_equalized.finish()?,
+        originals.finish()?,
+        sequences_pb,
+        by_equalized_pb,
+        &mut by_original,
+        temp_dir,
+    )?;
+
+    eprintln!("[{}] Substituting original sequences", now_str());
+    substitute_originals(by_original.finish()?, dictionary, by_original_pb)?;
+
+    Ok(collisions)
+}
+
+/// Report every sequence id that was derived from more than one original sequence, and return
+/// the amount of such ids. The lines (sequence id and original sequence) have to be sorted on id.
+fn report_collisions(lines: SortedLines) -> Result<u64> {
+    let mut collisions = 0;
+    let mut current: Option<String> = None;
+    let mut sequences: Vec<String> = Vec::new();
+
+    let mut report = |id: &str, sequences: &[String]| {
+        if sequences.len() > 1 {
+            eprintln!(
+                "[{}] Sequence id {} is shared by {}",
+                now_str(),
+                id,
+                sequences.join(", ")
+            );
+            collisions += 1;
+        }
+    };
+
+    for line in lines {
+        let line = line?;
+        let (id, sequence) = line
+            .split_once('\t')
+            .context("Unable to split sequence id line on tabs")?;
+
+        if current.as_deref() != Some(id) {
+            if let Some(current) = &current {
+                report(current, &sequences);
+            }
+            current = Some(id.to_string());
+            sequences.clear();
+        }
+        if !sequences.iter().any(|s| s == sequence) {
+            sequences.push(sequence.to_string());
+        }
+    }
+    if let Some(current) = &current {
+        report(current, &sequences);
+    }
+
+    Ok(collisions)
+}
+
+/// Build the combined dictionary of equalized and original sequences, and replace the equalized
+/// sequence of every peptide by its id. Both inputs have to be sorted on their sequence.
+///
+/// Every substituted peptide is also passed to `by_original`, so the original sequences can be
+/// substituted afterward. A copy of the dictionary is kept in a temporary file for that purpose,
+/// which is returned (rewound to its start).
+fn number_sequences(
+    peptides: SortedLines,
+    originals: SortedLines,
+    sequences_pb: &PathBuf,
+    peptides_pb: &PathBuf,
+    by_original: &mut ExternalSorter,
+    temp_dir: &PathBuf,
+) -> Result<BufReader<std::fs::File>> {
+    let mut peptides = peptides.peekable();
+    let mut originals = originals.peekable();
+
+    let mut sequences = open_write(sequences_pb).context("Unable to open sequences output file")?;
+    let mut substituted = open_write(peptides_pb).context("Unable to open peptides output file")?;
+    let mut dictionary = BufWriter::new(
+        tempfile::tempfile_in(temp_dir).context("Unable to create temporary dictionary file")?,
+    );
+
+    let mut id: u64 = 0;
+
+    loop {
+        let next_peptide = peek_key(&mut peptides, EQUALIZED_COLUMN)?;
+        let next_original = peek_key(&mut originals, 0)?;
+
+        let sequence = match (next_peptide, next_original) {
+            (None, None) => break,
+            (Some(p), None) => p,
+            (None, Some(o)) => o,
+            (Some(p), Some(o)) => {
+                if p.as_bytes() <= o.as_bytes() {
+                    p
+                } else {
+                    o
+                }
+            }
+        };
+
+        id += 1;
+        writeln!(&mut sequences, "{}\t{}", id, sequence)
+            .context("Error writing to sequences output file")?;
+        writeln!(&mut dictionary, "{}\t{}", id, sequence)
+            .context("Error writing to temporary dictionary file")?;
+
+        while peek_key(&mut peptides, EQUALIZED_COLUMN)?.as_deref() == Some(sequence.as_str()) {
+            // Safe to unwrap, we just peeked a line
+            let line = peptides.next().unwrap()?;
+            let line = replace_column(&line, EQUALIZED_COLUMN, &id.to_string());
+
+            writeln!(&mut substituted, "{}", line)
+                .context("Error writing to peptides output file")?;
+            by_original.push(&line)?;
+        }
+
+        while peek_key(&mut originals, 0)?.as_deref() == Some(sequence.as_str()) {
+            originals.next();
+        }
+    }
+
+    let mut dictionary = dictionary
+        .into_inner()
+        .context("Error flushing temporary dictionary file")?;
+    dictionary
+        .seek(SeekFrom::Start(0))
+        .context("Error rewinding temporary dictionary file")?;
+
+    Ok(BufReader::new(dictionary))
+}
+
+/// Replace the original sequence of every peptide by its id. Both the peptides and the
+/// dictionary (lines of id and sequence) have to be sorted on their sequence.
+fn substitute_originals(
+    peptides: SortedLines,
+    dictionary: BufReader<std::fs::File>,
+    peptides_pb: &PathBuf,
+) -> Result<()> {
+    let mut substituted = open_write(peptides_pb).context("Unable to open peptides output file")?;
+    let mut dictionary = dictionary.lines();
+    let mut current: Option<(String, String)> = None;
+
+    for line in peptides {
+        let line = line?;
+        let sequence = key(&line, ORIGINAL_COLUMN);
+
+        while current.as_ref().map(|(_, s)| s.as_str()) != Some(sequence) {
+            let entry = dictionary
+                .next()
+                .with_context(|| format!("Sequence {} is missing from the dictionary", sequence))?
+                .context("Error reading temporary dictionary file")?;
+            let (id, dictionary_sequence) = entry
+                .split_once('\t')
+                .context("Unable to split dictionary line on tabs")?;
+
+            if dictionary_sequence.as_bytes() > sequence.as_bytes() {
+                return Err(Error::msg(format!(
+                    "Sequence {} is missing from the dictionary",
+                    sequence
+                )));
+            }
+
+            current = Some((id.to_string(), dictionary_sequence.to_string()));
+        }
+
+        // Safe to unwrap, the loop above only stops once current is set
+        let (id, _) = current.as_ref().unwrap();
+        writeln!(
+            &mut substituted,
+            "{}",
+            replace_column(&line, ORIGINAL_COLUMN, id)
+        )
+        .context("Error writing to peptides output file")?;
+    }
+
+    Ok(())
+}
+
+/// Get the key of the next line of a sorted stream, without consuming it
+fn peek_key(lines: &mut Peekable<SortedLines>, column: usize) -> Result<Option<String>> {
+    match lines.peek() {
+        None => Ok(None),
+        Some(Ok(line)) => Ok(Some(key(line, column).to_string())),
+        Some(Err(_)) => Err(lines.next().unwrap().unwrap_err()),
+    }
+}
+
+fn replace_column(line: &str, column: usize, value: &str) -> String {
+    line.split('\t')
+        .enumerate()
+        .map(|(i, v)| if i == column { value } else { v })
+        .collect::<Vec<&str>>()
+        .join("\t")
+}
+
+#[derive(Parser, Debug)]
+struct Cli {
+    /// Path to the peptides file (generated by uniprot-parser-tryptic)
+    #[clap(long)]
+    input_file: PathBuf,
+
+    /// Path to the output file with the numbered original and equalized sequences
+    #[clap(long)]
+    sequences: PathBuf,
+
+    /// Path to the output file in which the equalized sequences are replaced by their id,
+    /// sorted on that id
+    #[clap(long)]
+    peptides_by_equalized: PathBuf,
+
+    /// Path to the output file in which both sequences are replaced by their id,
+    /// sorted on the id of the original sequence
+    #[clap(long)]
+    peptides_by_original: PathBuf,
+
+    /// Amount of memory the sorting steps are allowed to use (e.g. "2G")
+    #[clap(long, default_value = "2G")]
+    memory: String,
+
+    /// Directory in which temporary files are stored
+    #[clap(long, default_value = "/tmp")]
+    temp_dir: PathBuf,
+
+    /// Number of threads used to sort chunks of the input while it is being read
+    #[clap(long, default_value_t = 1)]
+    threads: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // id, equalized sequence, original sequence, entry id, functional annotations and taxon id
+    const PEPTIDES: &str = "\
+1\tAALK\tAAIK\t1\t\t562
+2\tPEPTLDEK\tPEPTIDEK\t1\t\t562
+3\tAALK\tAALK\t2\t\t9606
+4\tPEPTLDEK\tPEPTLDEK\t3\t\t9606
+5\tAALK\tAAIK\t3\t\t9606
+";
+
+    fn number(memory: usize) -> (String, String, String) {
+        let dir = tempfile::tempdir().unwrap();
+        let pb = |name: &str| dir.path().join(name);
+        std::fs::write(pb("peptides.tsv"), PEPTIDES).unwrap();
+
+        number_peptides(
+            &pb("peptides.tsv"),
+            &pb("sequences.tsv"),
+            &pb("by_equalized.tsv"),
+            &pb("by_original.tsv"),
+            memory,
+            2,
+            &dir.path().to_path_buf(),
+        )
+        .unwrap();
+
+        let read = |name: &str| std::fs::read_to_string(pb(name)).unwrap();
+        (
+            read("sequences.tsv"),
+            read("by_equalized.tsv"),
+            read("by_original.tsv"),
+        )
+    }
+
+    #[test]
+    fn test_number_peptides() {
+        let (sequences, by_equalized, by_original) = number(1 << 20);
+
+        // Original and equalized sequences are numbered together, in sorted order
+        assert_eq!(sequences, "1\tAAIK\n2\tAALK\n3\tPEPTIDEK\n4\tPEPTLDEK\n");
+        assert_eq!(
+            by_equalized,
+            "\
+1\t2\tAAIK\t1\t\t562
+3\t2\tAALK\t2\t\t9606
+5\t2\tAAIK\t3\t\t9606
+2\t4\tPEPTIDEK\t1\t\t562
+4\t4\tPEPTLDEK\t3\t\t9606
+"
+        );
+        assert_eq!(
+            by_original,
+            "\
+1\t2\t1\t1\t\t562
+5\t2\t1\t3\t\t9606
+3\t2\t2\t2\t\t9606
+2\t4\t3\t1\t\t562
+4\t4\t4\t3\t\t9606
+"
+        );
+    }
+
+    #[test]
+    fn test_number_peptides_with_runs() {
+        // A tiny memory budget makes every sorter spill to temporary files
+        assert_eq!(number(64), number(1 << 20));
+    }
+
+    #[test]
+    fn test_report_collisions() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sorter = ExternalSorter::new(0, KeyOrder::Numeric, 1 << 20, 1, dir.path());
+        // Id 7 is derived from two distinct sequences, 5 and 12 from a single one
+        for line in [
+            "7\tAAIK",
+            "12\tPEPTIDEK",
+            "5\tAALK",
+            "7\tAAIK",
+            "7\tPEPTLDEK",
+        ] {
+            sorter.push(line).unwrap();
+        }
+
+        assert_eq!(report_collisions(sorter.finish().unwrap()).unwrap(), 1);
+    }
+}
@@ -0,0 +1,261 @@
+use std::io::{BufRead, Lines, Write};
+use std::path::PathBuf;
+
+use anyhow::{Context, Error, Result};
+use clap::Parser;
+use utils::{now_str, open_read, open_write};
+
+const NULL_STRING: &str = "\\N";
+
+fn main() -> Result<()> {
+    let args = Cli::parse();
+
+    let sequences = SortedInput::open("sequences", &args.sequences)?;
+    let columns = [
+        SortedInput::open("original LCAs", &args.lcas_original)?,
+        SortedInput::open("equalized LCAs", &args.lcas_equalized)?,
+        SortedInput::open("original FAs", &args.fas_original)?,
+        SortedInput::open("equalized FAs", &args.fas_equalized)?,
+    ];
+
+    let writer = open_write(&args.output_file).context("Unable to open output file")?;
+
+    eprintln!("[{}] Building sequences table", now_str());
+    let count = build_table(sequences, columns, writer)?;
+
+    eprintln!("[{}] Wrote {} sequences", now_str(), count);
+    Ok(())
+}
+
+/// Left-join the columns onto the sequences and write the resulting rows
+/// Returns the amount of rows that were written.
+fn build_table<W: Write, const N: usize>(
+    mut sequences: SortedInput,
+    mut columns: [SortedInput; N],
+    mut writer: W,
+) -> Result<u64> {
+    let mut count: u64 = 0;
+    while let Some((id, sequence)) = sequences.next_record()? {
+        write!(&mut writer, "{}\t{}", id, sequence).context("Error writing to output file")?;
+
+        for column in columns.iter_mut() {
+            let value = column.value_for(id)?;
+            write!(&mut writer, "\t{}", value.as_deref().unwrap_or(NULL_STRING))
+                .context("Error writing to output file")?;
+        }
+
+        writeln!(&mut writer).context("Error writing to output file")?;
+        count += 1;
+    }
+
+    writer.flush().context("Error flushing output file")?;
+
+    for column in columns.iter_mut() {
+        column.check_exhausted()?;
+    }
+
+    Ok(count)
+}
+
+/// A tab-separated file of which the first column is a numeric id, sorted in ascending order
+/// without duplicates
+struct SortedInput {
+    name: &'static str,
+    lines: Lines<Box<dyn BufRead + Send>>,
+    last_id: Option<u64>,
+    current: Option<(u64, String)>,
+    skipped: u64,
+}
+
+impl SortedInput {
+    fn open(name: &'static str, pb: &PathBuf) -> Result<Self> {
+        let reader = open_read(pb)
+            .with_context(|| format!("Unable to open {} file {}", name, pb.display()))?;
+
+        Self::new(name, Box::new(reader))
+    }
+
+    fn new(name: &'static str, reader: Box<dyn BufRead + Send>) -> Result<Self> {
+        let mut input = SortedInput {
+            name,
+            lines: reader.lines(),
+            last_id: None,
+            current: None,
+            skipped: 0,
+        };
+        input.advance()?;
+
+        Ok(input)
+    }
+
+    /// Read the next record into `current`, verifying that the ids are strictly increasing
+    /// Empty lines are ignored
+    fn advance(&mut self) -> Result<()> {
+        self.current = None;
+
+        for line in self.lines.by_ref() {
+            let line = line.with_context(|| format!("Error reading {} file", self.name))?;
+            if line.is_empty() {
+                continue;
+            }
+
+            let (id, value) = line.split_once('\t').unwrap_or((&line, ""));
+            let id: u64 = id
+                .parse()
+                .with_context(|| format!("Unable to parse id {} in {} file", id, self.name))?;
+
+            if let Some(last_id) = self.last_id
+                && id <= last_id
+            {
+                let problem = if id == last_id {
+                    "contains a duplicate of"
+                } else {
+                    "is not sorted at"
+                };
+                return Err(Error::msg(format!(
+                    "The {} file {} id {}",
+                    self.name, problem, id
+                )));
+            }
+
+            self.last_id = Some(id);
+            self.current = Some((id, value.to_string()));
+            break;
+        }
+
+        Ok(())
+    }
+
+    fn next_record(&mut self) -> Result<Option<(u64, String)>> {
+        let record = self.current.take();
+        if record.is_some() {
+            self.advance()?;
+        }
+
+        Ok(record)
+    }
+
+    /// Get the value for the given id, if this input has one
+    /// Ids have to be requested in ascending order. Records with ids that are never requested
+    /// are skipped, like an unpaired line in a left join.
+    fn value_for(&mut self, id: u64) -> Result<Option<String>> {
+        while let Some((current_id, _)) = self.current
+            && current_id < id
+        {
+            self.skipped += 1;
+            self.advance()?;
+        }
+
+        match &self.current {
+            Some((current_id, _)) if *current_id == id => {
+                self.next_record().map(|r| r.map(|(_, v)| v))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Skip the remaining records, and report how many records did not match a sequence
+    fn check_exhausted(&mut self) -> Result<()> {
+        while self.current.is_some() {
+            self.skipped += 1;
+            self.advance()?;
+        }
+
+        if self.skipped > 0 {
+            eprintln!(
+                "[{}] Warning: {} records of the {} file did not match any sequence",
+                now_str(),
+                self.skipped,
+                self.name
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug)]
+struct Cli {
+    /// Path to the numbered sequences (id and sequence), sorted on id
+    #[clap(long)]
+    sequences: PathBuf,
+
+    /// Path to the LCAs of the original sequences, sorted on sequence id
+    #[clap(long)]
+    lcas_original: PathBuf,
+
+    /// Path to the LCAs of the equalized sequences, sorted on sequence id
+    #[clap(long)]
+    lcas_equalized: PathBuf,
+
+    /// Path to the functional annotations of the original sequences, sorted on sequence id
+    #[clap(long)]
+    fas_original: PathBuf,
+
+    /// Path to the functional annotations of the equalized sequences, sorted on sequence id
+    #[clap(long)]
+    fas_equalized: PathBuf,
+
+    /// Path to the sequences table that is written
+    #[clap(long)]
+    output_file: PathBuf,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(name: &'static str, content: &'static str) -> SortedInput {
+        SortedInput::new(name, Box::new(content.as_bytes())).unwrap()
+    }
+
+    fn build(columns: [&'static str; 2]) -> Result<String> {
+        let mut output = Vec::new();
+        build_table(
+            input("sequences", "1\tAAIK\n2\tAALK\n10\tPEPTIDEK\n"),
+            [input("LCAs", columns[0]), input("FAs", columns[1])],
+            &mut output,
+        )?;
+
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_build_table() {
+        // Ids are compared as numbers, and records without a sequence are skipped
+        let table = build(["1\t562\n10\t2\n11\t1\n", "\n2\t{}\n"]).unwrap();
+        assert_eq!(
+            table,
+            "1\tAAIK\t562\t\\N\n2\tAALK\t\\N\t{}\n10\tPEPTIDEK\t2\t\\N\n"
+        );
+    }
+
+    #[test]
+    fn test_unsorted_input() {
+        let error = build(["2\t562\n1\t2\n", ""]).unwrap_err();
+        assert_eq!(error.to_string(), "The LCAs file is not sorted at id 1");
+
+        // Ids are not sorted as text
+        let error = build(["", "10\t{}\n9\t{}\n"]).unwrap_err();
+        assert_eq!(error.to_string(), "The FAs file is not sorted at id 9");
+    }
+
+    #[test]
+    fn test_duplicate_ids() {
+        let error = build(["1\t562\n1\t2\n", ""]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The LCAs file contains a duplicate of id 1"
+        );
+
+        // Duplicates are also detected in records that are skipped
+        let error = build(["", "3\t{}\n3\t{}\n"]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The FAs file contains a duplicate of id 3"
+        );
+
+        let mut sequences = input("sequences", "1\tAAIK\n1\tAALK\n");
+        assert!(sequences.next_record().is_err());
+    }
+}
@@ -0,0 +1,130 @@
+use rand::SeedableRng;
+use rand::seq::SliceRandom;
+use rand_chacha::ChaCha8Rng;
+use strum_macros::{Display, EnumString};
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::models::Entry;
+
+/// Prefix of the accession numbers of decoy entries
+pub const DECOY_PREFIX: &str = "DECOY_";
+
+/// How the sequence of a decoy protein is derived from the sequence of its target protein
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Display, EnumString)]
+pub enum DecoyStrategy {
+    /// Reverse the whole sequence
+    #[strum(serialize = "reverse")]
+    Reverse,
+    /// Reverse every tryptic peptide, but keep the K/R cleavage sites in place, so the decoy
+    /// peptides have the same masses and lengths as the target peptides
+    #[strum(serialize = "pseudo-reverse")]
+    PseudoReverse,
+    /// Shuffle the sequence, with a seed that only depends on the given seed and the accession
+    /// number, so the decoys are the same in every run
+    #[strum(serialize = "shuffle")]
+    Shuffle,
+}
+
+/// Create the decoy of an entry: a copy with a prefixed accession number, a decoy sequence and
+/// without functional annotations
+pub fn decoy_entry(entry: &Entry, strategy: DecoyStrategy, seed: u64) -> Entry {
+    Entry {
+        accession_number: format!("{}{}", DECOY_PREFIX, entry.accession_number),
+        version: entry.version.clone(),
+        taxon_id: entry.taxon_id,
+        type_: entry.type_.clone(),
+        name: entry.name.clone(),
+        sequence: decoy_sequence(&entry.sequence, &entry.accession_number, strategy, seed),
+        ec_references: Vec::new(),
+        go_references: Vec::new(),
+        ip_references: Vec::new(),
+        proteome_references: Vec::new(),
+    }
+}
+
+/// Derive a decoy sequence from the sequence of the target protein with the given accession
+pub fn decoy_sequence(
+    sequence: &str,
+    accession_number: &str,
+    strategy: DecoyStrategy,
+    seed: u64,
+) -> String {
+    let mut residues = sequence.as_bytes().to_vec();
+
+    match strategy {
+        DecoyStrategy::Reverse => residues.reverse(),
+        DecoyStrategy::PseudoReverse => pseudo_reverse(&mut residues),
+        DecoyStrategy::Shuffle => {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed ^ xxh3_64(accession_number.as_bytes()));
+            residues.shuffle(&mut rng);
+        }
+    }
+
+    String::from_utf8_lossy(&residues).into_owned()
+}
+
+/// Reverse every tryptic peptide in place, except for the K or R at which it is cleaved
+/// Cleavage follows the same rules as `calculate_entry_digest`.
+fn pseudo_reverse(residues: &mut [u8]) {
+    let length = residues.len();
+    let mut start = 0;
+
+    for i in 0..length {
+        let c = residues[i];
+        if (c == b'K' || c == b'R') && (i + 1 < length && residues[i + 1] != b'P') {
+            residues[start..i].reverse();
+            start = i + 1;
+        }
+    }
+
+    // The last peptide is not followed by a cleavage site
+    if start < length {
+        let end = match residues[length - 1] {
+            b'K' | b'R' => length - 1,
+            _ => length,
+        };
+        residues[start..end].reverse();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reverse() {
+        assert_eq!(
+            decoy_sequence("MAKPEPTIDER", "P1", DecoyStrategy::Reverse, 0),
+            "REDITPEPKAM"
+        );
+    }
+
+    #[test]
+    fn test_pseudo_reverse() {
+        // The KP bond is not cleaved, so MAKPEPTIDE is reversed as a whole, keeping the final R
+        assert_eq!(
+            decoy_sequence("MAKPEPTIDERGGK", "P1", DecoyStrategy::PseudoReverse, 0),
+            "EDITPEPKAMRGGK"
+        );
+        assert_eq!(
+            decoy_sequence("MAKGGA", "P1", DecoyStrategy::PseudoReverse, 0),
+            "AMKAGG"
+        );
+    }
+
+    #[test]
+    fn test_shuffle() {
+        let first = decoy_sequence("MAKPEPTIDERGGK", "P1", DecoyStrategy::Shuffle, 42);
+        let again = decoy_sequence("MAKPEPTIDERGGK", "P1", DecoyStrategy::Shuffle, 42);
+        let other_seed = decoy_sequence("MAKPEPTIDERGGK", "P1", DecoyStrategy::Shuffle, 43);
+
+        assert_eq!(first, again);
+        assert_ne!(first, other_seed);
+
+        let mut sorted: Vec<u8> = first.into_bytes();
+        sorted.sort();
+        let mut expected: Vec<u8> = b"MAKPEPTIDERGGK".to_vec();
+        expected.sort();
+        assert_eq!(sorted, expected);
+    }
+}
@@ -0,0 +1,106 @@
+use anyhow::{Error, Result};
+use strum_macros::{Display, EnumString};
+use xxhash_rust::xxh3::xxh3_64;
+
+/// Longest accession number that can be encoded into an i64 without collisions
+const MAX_ACCESSION_LENGTH: usize = 10;
+
+/// How the id columns of the generated tables are filled in
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Display, EnumString)]
+pub enum IdStrategy {
+    /// Number rows in the order in which they are written
+    #[strum(serialize = "counter")]
+    Counter,
+    /// Derive ids from the content of a row, so they remain the same across builds
+    #[strum(serialize = "stable")]
+    Stable,
+}
+
+/// Derive an id from a UniProtKB accession number
+/// Every character is treated as a digit in base 37 (0 means "no character"), so that all
+/// accession numbers of at most 10 alphanumeric characters map onto a distinct positive id
+pub fn accession_id(accession_number: &str) -> Result<i64> {
+    if accession_number.is_empty() || accession_number.len() > MAX_ACCESSION_LENGTH {
+        return Err(Error::msg(format!(
+            "Unable to derive an id from accession number {}",
+            accession_number
+        )));
+    }
+
+    let mut id: i64 = 0;
+    for c in accession_number.bytes() {
+        let digit = match c {
+            b'0'..=b'9' => c - b'0' + 1,
+            b'A'..=b'Z' => c - b'A' + 11,
+            _ => {
+                return Err(Error::msg(format!(
+                    "Invalid character in accession number {}",
+                    accession_number
+                )));
+            }
+        };
+
+        id = id * 37 + digit as i64;
+    }
+
+    Ok(id)
+}
+
+/// Derive an id from a protein or peptide sequence
+/// This is the 64-bit XXH3 hash of the sequence, with the sign bit cleared. Distinct sequences
+/// can share an id (for 10^9 sequences the odds are a few percent), so it is never used as the
+/// key of a row.
+pub fn sequence_id(sequence: &[u8]) -> i64 {
+    (xxh3_64(sequence) & i64::MAX as u64) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accession_id() {
+        assert_eq!(accession_id("0").unwrap(), 1);
+        assert_eq!(accession_id("A").unwrap(), 11);
+        assert_eq!(accession_id("A0").unwrap(), 11 * 37 + 1);
+
+        // The longest accession numbers still fit into a positive i64
+        assert!(accession_id("ZZZZZZZZZZ").unwrap() > 0);
+        assert!(accession_id("A0A023GPI8").unwrap() > 0);
+    }
+
+    #[test]
+    fn test_accession_id_is_unique() {
+        let accessions = [
+            "P12345",
+            "P12346",
+            "Q12345",
+            "P1234",
+            "0P1234",
+            "A0A023GPI8",
+        ];
+        let mut ids: Vec<i64> = accessions
+            .iter()
+            .map(|a| accession_id(a).unwrap())
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), accessions.len());
+    }
+
+    #[test]
+    fn test_accession_id_invalid() {
+        assert!(accession_id("").is_err());
+        assert!(accession_id("A0A023GPI8X").is_err());
+        assert!(accession_id("p12345").is_err());
+        assert!(accession_id("P12345-2").is_err());
+    }
+
+    #[test]
+    fn test_sequence_id() {
+        assert_eq!(sequence_id(b"MPEPTIDEK"), sequence_id(b"MPEPTIDEK"));
+        assert_ne!(sequence_id(b"MPEPTIDEK"), sequence_id(b"MPEPTLDEK"));
+        assert!(sequence_id(b"MPEPTIDEK") >= 0);
+        assert!(sequence_id(b"") >= 0);
+    }
+}
@@ -1,3 +1,10 @@
+pub mod decoys;
+pub mod identifiers;
 pub mod models;
+pub mod protein_stats;
+pub mod protein_text;
+pub mod reference_proteomes;
+pub mod sharding;
+pub mod table_format;
 pub mod table_writer;
 pub mod taxon_list;
@@ -1,5 +1,10 @@
 use anyhow::{Context, Result};
 
+use crate::identifiers::IdStrategy;
+use crate::protein_stats::ProteinStats;
+use crate::table_format::{Column, ColumnType, Row, Value};
+use functional_annotation::FunctionalAnnotation;
+
 #[derive(Debug)]
 pub struct Entry {
     // The "version" and "accession_number" fields are actually integers, but they are never used as such,
@@ -49,6 +54,190 @@ impl Entry {
             proteome_references,
         })
     }
+
+    /// The EC numbers, GO terms and InterPro entries of this entry
+    /// References with an invalid id are returned as an error
+    pub fn functional_annotations(
+        &self,
+    ) -> impl Iterator<Item = Result<FunctionalAnnotation>> + '_ {
+        let ec = self
+            .ec_references
+            .iter()
+            .filter(|x| !x.is_empty())
+            .map(|x| FunctionalAnnotation::ec(x));
+        let go = self
+            .go_references
+            .iter()
+            .map(|x| FunctionalAnnotation::go(x));
+        let ip = self
+            .ip_references
+            .iter()
+            .filter(|x| !x.is_empty())
+            .map(|x| FunctionalAnnotation::interpro(x));
+
+        ec.chain(go).chain(ip)
+    }
+}
+
+/// A row of the uniprot_entries table
+pub struct EntryRow<'a> {
+    pub id: i64,
+    pub accession_number: &'a str,
+    pub version: &'a str,
+    pub taxon_id: i32,
+    pub type_: &'a str,
+    pub name: &'a str,
+    pub sequence: &'a str,
+    pub fa: &'a str,
+}
+
+impl EntryRow<'_> {
+    pub const COLUMNS: &'static [Column] = &[
+        Column::new("id", ColumnType::Int64),
+        Column::new("uniprot_accession_number", ColumnType::Utf8),
+        Column::new("version", ColumnType::Utf8),
+        Column::new("taxon_id", ColumnType::Int32),
+        Column::new("type", ColumnType::Utf8),
+        Column::new("name", ColumnType::Utf8),
+        Column::new("sequence", ColumnType::Utf8),
+        Column::new("fa", ColumnType::Utf8),
+    ];
+}
+
+impl Row for EntryRow<'_> {
+    fn value(&self, column: usize) -> Value<'_> {
+        match column {
+            0 => Value::Int64(self.id),
+            1 => Value::Str(self.accession_number),
+            2 => Value::Str(self.version),
+            3 => Value::Int32(self.taxon_id),
+            4 => Value::Str(self.type_),
+            5 => Value::Str(self.name),
+            6 => Value::Str(self.sequence),
+            _ => Value::Str(self.fa),
+        }
+    }
+}
+
+/// A row of the peptides table
+pub struct PeptideRow<'a> {
+    pub id: i64,
+    pub equalized_sequence: &'a str,
+    pub original_sequence: &'a str,
+    pub entry_id: i64,
+    pub fa: &'a str,
+    pub taxon_id: i32,
+    /// Id derived from the original sequence with `sequence_id`, only written if ids are stable
+    pub sequence_id: i64,
+}
+
+impl PeptideRow<'_> {
+    pub const COLUMNS: &'static [Column] = &[
+        Column::new("id", ColumnType::Int64),
+        Column::new("equalized_sequence", ColumnType::Utf8),
+        Column::new("original_sequence", ColumnType::Utf8),
+        Column::new("uniprot_entry_id", ColumnType::Int64),
+        Column::new("fa", ColumnType::Utf8),
+        Column::new("taxon_id", ColumnType::Int32),
+        Column::new("sequence_id", ColumnType::Int64),
+    ];
+
+    /// The columns that are written with the given id strategy: the sequence_id column is left
+    /// out unless ids are stable, so the layout of the table does not change otherwise
+    pub fn columns(id_strategy: IdStrategy) -> &'static [Column] {
+        match id_strategy {
+            IdStrategy::Counter => &Self::COLUMNS[..Self::COLUMNS.len() - 1],
+            IdStrategy::Stable => Self::COLUMNS,
+        }
+    }
+}
+
+impl Row for PeptideRow<'_> {
+    fn value(&self, column: usize) -> Value<'_> {
+        match column {
+            0 => Value::Int64(self.id),
+            1 => Value::Str(self.equalized_sequence),
+            2 => Value::Str(self.original_sequence),
+            3 => Value::Int64(self.entry_id),
+            4 => Value::Str(self.fa),
+            5 => Value::Int32(self.taxon_id),
+            _ => Value::Int64(self.sequence_id),
+        }
+    }
+}
+
+/// A row of the (unprocessed) proteomes table, linking a proteome to one of its entries
+pub struct ProteomeRow<'a> {
+    pub proteome: &'a str,
+    pub accession_number: &'a str,
+}
+
+impl ProteomeRow<'_> {
+    pub const COLUMNS: &'static [Column] = &[
+        Column::new("proteome", ColumnType::Utf8),
+        Column::new("uniprot_accession_number", ColumnType::Utf8),
+    ];
+}
+
+impl Row for ProteomeRow<'_> {
+    fn value(&self, column: usize) -> Value<'_> {
+        match column {
+            0 => Value::Str(self.proteome),
+            _ => Value::Str(self.accession_number),
+        }
+    }
+}
+
+/// A row of the protein statistics table
+pub struct ProteinStatsRow<'a> {
+    pub entry_id: i64,
+    pub stats: &'a ProteinStats,
+}
+
+impl ProteinStatsRow<'_> {
+    pub const COLUMNS: &'static [Column] = &[
+        Column::new("uniprot_entry_id", ColumnType::Int64),
+        Column::new("length", ColumnType::Int32),
+        Column::new("monoisotopic_mass", ColumnType::Float64),
+        Column::new("isoelectric_point", ColumnType::Float64),
+        Column::new("tryptic_peptides", ColumnType::Int32),
+        Column::new("non_standard_fraction", ColumnType::Float64),
+        Column::new("count_a", ColumnType::Int32),
+        Column::new("count_c", ColumnType::Int32),
+        Column::new("count_d", ColumnType::Int32),
+        Column::new("count_e", ColumnType::Int32),
+        Column::new("count_f", ColumnType::Int32),
+        Column::new("count_g", ColumnType::Int32),
+        Column::new("count_h", ColumnType::Int32),
+        Column::new("count_i", ColumnType::Int32),
+        Column::new("count_k", ColumnType::Int32),
+        Column::new("count_l", ColumnType::Int32),
+        Column::new("count_m", ColumnType::Int32),
+        Column::new("count_n", ColumnType::Int32),
+        Column::new("count_p", ColumnType::Int32),
+        Column::new("count_q", ColumnType::Int32),
+        Column::new("count_r", ColumnType::Int32),
+        Column::new("count_s", ColumnType::Int32),
+        Column::new("count_t", ColumnType::Int32),
+        Column::new("count_v", ColumnType::Int32),
+        Column::new("count_w", ColumnType::Int32),
+        Column::new("count_y", ColumnType::Int32),
+    ];
+}
+
+impl Row for ProteinStatsRow<'_> {
+    fn value(&self, column: usize) -> Value<'_> {
+        match column {
+            0 => Value::Int64(self.entry_id),
+            1 => Value::Int32(self.stats.length as i32),
+            2 => Value::Float64(self.stats.monoisotopic_mass),
+            3 => Value::Float64(self.stats.isoelectric_point),
+            4 => Value::Int32(self.stats.tryptic_peptides as i32),
+            5 => Value::Float64(self.stats.non_standard_fraction),
+            // The remaining columns hold the composition, in the order of STANDARD_RESIDUES
+            i => Value::Int32(self.stats.composition[i - 6] as i32),
+        }
+    }
 }
 
 pub fn calculate_entry_digest(
@@ -0,0 +1,184 @@
+use crate::models::calculate_entry_digest;
+
+/// The 20 standard amino acids, in the order in which their counts are stored
+pub const STANDARD_RESIDUES: &[u8; 20] = b"ACDEFGHIKLMNPQRSTVWY";
+
+/// Monoisotopic mass of a water molecule, which is added once for the termini of a protein
+const WATER_MASS: f64 = 18.010565;
+
+/// pKa values of the termini and the ionizable side chains (EMBOSS)
+const PKA_N_TERMINUS: f64 = 8.6;
+const PKA_C_TERMINUS: f64 = 3.6;
+const PKA_K: f64 = 10.8;
+const PKA_R: f64 = 12.5;
+const PKA_H: f64 = 6.5;
+const PKA_D: f64 = 3.9;
+const PKA_E: f64 = 4.1;
+const PKA_C: f64 = 8.5;
+const PKA_Y: f64 = 10.1;
+
+/// Sequence-level properties of a single protein
+#[derive(Debug, PartialEq)]
+pub struct ProteinStats {
+    pub length: usize,
+    /// Monoisotopic mass in Dalton, ignoring residues with an unknown mass
+    pub monoisotopic_mass: f64,
+    pub isoelectric_point: f64,
+    /// Amount of tryptic peptides of which the length lies within the configured range
+    pub tryptic_peptides: usize,
+    /// Fraction of the residues that are not one of the 20 standard amino acids
+    pub non_standard_fraction: f64,
+    /// Amount of every standard amino acid, in the order of `STANDARD_RESIDUES`
+    pub composition: [u32; 20],
+}
+
+impl ProteinStats {
+    pub fn calculate(sequence: &String, min_length: usize, max_length: usize) -> Self {
+        let mut composition = [0u32; 20];
+        let mut non_standard = 0;
+        let mut mass = 0.0;
+
+        for &residue in sequence.as_bytes() {
+            match STANDARD_RESIDUES.iter().position(|&r| r == residue) {
+                Some(index) => composition[index] += 1,
+                None => non_standard += 1,
+            }
+            mass += residue_mass(residue).unwrap_or(0.0);
+        }
+
+        let length = sequence.len();
+        let non_standard_fraction = if length == 0 {
+            0.0
+        } else {
+            non_standard as f64 / length as f64
+        };
+
+        ProteinStats {
+            length,
+            monoisotopic_mass: round(if length == 0 { 0.0 } else { mass + WATER_MASS }, 4),
+            isoelectric_point: round(isoelectric_point(&composition), 2),
+            tryptic_peptides: calculate_entry_digest(sequence, min_length, max_length).len(),
+            non_standard_fraction: round(non_standard_fraction, 4),
+            composition,
+        }
+    }
+
+    /// Amount of the given standard amino acid in the protein
+    pub fn count(&self, residue: u8) -> u32 {
+        STANDARD_RESIDUES
+            .iter()
+            .position(|&r| r == residue)
+            .map_or(0, |i| self.composition[i])
+    }
+}
+
+/// Monoisotopic mass of a residue in a peptide chain, or None if it is ambiguous or unknown
+pub fn residue_mass(residue: u8) -> Option<f64> {
+    let mass = match residue {
+        b'G' => 57.02146,
+        b'A' => 71.03711,
+        b'S' => 87.03203,
+        b'P' => 97.05276,
+        b'V' => 99.06841,
+        b'T' => 101.04768,
+        b'C' => 103.00919,
+        b'L' | b'I' => 113.08406,
+        b'N' => 114.04293,
+        b'D' => 115.02694,
+        b'Q' => 128.05858,
+        b'K' => 128.09496,
+        b'E' => 129.04259,
+        b'M' => 131.04049,
+        b'H' => 137.05891,
+        b'F' => 147.06841,
+        b'U' => 150.95364,
+        b'R' => 156.10111,
+        b'Y' => 163.06333,
+        b'W' => 186.07931,
+        b'O' => 237.14773,
+        _ => return None,
+    };
+
+    Some(mass)
+}
+
+/// Net charge of a protein with the given composition at the given pH
+fn charge(composition: &[u32; 20], ph: f64) -> f64 {
+    let count = |residue: u8| -> f64 {
+        let index = STANDARD_RESIDUES
+            .iter()
+            .position(|&r| r == residue)
+            .unwrap();
+        composition[index] as f64
+    };
+    let positive = |pka: f64| 1.0 / (1.0 + 10f64.powf(ph - pka));
+    let negative = |pka: f64| -1.0 / (1.0 + 10f64.powf(pka - ph));
+
+    positive(PKA_N_TERMINUS)
+        + count(b'K') * positive(PKA_K)
+        + count(b'R') * positive(PKA_R)
+        + count(b'H') * positive(PKA_H)
+        + negative(PKA_C_TERMINUS)
+        + count(b'D') * negative(PKA_D)
+        + count(b'E') * negative(PKA_E)
+        + count(b'C') * negative(PKA_C)
+        + count(b'Y') * negative(PKA_Y)
+}
+
+/// Find the pH at which a protein with the given composition has no net charge by bisection
+/// The charge strictly decreases with the pH, so the search always converges.
+fn isoelectric_point(composition: &[u32; 20]) -> f64 {
+    let (mut low, mut high) = (0.0, 14.0);
+
+    while high - low > 0.001 {
+        let middle = (low + high) / 2.0;
+        if charge(composition, middle) > 0.0 {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+
+    (low + high) / 2.0
+}
+
+fn round(value: f64, decimals: i32) -> f64 {
+    let factor = 10f64.powi(decimals);
+    (value * factor).round() / factor
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protein_stats() {
+        let stats = ProteinStats::calculate(&"MKWVTFISLLXLFSSAYSR".to_string(), 5, 50);
+
+        assert_eq!(stats.length, 19);
+        assert_eq!(stats.count(b'S'), 4);
+        assert_eq!(stats.count(b'L'), 3);
+        assert_eq!(stats.composition.iter().sum::<u32>(), 18);
+        assert_eq!(stats.non_standard_fraction, 0.0526);
+        // MK is too short, the rest of the sequence is a single tryptic peptide
+        assert_eq!(stats.tryptic_peptides, 1);
+    }
+
+    #[test]
+    fn test_monoisotopic_mass() {
+        // Monoisotopic mass of the peptide PEPTIDE
+        let stats = ProteinStats::calculate(&"PEPTIDE".to_string(), 5, 50);
+        assert!((stats.monoisotopic_mass - 799.3599).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_isoelectric_point() {
+        let acidic = ProteinStats::calculate(&"DDEEDDEE".to_string(), 5, 50);
+        let basic = ProteinStats::calculate(&"KKRRKKRR".to_string(), 5, 50);
+        let neutral = ProteinStats::calculate(&"GGGGGGGG".to_string(), 5, 50);
+
+        assert!(acidic.isoelectric_point < 4.0);
+        assert!(basic.isoelectric_point > 11.0);
+        assert!((neutral.isoelectric_point - 6.1).abs() < 0.1);
+    }
+}
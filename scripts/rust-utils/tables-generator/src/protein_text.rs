@@ -0,0 +1,326 @@
+use std::fs::File;
+use std::io::{BufRead, BufWriter, ErrorKind, Read, Write};
+use std::path::PathBuf;
+
+use anyhow::{Context, Error, Result};
+use utils::open_write;
+
+/// Character written between two consecutive proteins in the protein text
+pub const SEPARATOR: u8 = b'-';
+/// Character written after the last protein in the protein text
+pub const TERMINATOR: u8 = b'$';
+/// Size of a single record in the offsets file
+pub const OFFSET_RECORD_SIZE: usize = 16;
+
+// Columns of the uniprot_entries table
+const ID_COLUMN: usize = 0;
+const SEQUENCE_COLUMN: usize = 6;
+
+/// Writes the concatenated protein text that serves as input for the suffix array, together with
+/// a binary offsets file that maps positions in the text back to UniProt entry ids.
+/// Every record of the offsets file consists of the position of the first residue of a protein
+/// in the text and the id of its entry, both as little-endian 64-bit integers. Records are
+/// written in the order of the proteins in the text.
+pub struct ProteinTextWriter {
+    text: BufWriter<File>,
+    offsets: BufWriter<File>,
+    equate_il: bool,
+    position: u64,
+    proteins: u64,
+}
+
+impl ProteinTextWriter {
+    pub fn new(text: &PathBuf, offsets: &PathBuf, equate_il: bool) -> Result<Self> {
+        Ok(Self {
+            text: open_write(text).context("Unable to open protein text file")?,
+            offsets: open_write(offsets).context("Unable to open protein offsets file")?,
+            equate_il,
+            position: 0,
+            proteins: 0,
+        })
+    }
+
+    /// Append the sequence of the entry with the given id to the text
+    pub fn write(&mut self, id: i64, sequence: &str) -> Result<()> {
+        if self.proteins > 0 {
+            self.text
+                .write_all(&[SEPARATOR])
+                .context("Error writing to protein text")?;
+            self.position += 1;
+        }
+
+        self.offsets
+            .write_all(&self.position.to_le_bytes())
+            .and_then(|_| self.offsets.write_all(&id.to_le_bytes()))
+            .context("Error writing to protein offsets file")?;
+
+        self.text
+            .write_all(&equalize(sequence.as_bytes(), self.equate_il))
+            .context("Error writing to protein text")?;
+
+        self.position += sequence.len() as u64;
+        self.proteins += 1;
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> Result<()> {
+        self.text
+            .write_all(&[TERMINATOR])
+            .context("Error writing to protein text")?;
+        self.text.flush().context("Error flushing protein text")?;
+        self.offsets
+            .flush()
+            .context("Error flushing protein offsets file")
+    }
+}
+
+/// Maps positions in the protein text onto the ids of the entries they belong to
+pub struct ProteinOffsets {
+    starts: Vec<u64>,
+    ids: Vec<i64>,
+}
+
+impl ProteinOffsets {
+    pub fn read<R: Read>(mut reader: R) -> Result<Self> {
+        let mut starts = Vec::new();
+        let mut ids = Vec::new();
+
+        while let Some((start, id)) = read_offset_record(&mut reader)? {
+            starts.push(start);
+            ids.push(id);
+        }
+
+        Ok(Self { starts, ids })
+    }
+
+    pub fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.starts.is_empty()
+    }
+
+    /// Get the id of the entry of the protein that contains the given position, or None if the
+    /// position lies before the first protein
+    /// Positions of separators are attributed to the protein in front of them.
+    pub fn lookup(&self, position: u64) -> Option<i64> {
+        let index = self.starts.partition_point(|&start| start <= position);
+        index.checked_sub(1).map(|i| self.ids[i])
+    }
+}
+
+/// Read the next record of an offsets file, or None if the end of the file was reached
+/// A file that ends in the middle of a record is an error.
+pub fn read_offset_record<R: Read>(reader: &mut R) -> Result<Option<(u64, i64)>> {
+    let mut record = [0u8; OFFSET_RECORD_SIZE];
+    let mut filled = 0;
+    while filled < OFFSET_RECORD_SIZE {
+        match reader.read(&mut record[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(Error::new(e).context("Error reading protein offsets file")),
+        }
+    }
+
+    match filled {
+        0 => return Ok(None),
+        OFFSET_RECORD_SIZE => {}
+        _ => {
+            return Err(Error::msg(format!(
+                "Protein offsets file ends with a partial record of {} bytes",
+                filled
+            )));
+        }
+    }
+
+    let (start, id) = record.split_at(8);
+    Ok(Some((
+        u64::from_le_bytes(start.try_into()?),
+        i64::from_le_bytes(id.try_into()?),
+    )))
+}
+
+/// Check that a protein text and its offsets file match the entries of a uniprot_entries table
+/// Returns the amount of proteins that were checked.
+pub fn validate_protein_text<E: BufRead, T: Read, O: Read>(
+    entries: E,
+    mut text: T,
+    mut offsets: O,
+    equate_il: bool,
+) -> Result<u64> {
+    let mut position: u64 = 0;
+    let mut proteins: u64 = 0;
+    let mut residues = Vec::new();
+
+    for line in entries.lines() {
+        let line = line.context("Error reading line from UniProt entries file")?;
+        let columns: Vec<&str> = line.split('\t').collect();
+        let (Some(id), Some(sequence)) = (columns.get(ID_COLUMN), columns.get(SEQUENCE_COLUMN))
+        else {
+            return Err(Error::msg(format!("Malformed UniProt entry: {}", line)));
+        };
+        let id: i64 = id
+            .parse()
+            .with_context(|| format!("Unable to parse entry id {}", id))?;
+
+        if proteins > 0 {
+            expect_character(&mut text, SEPARATOR, position)?;
+            position += 1;
+        }
+
+        let (start, offset_id) = read_offset_record(&mut offsets)?
+            .with_context(|| format!("Offsets file ends before the protein of entry {}", id))?;
+        if start != position || offset_id != id {
+            return Err(Error::msg(format!(
+                "Offsets file maps position {} to entry {}, but entry {} starts at position {}",
+                start, offset_id, id, position
+            )));
+        }
+
+        residues.resize(sequence.len(), 0);
+        text.read_exact(&mut residues)
+            .with_context(|| format!("Protein text ends within the sequence of entry {}", id))?;
+        if residues != equalize(sequence.as_bytes(), equate_il) {
+            return Err(Error::msg(format!(
+                "Protein text at position {} does not match the sequence of entry {}",
+                position, id
+            )));
+        }
+
+        position += sequence.len() as u64;
+        proteins += 1;
+    }
+
+    expect_character(&mut text, TERMINATOR, position)?;
+
+    if text.read(&mut [0u8])? != 0 {
+        return Err(Error::msg(format!(
+            "Protein text continues after the terminator at position {}",
+            position
+        )));
+    }
+    if read_offset_record(&mut offsets)?.is_some() {
+        return Err(Error::msg(format!(
+            "Offsets file contains more than {} records",
+            proteins
+        )));
+    }
+
+    Ok(proteins)
+}
+
+fn expect_character<T: Read>(text: &mut T, expected: u8, position: u64) -> Result<()> {
+    let mut character = [0u8];
+    text.read_exact(&mut character)
+        .with_context(|| format!("Protein text ends unexpectedly at position {}", position))?;
+
+    if character[0] != expected {
+        return Err(Error::msg(format!(
+            "Expected '{}' at position {} of the protein text, found '{}'",
+            expected as char, position, character[0] as char
+        )));
+    }
+
+    Ok(())
+}
+
+fn equalize(sequence: &[u8], equate_il: bool) -> Vec<u8> {
+    if !equate_il {
+        return sequence.to_vec();
+    }
+
+    sequence
+        .iter()
+        .map(|&x| if x == b'I' { b'L' } else { x })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENTRIES: &str = "1\tP1\t1\t2\tswissprot\tA\tMIK\t\n\
+                           2\tP2\t1\t562\ttrembl\tB\tAILR\t\n";
+
+    fn write_text(equate_il: bool) -> (Vec<u8>, Vec<u8>) {
+        let dir = tempfile::tempdir().unwrap();
+        let text_path = dir.path().join("text.txt");
+        let offsets_path = dir.path().join("offsets.bin");
+
+        let mut writer = ProteinTextWriter::new(&text_path, &offsets_path, equate_il).unwrap();
+        writer.write(1, "MIK").unwrap();
+        writer.write(2, "AILR").unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+
+        (
+            std::fs::read(text_path).unwrap(),
+            std::fs::read(offsets_path).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_protein_text() {
+        let (text, offsets) = write_text(true);
+        assert_eq!(text, b"MLK-ALLR$");
+
+        let offsets = ProteinOffsets::read(offsets.as_slice()).unwrap();
+        assert_eq!(offsets.len(), 2);
+        assert_eq!(offsets.lookup(0), Some(1));
+        assert_eq!(offsets.lookup(3), Some(1));
+        assert_eq!(offsets.lookup(4), Some(2));
+        assert_eq!(offsets.lookup(7), Some(2));
+    }
+
+    #[test]
+    fn test_read_offset_record() {
+        let (_, offsets) = write_text(false);
+        let mut reader = offsets.as_slice();
+        assert_eq!(read_offset_record(&mut reader).unwrap(), Some((0, 1)));
+        assert_eq!(read_offset_record(&mut reader).unwrap(), Some((4, 2)));
+        assert_eq!(read_offset_record(&mut reader).unwrap(), None);
+
+        // A truncated file is not mistaken for the end of the offsets
+        let mut truncated = &offsets[..OFFSET_RECORD_SIZE + 5];
+        assert!(read_offset_record(&mut truncated).unwrap().is_some());
+        assert!(read_offset_record(&mut truncated).is_err());
+        assert!(ProteinOffsets::read(&offsets[..OFFSET_RECORD_SIZE + 5]).is_err());
+    }
+
+    #[test]
+    fn test_validate_protein_text() {
+        let (text, offsets) = write_text(false);
+        let proteins = validate_protein_text(
+            ENTRIES.as_bytes(),
+            text.as_slice(),
+            offsets.as_slice(),
+            false,
+        )
+        .unwrap();
+        assert_eq!(proteins, 2);
+
+        // The text was not equalized, so validating it as such fails
+        assert!(
+            validate_protein_text(
+                ENTRIES.as_bytes(),
+                text.as_slice(),
+                offsets.as_slice(),
+                true
+            )
+            .is_err()
+        );
+        // A missing protein is detected
+        let first_entry = &ENTRIES[..ENTRIES.find('\n').unwrap() + 1];
+        assert!(
+            validate_protein_text(
+                first_entry.as_bytes(),
+                text.as_slice(),
+                offsets.as_slice(),
+                false
+            )
+            .is_err()
+        );
+    }
+}
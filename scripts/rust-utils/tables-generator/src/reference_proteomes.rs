@@ -0,0 +1,221 @@
+use std::fmt::Write as _;
+use std::io::{BufRead, Write};
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use external_sort::{ExternalSorter, KeyOrder, SortedLines};
+use utils::{open_read, open_write};
+
+/// A reference proteome, as listed by the UniProt proteomes endpoint
+/// (upid, organism_id and protein_count)
+struct ReferenceProteome {
+    /// Position of the proteome in the reference proteomes file
+    index: usize,
+    upid: String,
+    organism_id: String,
+    protein_count: String,
+}
+
+/// Build the proteomes table from the proteomes file written by ProteomeTableWriter (proteome
+/// and accession number) and a TSV-file of reference proteomes.
+///
+/// Every reference proteome gets one line with its organism, its protein count and the accession
+/// numbers of all entries that reference it, joined by semicolons. These lines are numbered in the
+/// order in which the proteomes occur in the reference proteomes file, so the numbering stays the
+/// same as long as that file does. Proteomes that are not a reference proteome are left out. The
+/// upids of the reference proteomes that were not referenced by any entry are returned.
+pub fn build_reference_proteomes(
+    proteomes_pb: &PathBuf,
+    reference_pb: &PathBuf,
+    output_pb: &PathBuf,
+    memory: usize,
+    temp_dir: &Path,
+) -> Result<Vec<String>> {
+    let references = read_reference_proteomes(reference_pb)?;
+
+    // The proteomes are joined in the order of their upid, and are then put back in the order of
+    // the reference proteomes file. Both sorters are alive at the same time.
+    let mut sorter = ExternalSorter::new(0, KeyOrder::Bytes, memory / 2, 1, temp_dir);
+    sorter.push_all(open_read(proteomes_pb).context("Unable to open proteomes file")?)?;
+    let mut proteomes = sorter.finish()?.peekable();
+
+    let mut joined = ExternalSorter::new(0, KeyOrder::Numeric, memory / 2, 1, temp_dir);
+    let mut unmatched = Vec::new();
+
+    for reference in &references {
+        let accessions = collect_accessions(&mut proteomes, &reference.upid)?;
+        if accessions.is_empty() {
+            unmatched.push(reference.upid.clone());
+        }
+
+        joined.push_with(|line| {
+            write!(
+                line,
+                "{}\t{}\t{}\t{}\t{}",
+                reference.index,
+                reference.upid,
+                reference.organism_id,
+                reference.protein_count,
+                accessions.join(";")
+            )
+        })?;
+    }
+
+    let mut writer = open_write(output_pb).context("Unable to open output file")?;
+    for (i, line) in joined.finish()?.enumerate() {
+        let line = line?;
+        // Number the lines again, the indices have gaps where duplicates were removed
+        let (_, proteome) = line
+            .split_once('\t')
+            .with_context(|| format!("Malformed joined line: {}", line))?;
+
+        writeln!(&mut writer, "{}\t{}", i + 1, proteome)
+            .context("Error writing to proteomes output file")?;
+    }
+
+    writer
+        .flush()
+        .context("Error flushing proteomes output file")?;
+
+    Ok(unmatched)
+}
+
+/// Read the reference proteomes, sorted on their upid
+/// Only the first occurrence of a proteome that is listed more than once is kept.
+fn read_reference_proteomes(pb: &PathBuf) -> Result<Vec<ReferenceProteome>> {
+    let reader = open_read(pb).context("Unable to open reference proteomes file")?;
+    let mut references = Vec::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.context("Error reading line from reference proteomes file")?;
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut fields = line.splitn(3, '\t');
+        let upid = fields.next().unwrap_or_default();
+        let organism_id = fields
+            .next()
+            .with_context(|| format!("Missing organism for reference proteome {}", upid))?;
+        let protein_count = fields
+            .next()
+            .with_context(|| format!("Missing protein count for reference proteome {}", upid))?;
+
+        references.push(ReferenceProteome {
+            index,
+            upid: upid.to_string(),
+            organism_id: organism_id.to_string(),
+            protein_count: protein_count.to_string(),
+        });
+    }
+
+    // A stable sort keeps the first occurrence of every upid in front
+    references.sort_by(|a, b| a.upid.as_bytes().cmp(b.upid.as_bytes()));
+    references.dedup_by(|a, b| a.upid == b.upid);
+
+    Ok(references)
+}
+
+/// Skip all proteome lines that sort before `upid`, and collect the accession numbers of the
+/// lines that belong to it
+fn collect_accessions(proteomes: &mut Peekable<SortedLines>, upid: &str) -> Result<Vec<String>> {
+    let mut accessions = Vec::new();
+
+    loop {
+        let line = match proteomes.peek() {
+            None => break,
+            Some(Ok(line)) => line,
+            Some(Err(_)) => return Err(proteomes.next().unwrap().unwrap_err()),
+        };
+
+        let (proteome, accession) = line
+            .split_once('\t')
+            .context("Unable to split proteomes file on tabs")?;
+
+        if proteome.as_bytes() > upid.as_bytes() {
+            break;
+        }
+        if proteome == upid {
+            accessions.push(accession.to_string());
+        }
+
+        proteomes.next();
+    }
+
+    Ok(accessions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROTEOMES: &str = "\
+UP000000625\tP0A7B8
+UP000000001\tQ00001
+UP000005640\tP69905
+UP000000625\tP0A6F5
+UP000005640\tP68871
+";
+
+    // Not sorted on upid, with one duplicate
+    const REFERENCES: &str = "\
+UP000005640\t9606\t20000
+UP000000625\t83333\t4400
+
+UP000002311\t559292\t6000
+UP000000625\t83333\t4400
+";
+
+    fn build(memory: usize) -> (String, Vec<String>) {
+        let dir = tempfile::tempdir().unwrap();
+        let pb = |name: &str| dir.path().join(name);
+        std::fs::write(pb("proteomes.tsv"), PROTEOMES).unwrap();
+        std::fs::write(pb("reference_proteomes.tsv"), REFERENCES).unwrap();
+
+        let unmatched = build_reference_proteomes(
+            &pb("proteomes.tsv"),
+            &pb("reference_proteomes.tsv"),
+            &pb("output.tsv"),
+            memory,
+            dir.path(),
+        )
+        .unwrap();
+
+        (
+            std::fs::read_to_string(pb("output.tsv")).unwrap(),
+            unmatched,
+        )
+    }
+
+    #[test]
+    fn test_build_reference_proteomes() {
+        let (output, unmatched) = build(1 << 20);
+
+        // Proteomes keep the order of the reference file, and UP000000001 is not a reference
+        assert_eq!(
+            output,
+            "\
+1\tUP000005640\t9606\t20000\tP68871;P69905
+2\tUP000000625\t83333\t4400\tP0A6F5;P0A7B8
+3\tUP000002311\t559292\t6000\t
+"
+        );
+        assert_eq!(unmatched, vec!["UP000002311"]);
+    }
+
+    #[test]
+    fn test_build_reference_proteomes_with_runs() {
+        // A tiny memory budget makes both sorting steps spill to temporary files
+        assert_eq!(build(64), build(1 << 20));
+    }
+
+    #[test]
+    fn test_missing_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        let pb = dir.path().join("reference_proteomes.tsv");
+        std::fs::write(&pb, "UP000005640\t9606\n").unwrap();
+
+        assert!(read_reference_proteomes(&pb).is_err());
+    }
+}
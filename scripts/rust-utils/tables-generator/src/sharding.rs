@@ -0,0 +1,140 @@
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Error, Result};
+use strum_macros::{Display, EnumString};
+use utils::{open_read, open_write};
+use xxhash_rust::xxh3::xxh3_64;
+
+/// Amount of possible values of the first two residues of a sequence, when every residue is
+/// treated as a letter from A to Z
+const PREFIX_VALUES: usize = 26 * 26;
+
+/// How rows are divided over the shards of a sharded table
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Display, EnumString)]
+pub enum ShardStrategy {
+    /// Use the hash of the sequence, which spreads rows evenly over the shards
+    #[strum(serialize = "hash")]
+    Hash,
+    /// Use the first residues of the sequence, so that every shard holds a contiguous range of
+    /// sorted sequences and the sorted shards can simply be concatenated in order
+    #[strum(serialize = "prefix")]
+    Prefix,
+}
+
+/// Get the index of the shard in which a row with the given sequence belongs
+/// Rows with the same sequence always end up in the same shard
+pub fn shard_index(strategy: ShardStrategy, sequence: &[u8], shards: usize) -> usize {
+    if shards <= 1 {
+        return 0;
+    }
+
+    match strategy {
+        ShardStrategy::Hash => (xxh3_64(sequence) % shards as u64) as usize,
+        ShardStrategy::Prefix => {
+            let residue = |i: usize| -> usize {
+                sequence
+                    .get(i)
+                    .map_or(0, |c| c.saturating_sub(b'A').min(25) as usize)
+            };
+            let prefix = residue(0) * 26 + residue(1);
+            prefix * shards / PREFIX_VALUES
+        }
+    }
+}
+
+/// Get the path of a shard, by inserting its index in the file name of `pb` before the first
+/// extension (e.g. `peptides.tsv` becomes `peptides.0003.tsv`)
+pub fn shard_path(pb: &Path, index: usize) -> PathBuf {
+    let file_name = pb
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let shard_name = match file_name.split_once('.') {
+        Some((stem, extension)) => format!("{}.{:04}.{}", stem, index, extension),
+        None => format!("{}.{:04}", file_name, index),
+    };
+
+    pb.with_file_name(shard_name)
+}
+
+/// A shard of a sharded table, as listed in its manifest
+pub struct Shard {
+    pub path: PathBuf,
+    pub rows: u64,
+}
+
+/// Write the manifest of a sharded table: one line per shard with its index, path and amount
+/// of rows
+pub fn write_manifest(pb: &PathBuf, shards: &[Shard]) -> Result<()> {
+    let mut writer = open_write(pb).context("Unable to open shard manifest")?;
+
+    for (i, shard) in shards.iter().enumerate() {
+        writeln!(
+            &mut writer,
+            "{}\t{}\t{}",
+            i,
+            shard.path.display(),
+            shard.rows
+        )
+        .context("Error writing to shard manifest")?;
+    }
+
+    Ok(())
+}
+
+/// Read the manifest of a sharded table, written by `write_manifest`
+pub fn read_manifest(pb: &PathBuf) -> Result<Vec<Shard>> {
+    let reader = open_read(pb).context("Unable to open shard manifest")?;
+    let mut shards = Vec::new();
+
+    for line in reader.lines() {
+        let line = line.context("Error reading line from shard manifest")?;
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 3 || fields[0] != shards.len().to_string() {
+            return Err(Error::msg(format!(
+                "Malformed shard manifest line: {}",
+                line
+            )));
+        }
+
+        shards.push(Shard {
+            path: PathBuf::from(fields[1]),
+            rows: fields[2]
+                .parse()
+                .with_context(|| format!("Unable to parse {} as u64", fields[2]))?,
+        });
+    }
+
+    Ok(shards)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefix_shards_are_ordered() {
+        let sequences: [&[u8]; 5] = [b"AAK", b"AK", b"LLR", b"MK", b"YYK"];
+        let shards: Vec<usize> = sequences
+            .iter()
+            .map(|s| shard_index(ShardStrategy::Prefix, s, 8))
+            .collect();
+
+        assert!(shards.windows(2).all(|w| w[0] <= w[1]));
+        assert!(shards.iter().all(|&s| s < 8));
+    }
+
+    #[test]
+    fn test_shard_path() {
+        assert_eq!(
+            shard_path(Path::new("/tmp/peptides.tsv.lz4"), 3),
+            PathBuf::from("/tmp/peptides.0003.tsv.lz4")
+        );
+        assert_eq!(
+            shard_path(Path::new("peptides"), 12),
+            PathBuf::from("peptides.0012")
+        );
+    }
+}
@@ -0,0 +1,497 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{Context, Error, Result};
+use arrow::array::{ArrayRef, Float64Builder, Int32Builder, Int64Builder, StringBuilder};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::ipc::writer::FileWriter;
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use strum_macros::{Display, EnumString};
+use utils::open_write;
+
+/// Amount of rows that are buffered before a record batch is written to a columnar output file
+const BATCH_SIZE: usize = 8192;
+
+/// The file formats in which the generated tables can be written
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Display, EnumString)]
+pub enum OutputFormat {
+    #[strum(serialize = "tsv")]
+    Tsv,
+    #[strum(serialize = "parquet")]
+    Parquet,
+    #[strum(serialize = "arrow")]
+    Arrow,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum ColumnType {
+    Int32,
+    Int64,
+    Float64,
+    Utf8,
+}
+
+/// Description of a single column in an output table
+#[derive(Debug)]
+pub struct Column {
+    pub name: &'static str,
+    pub type_: ColumnType,
+}
+
+impl Column {
+    pub const fn new(name: &'static str, type_: ColumnType) -> Self {
+        Column { name, type_ }
+    }
+}
+
+/// A single value in a row, borrowed from the data that is being written
+#[derive(Debug)]
+pub enum Value<'a> {
+    Int32(i32),
+    Int64(i64),
+    Float64(f64),
+    Str(&'a str),
+}
+
+/// A typed row of an output table
+/// The columns of the table are described by the `COLUMNS` constant of the implementing type
+pub trait Row {
+    /// Get the value of the column at the given index
+    fn value(&self, column: usize) -> Value<'_>;
+}
+
+/// Common interface of all table output backends
+pub trait TableWriter: Send {
+    fn write_row(&mut self, row: &dyn Row) -> Result<()>;
+
+    /// Whether rows can be rendered ahead of time and written with `write_prerendered`
+    fn supports_prerendered(&self) -> bool {
+        false
+    }
+
+    /// Write a row of which all columns but the first one were already rendered with
+    /// `render_tsv_columns` (starting from column 1, including the trailing newline)
+    fn write_prerendered(&mut self, _first: i64, _rest: &[u8]) -> Result<()> {
+        Err(Error::msg(
+            "This output format does not support pre-rendered rows",
+        ))
+    }
+
+    /// Flush all buffered rows and write any trailing metadata the format requires.
+    /// No rows can be written after calling this function.
+    fn finish(&mut self) -> Result<()>;
+}
+
+/// Create a TableWriter for the given format that writes rows with the given columns to `pb`
+pub fn create_table_writer(
+    format: OutputFormat,
+    pb: &PathBuf,
+    columns: &'static [Column],
+) -> Result<Box<dyn TableWriter>> {
+    let writer: Box<dyn TableWriter> = match format {
+        OutputFormat::Tsv => Box::new(TsvTableWriter::new(pb, columns)?),
+        OutputFormat::Parquet => Box::new(ParquetTableWriter::new(pb, columns)?),
+        OutputFormat::Arrow => Box::new(IpcTableWriter::new(pb, columns)?),
+    };
+
+    Ok(writer)
+}
+
+/// Writes rows as tab-separated lines, without a header
+pub struct TsvTableWriter {
+    writer: BufWriter<File>,
+    columns: usize,
+    buffer: Vec<u8>,
+}
+
+impl TsvTableWriter {
+    pub fn new(pb: &PathBuf, columns: &'static [Column]) -> Result<Self> {
+        Ok(TsvTableWriter {
+            writer: open_write(pb).context("Unable to open output file")?,
+            columns: columns.len(),
+            buffer: Vec::new(),
+        })
+    }
+}
+
+/// Render the columns of a row from column `from` onwards as they are written to a TSV file
+/// Every column is preceded by a tab, except for column 0, and the row ends with a newline
+pub fn render_tsv_columns(row: &dyn Row, from: usize, columns: usize, buffer: &mut Vec<u8>) {
+    for i in from..columns {
+        if i > 0 {
+            buffer.push(b'\t');
+        }
+
+        // Writing to a Vec can not fail
+        match row.value(i) {
+            Value::Int32(v) => write!(buffer, "{}", v).unwrap(),
+            Value::Int64(v) => write!(buffer, "{}", v).unwrap(),
+            Value::Float64(v) => write!(buffer, "{}", v).unwrap(),
+            Value::Str(v) => buffer.extend_from_slice(v.as_bytes()),
+        }
+    }
+
+    buffer.push(b'\n');
+}
+
+impl TableWriter for TsvTableWriter {
+    fn write_row(&mut self, row: &dyn Row) -> Result<()> {
+        self.buffer.clear();
+        render_tsv_columns(row, 0, self.columns, &mut self.buffer);
+        self.writer
+            .write_all(&self.buffer)
+            .context("Error writing to TSV")
+    }
+
+    fn supports_prerendered(&self) -> bool {
+        true
+    }
+
+    fn write_prerendered(&mut self, first: i64, rest: &[u8]) -> Result<()> {
+        write!(&mut self.writer, "{}", first).context("Error writing to TSV")?;
+        self.writer.write_all(rest).context("Error writing to TSV")
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.flush().context("Error flushing TSV")
+    }
+}
+
+/// Apache Parquet output, compressed with zstd
+pub struct ParquetTableWriter {
+    batch: BatchBuilder,
+    writer: Option<ArrowWriter<BufWriter<File>>>,
+}
+
+impl ParquetTableWriter {
+    pub fn new(pb: &PathBuf, columns: &'static [Column]) -> Result<Self> {
+        let batch = BatchBuilder::new(columns);
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(ZstdLevel::default()))
+            .build();
+        let writer = ArrowWriter::try_new(
+            open_write(pb).context("Unable to open output file")?,
+            batch.schema.clone(),
+            Some(properties),
+        )
+        .context("Unable to create Parquet writer")?;
+
+        Ok(ParquetTableWriter {
+            batch,
+            writer: Some(writer),
+        })
+    }
+
+    fn flush_batch(&mut self) -> Result<()> {
+        let writer = self
+            .writer
+            .as_mut()
+            .context("Parquet writer was already finished")?;
+        writer
+            .write(&self.batch.finish()?)
+            .context("Error writing record batch to Parquet")
+    }
+}
+
+impl TableWriter for ParquetTableWriter {
+    fn write_row(&mut self, row: &dyn Row) -> Result<()> {
+        self.batch.append(row)?;
+        if self.batch.len() >= BATCH_SIZE {
+            self.flush_batch()?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.batch.len() > 0 {
+            self.flush_batch()?;
+        }
+
+        if let Some(writer) = self.writer.take() {
+            writer.close().context("Error closing Parquet writer")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Arrow IPC file output
+pub struct IpcTableWriter {
+    batch: BatchBuilder,
+    writer: FileWriter<BufWriter<File>>,
+}
+
+impl IpcTableWriter {
+    pub fn new(pb: &PathBuf, columns: &'static [Column]) -> Result<Self> {
+        let batch = BatchBuilder::new(columns);
+        let writer = FileWriter::try_new(
+            open_write(pb).context("Unable to open output file")?,
+            &batch.schema,
+        )
+        .context("Unable to create Arrow IPC writer")?;
+
+        Ok(IpcTableWriter { batch, writer })
+    }
+
+    fn flush_batch(&mut self) -> Result<()> {
+        self.writer
+            .write(&self.batch.finish()?)
+            .context("Error writing record batch to Arrow IPC")
+    }
+}
+
+impl TableWriter for IpcTableWriter {
+    fn write_row(&mut self, row: &dyn Row) -> Result<()> {
+        self.batch.append(row)?;
+        if self.batch.len() >= BATCH_SIZE {
+            self.flush_batch()?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.batch.len() > 0 {
+            self.flush_batch()?;
+        }
+
+        self.writer
+            .finish()
+            .context("Error closing Arrow IPC writer")
+    }
+}
+
+enum ColumnBuilder {
+    Int32(Int32Builder),
+    Int64(Int64Builder),
+    Float64(Float64Builder),
+    Utf8(StringBuilder),
+}
+
+/// Buffers typed rows into Arrow arrays until they are turned into a RecordBatch
+struct BatchBuilder {
+    schema: SchemaRef,
+    builders: Vec<ColumnBuilder>,
+    rows: usize,
+}
+
+impl BatchBuilder {
+    fn new(columns: &'static [Column]) -> Self {
+        let fields: Vec<Field> = columns
+            .iter()
+            .map(|c| {
+                let data_type = match c.type_ {
+                    ColumnType::Int32 => DataType::Int32,
+                    ColumnType::Int64 => DataType::Int64,
+                    ColumnType::Float64 => DataType::Float64,
+                    ColumnType::Utf8 => DataType::Utf8,
+                };
+                Field::new(c.name, data_type, false)
+            })
+            .collect();
+
+        let builders = columns
+            .iter()
+            .map(|c| match c.type_ {
+                ColumnType::Int32 => ColumnBuilder::Int32(Int32Builder::new()),
+                ColumnType::Int64 => ColumnBuilder::Int64(Int64Builder::new()),
+                ColumnType::Float64 => ColumnBuilder::Float64(Float64Builder::new()),
+                ColumnType::Utf8 => ColumnBuilder::Utf8(StringBuilder::new()),
+            })
+            .collect();
+
+        BatchBuilder {
+            schema: Arc::new(Schema::new(fields)),
+            builders,
+            rows: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.rows
+    }
+
+    fn append(&mut self, row: &dyn Row) -> Result<()> {
+        for (i, builder) in self.builders.iter_mut().enumerate() {
+            match (builder, row.value(i)) {
+                (ColumnBuilder::Int32(b), Value::Int32(v)) => b.append_value(v),
+                (ColumnBuilder::Int64(b), Value::Int64(v)) => b.append_value(v),
+                (ColumnBuilder::Float64(b), Value::Float64(v)) => b.append_value(v),
+                (ColumnBuilder::Utf8(b), Value::Str(v)) => b.append_value(v),
+                (_, v) => {
+                    return Err(Error::msg(format!(
+                        "Value {:?} does not match the type of column {}",
+                        v,
+                        self.schema.field(i).name()
+                    )));
+                }
+            }
+        }
+
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<RecordBatch> {
+        let arrays: Vec<ArrayRef> = self
+            .builders
+            .iter_mut()
+            .map(|b| -> ArrayRef {
+                match b {
+                    ColumnBuilder::Int32(b) => Arc::new(b.finish()),
+                    ColumnBuilder::Int64(b) => Arc::new(b.finish()),
+                    ColumnBuilder::Float64(b) => Arc::new(b.finish()),
+                    ColumnBuilder::Utf8(b) => Arc::new(b.finish()),
+                }
+            })
+            .collect();
+
+        self.rows = 0;
+        RecordBatch::try_new(self.schema.clone(), arrays).context("Unable to build record batch")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, Float64Array, Int32Array, Int64Array, StringArray};
+    use arrow::ipc::reader::FileReader;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    struct TestRow {
+        id: i64,
+        taxon_id: i32,
+        mass: f64,
+        name: &'static str,
+    }
+
+    impl TestRow {
+        const COLUMNS: &'static [Column] = &[
+            Column::new("id", ColumnType::Int64),
+            Column::new("taxon_id", ColumnType::Int32),
+            Column::new("mass", ColumnType::Float64),
+            Column::new("name", ColumnType::Utf8),
+        ];
+    }
+
+    impl Row for TestRow {
+        fn value(&self, column: usize) -> Value<'_> {
+            match column {
+                0 => Value::Int64(self.id),
+                1 => Value::Int32(self.taxon_id),
+                2 => Value::Float64(self.mass),
+                _ => Value::Str(self.name),
+            }
+        }
+    }
+
+    const ROWS: [TestRow; 2] = [
+        TestRow {
+            id: 1,
+            taxon_id: 9606,
+            mass: 1.5,
+            name: "human",
+        },
+        TestRow {
+            id: 2,
+            taxon_id: 562,
+            mass: 0.25,
+            name: "E. coli",
+        },
+    ];
+
+    fn write_rows(format: OutputFormat, pb: &PathBuf) {
+        let mut writer = create_table_writer(format, pb, TestRow::COLUMNS).unwrap();
+        for row in &ROWS {
+            writer.write_row(row).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    fn assert_batch(batch: &RecordBatch) {
+        let column = |i: usize| batch.column(i).as_any();
+        let ids = column(0).downcast_ref::<Int64Array>().unwrap();
+        let taxa = column(1).downcast_ref::<Int32Array>().unwrap();
+        let masses = column(2).downcast_ref::<Float64Array>().unwrap();
+        let names = column(3).downcast_ref::<StringArray>().unwrap();
+
+        assert_eq!(batch.num_rows(), ROWS.len());
+        assert_eq!(batch.schema().field(3).name(), "name");
+        for (i, row) in ROWS.iter().enumerate() {
+            assert_eq!(ids.value(i), row.id);
+            assert_eq!(taxa.value(i), row.taxon_id);
+            assert_eq!(masses.value(i), row.mass);
+            assert_eq!(names.value(i), row.name);
+        }
+        assert_eq!(names.null_count(), 0);
+    }
+
+    #[test]
+    fn test_tsv_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let pb = dir.path().join("table.tsv");
+        write_rows(OutputFormat::Tsv, &pb);
+
+        assert_eq!(
+            std::fs::read_to_string(&pb).unwrap(),
+            "1\t9606\t1.5\thuman\n2\t562\t0.25\tE. coli\n"
+        );
+    }
+
+    #[test]
+    fn test_tsv_prerendered() {
+        let dir = tempfile::tempdir().unwrap();
+        let pb = dir.path().join("table.tsv");
+
+        let mut writer = create_table_writer(OutputFormat::Tsv, &pb, TestRow::COLUMNS).unwrap();
+        assert!(writer.supports_prerendered());
+        let mut rest = Vec::new();
+        render_tsv_columns(&ROWS[0], 1, TestRow::COLUMNS.len(), &mut rest);
+        writer.write_prerendered(7, &rest).unwrap();
+        writer.finish().unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&pb).unwrap(),
+            "7\t9606\t1.5\thuman\n"
+        );
+    }
+
+    #[test]
+    fn test_parquet_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let pb = dir.path().join("table.parquet");
+        write_rows(OutputFormat::Parquet, &pb);
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&pb).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<RecordBatch> = reader.map(|b| b.unwrap()).collect();
+        assert_eq!(batches.len(), 1);
+        assert_batch(&batches[0]);
+    }
+
+    #[test]
+    fn test_ipc_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let pb = dir.path().join("table.arrow");
+        write_rows(OutputFormat::Arrow, &pb);
+
+        let reader = FileReader::try_new(File::open(&pb).unwrap(), None).unwrap();
+        let batches: Vec<RecordBatch> = reader.map(|b| b.unwrap()).collect();
+        assert_eq!(batches.len(), 1);
+        assert_batch(&batches[0]);
+    }
+
+    #[test]
+    fn test_mismatched_value_type() {
+        let mut batch = BatchBuilder::new(&TestRow::COLUMNS[1..]);
+        assert!(batch.append(&ROWS[0]).is_err());
+    }
+}
@@ -1,146 +1,444 @@
-use std::collections::HashSet;
-use std::fs::File;
-use std::io::{BufWriter, Write};
-use std::path::PathBuf;
-
-use crate::models::{Entry, calculate_entry_digest};
-use crate::taxon_list::parse_taxon_file_basic;
-use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::io::Write;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::decoys::{DecoyStrategy, decoy_entry};
+use crate::identifiers::{IdStrategy, accession_id, sequence_id};
+use crate::models::{
+    Entry, EntryRow, PeptideRow, ProteinStatsRow, ProteomeRow, calculate_entry_digest,
+};
+use crate::protein_stats::ProteinStats;
+use crate::protein_text::ProteinTextWriter;
+use crate::sharding::{Shard, ShardStrategy, shard_index, shard_path, write_manifest};
+use crate::table_format::{OutputFormat, TableWriter, create_table_writer, render_tsv_columns};
+use crate::taxon_list::{TaxonFilter, parse_merged_taxa_file, parse_taxon_file};
+use anyhow::{Context, Error, Result};
 use bit_vec::BitVec;
-use utils::open_write;
+use functional_annotation::FunctionalAnnotation;
+use utils::{now_str, open_write};
 
 pub struct EntryTableWriter {
     taxa: BitVec,
-    wrong_ids: HashSet<i32>,
-    uniprot_entries: BufWriter<File>,
+    /// Amount of dropped entries for every taxon id that is unknown or invalid
+    wrong_ids: HashMap<i32, u64>,
+    /// Maps taxon ids that were merged into another taxon onto their current id
+    merged_taxa: HashMap<i32, i32>,
+    /// Amount of entries that were kept by rewriting their merged taxon id, per old taxon id
+    rescued_ids: HashMap<i32, u64>,
+    /// Maps NCBI taxon ids onto the ids of the taxonomy the taxa file belongs to (e.g. GTDB)
+    /// Entries of NCBI taxa without a mapped taxon are dropped.
+    taxon_mapping: Option<HashMap<i32, i32>>,
+    /// Taxa of which entries are written, if the output is restricted to a part of the taxonomy
+    allowed_taxa: Option<BitVec>,
+    /// Amount of entries with a known taxon that were left out by the taxon filter
+    filtered_count: u64,
+    uniprot_entries: Box<dyn TableWriter>,
     uniprot_count: i64,
+    /// Amount of functional annotations that were left out because their id is invalid
+    invalid_annotations: u64,
+    id_strategy: IdStrategy,
+    /// Concatenated protein text of all written entries, if requested
+    protein_text: Option<ProteinTextWriter>,
 }
 
 impl EntryTableWriter {
-    pub fn new(taxa: &PathBuf, uniprot_entries: &PathBuf) -> Result<Self> {
+    /// Create a writer for the entries of the taxa in the given taxons file
+    /// If the filter is not empty, only entries of which the taxon passes it are written. Entries
+    /// of known taxa that are left out by the filter are not counted as dropped.
+    pub fn new(
+        taxa: &PathBuf,
+        filter: &TaxonFilter,
+        uniprot_entries: &PathBuf,
+        format: OutputFormat,
+        id_strategy: IdStrategy,
+    ) -> Result<Self> {
+        let (taxa, allowed_taxa) =
+            parse_taxon_file(taxa, filter).context("Unable to parse taxonomy file")?;
+
         Ok(Self {
-            taxa: parse_taxon_file_basic(taxa).context("Unable to parse taxonomy file")?,
-            wrong_ids: HashSet::new(),
-            uniprot_entries: open_write(uniprot_entries).context("Unable to open output file")?,
+            taxa,
+            wrong_ids: HashMap::new(),
+            merged_taxa: HashMap::new(),
+            rescued_ids: HashMap::new(),
+            taxon_mapping: None,
+            allowed_taxa,
+            filtered_count: 0,
+            uniprot_entries: create_table_writer(format, uniprot_entries, EntryRow::COLUMNS)
+                .context("Unable to open output file")?,
             uniprot_count: 0,
+            invalid_annotations: 0,
+            id_strategy,
+            protein_text: None,
         })
     }
 
-    pub fn write(&mut self, entry: Entry) -> Result<()> {
-        self.write_uniprot_entry(&entry)
+    /// Load a merged taxa file (generated by taxdmp-parser), so that entries referring to a taxon
+    /// that has been merged into another one are rewritten instead of dropped
+    pub fn load_merged_taxa(&mut self, pb: &PathBuf) -> Result<()> {
+        self.merged_taxa =
+            parse_merged_taxa_file(pb).context("Unable to parse merged taxa file")?;
+        Ok(())
+    }
+
+    /// Load a file that maps NCBI taxon ids onto the ids of another taxonomy (generated by
+    /// taxdmp-parser for GTDB), which has the same layout as the merged taxa file. The taxon ids
+    /// of all entries are translated using this mapping.
+    pub fn load_taxon_mapping(&mut self, pb: &PathBuf) -> Result<()> {
+        self.taxon_mapping =
+            Some(parse_merged_taxa_file(pb).context("Unable to parse taxon mapping file")?);
+        Ok(())
+    }
+
+    /// Also write the sequences of all written entries to a protein text, together with an offsets
+    /// file that maps positions in this text back to entry ids
+    pub fn write_protein_text(
+        &mut self,
+        text: &PathBuf,
+        offsets: &PathBuf,
+        equate_il: bool,
+    ) -> Result<()> {
+        self.protein_text = Some(ProteinTextWriter::new(text, offsets, equate_il)?);
+        Ok(())
+    }
+
+    pub fn write(&mut self, mut entry: Entry) -> Result<()> {
+        self.write_uniprot_entry(&mut entry)
             .context("Failed to write entry")?;
         Ok(())
     }
 
-    pub fn write_uniprot_entry(&mut self, entry: &Entry) -> Result<i64> {
-        if 0 <= entry.taxon_id
-            && entry.taxon_id < self.taxa.len() as i32
-            && self.taxa[entry.taxon_id as usize]
-        // This indexing is safe due to the line above
+    fn is_valid_taxon(&self, taxon_id: i32) -> bool {
+        // This indexing is safe due to the bounds check before it
+        0 <= taxon_id && taxon_id < self.taxa.len() as i32 && self.taxa[taxon_id as usize]
+    }
+
+    fn is_allowed_taxon(&self, taxon_id: i32) -> bool {
+        match &self.allowed_taxa {
+            // Only called for valid taxa, so the id is positive
+            Some(allowed) => (taxon_id as usize) < allowed.len() && allowed[taxon_id as usize],
+            None => true,
+        }
+    }
+
+    /// Write an entry to the UniProt table and return its id, or -1 if its taxon is unknown or
+    /// not allowed by the taxon filter.
+    /// If the taxon of the entry has been merged into another one, the taxon id of the entry is
+    /// rewritten to the current id.
+    /// If a taxon mapping is loaded, the taxon id of the entry is translated using that mapping.
+    pub fn write_uniprot_entry(&mut self, entry: &mut Entry) -> Result<i64> {
+        if let Some(mapping) = &self.taxon_mapping {
+            // Merged taxa are NCBI ids as well, so they are resolved before translating
+            let ncbi_id = match self.merged_taxa.get(&entry.taxon_id) {
+                Some(&new_id) if !mapping.contains_key(&entry.taxon_id) => new_id,
+                _ => entry.taxon_id,
+            };
+
+            match mapping.get(&ncbi_id) {
+                Some(&mapped_id) => {
+                    // The entry is only rescued if its current taxon can be translated
+                    if ncbi_id != entry.taxon_id {
+                        *self.rescued_ids.entry(entry.taxon_id).or_insert(0) += 1;
+                    }
+                    entry.taxon_id = mapped_id;
+                }
+                None => {
+                    *self.wrong_ids.entry(entry.taxon_id).or_insert(0) += 1;
+                    return Ok(-1);
+                }
+            }
+        } else if !self.is_valid_taxon(entry.taxon_id)
+            && let Some(&new_id) = self.merged_taxa.get(&entry.taxon_id)
+            && self.is_valid_taxon(new_id)
         {
+            *self.rescued_ids.entry(entry.taxon_id).or_insert(0) += 1;
+            entry.taxon_id = new_id;
+        }
+
+        if self.is_valid_taxon(entry.taxon_id) && !self.is_allowed_taxon(entry.taxon_id) {
+            self.filtered_count += 1;
+            return Ok(-1);
+        }
+
+        if self.is_valid_taxon(entry.taxon_id) {
             self.uniprot_count += 1;
 
-            let accession_number = &entry.accession_number;
-            let version = entry.version.clone();
-            let taxon_id = entry.taxon_id;
-            let type_ = entry.type_.clone();
-            let name = entry.name.clone();
-            let sequence = entry.sequence.clone();
+            let id = match self.id_strategy {
+                IdStrategy::Counter => self.uniprot_count,
+                IdStrategy::Stable => accession_id(&entry.accession_number)?,
+            };
 
-            let ec = entry
-                .ec_references
-                .iter()
-                .filter(|x| !x.is_empty())
-                .map(|x| format!("EC:{}", x))
-                .collect::<Vec<String>>()
-                .join(";");
-            let go = entry.go_references.join(";");
-            let ip = entry
-                .ip_references
-                .iter()
-                .filter(|x| !x.is_empty())
-                .map(|x| format!("IPR:{}", x))
-                .collect::<Vec<String>>()
-                .join(";");
-
-            let mut fa = String::with_capacity(ec.len() + go.len() + ip.len() + 2);
-            fa.push_str(&ec);
-            fa.push(';');
-            fa.push_str(&go);
-            fa.push(';');
-            fa.push_str(&ip);
-
-            writeln!(
-                &mut self.uniprot_entries,
-                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
-                self.uniprot_count, accession_number, version, taxon_id, type_, name, sequence, fa
-            )
-            .context("Error writing to TSV")?;
-
-            return Ok(self.uniprot_count);
-        } else if !self.wrong_ids.contains(&entry.taxon_id) {
-            self.wrong_ids.insert(entry.taxon_id);
+            let (fa, invalid) = serialize_annotations(entry);
+            self.invalid_annotations += invalid;
+
+            self.uniprot_entries
+                .write_row(&EntryRow {
+                    id,
+                    accession_number: &entry.accession_number,
+                    version: &entry.version,
+                    taxon_id: entry.taxon_id,
+                    type_: &entry.type_,
+                    name: &entry.name,
+                    sequence: &entry.sequence,
+                    fa: &fa,
+                })
+                .context("Error writing entry row")?;
+
+            if let Some(protein_text) = &mut self.protein_text {
+                protein_text
+                    .write(id, &entry.sequence)
+                    .context("Error writing protein text")?;
+            }
+
+            return Ok(id);
         }
 
+        *self.wrong_ids.entry(entry.taxon_id).or_insert(0) += 1;
+
         Ok(-1)
     }
+
+    /// Amount of entries that were not written because their taxon is unknown or invalid
+    pub fn dropped_count(&self) -> u64 {
+        self.wrong_ids.values().sum()
+    }
+
+    /// Fraction of all processed entries that were dropped
+    pub fn dropped_fraction(&self) -> f64 {
+        let dropped = self.dropped_count();
+        let total = dropped + self.uniprot_count as u64;
+
+        if total == 0 {
+            return 0.0;
+        }
+
+        dropped as f64 / total as f64
+    }
+
+    /// Write the amount of dropped entries per missing taxon id to a TSV file,
+    /// with the taxa that lost the most entries first
+    pub fn write_dropped_report(&self, pb: &PathBuf) -> Result<()> {
+        let mut writer = open_write(pb).context("Unable to open dropped entries report")?;
+
+        let mut counts: Vec<(&i32, &u64)> = self.wrong_ids.iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+
+        for (taxon_id, count) in counts {
+            writeln!(&mut writer, "{}\t{}", taxon_id, count)
+                .context("Error writing to dropped entries report")?;
+        }
+
+        Ok(())
+    }
+
+    /// Print a summary of the dropped entries to stderr, and fail if more than `max_fraction`
+    /// of all entries were dropped
+    pub fn check_dropped(&self, max_fraction: Option<f64>) -> Result<()> {
+        let fraction = self.dropped_fraction();
+
+        eprintln!(
+            "[{}] Dropped {} of {} entries ({:.4}%) referring to {} unknown or invalid taxa",
+            now_str(),
+            self.dropped_count(),
+            self.dropped_count() + self.uniprot_count as u64,
+            fraction * 100.0,
+            self.wrong_ids.len()
+        );
+
+        if !self.rescued_ids.is_empty() {
+            eprintln!(
+                "[{}] Rescued {} entries by rewriting {} merged taxa to their current id",
+                now_str(),
+                self.rescued_ids.values().sum::<u64>(),
+                self.rescued_ids.len()
+            );
+        }
+
+        if self.filtered_count > 0 {
+            eprintln!(
+                "[{}] Left out {} entries of taxa that were excluded by the taxon filter",
+                now_str(),
+                self.filtered_count
+            );
+        }
+
+        if self.invalid_annotations > 0 {
+            eprintln!(
+                "[{}] Left out {} functional annotations with an invalid id",
+                now_str(),
+                self.invalid_annotations
+            );
+        }
+
+        if let Some(max_fraction) = max_fraction
+            && fraction > max_fraction
+        {
+            return Err(Error::msg(format!(
+                "Fraction of dropped entries {:.6} exceeds the maximum of {}",
+                fraction, max_fraction
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> Result<()> {
+        if let Some(protein_text) = &mut self.protein_text {
+            protein_text.finish()?;
+        }
+        self.uniprot_entries.finish()
+    }
 }
 
 pub struct PeptideTableWriter {
-    peptides: BufWriter<File>,
+    /// One writer per shard, or a single writer if the output is not sharded
+    peptides: Vec<Box<dyn TableWriter>>,
+    shard_paths: Vec<PathBuf>,
+    shard_rows: Vec<u64>,
+    shard_strategy: ShardStrategy,
     peptide_count: i64,
+    negative_ids: bool,
     min_length: usize,
     max_length: usize,
+    id_strategy: IdStrategy,
 }
 
 impl PeptideTableWriter {
-    pub fn new(peptides: &PathBuf, min_length: usize, max_length: usize) -> Result<Self> {
+    pub fn new(
+        peptides: &Path,
+        min_length: usize,
+        max_length: usize,
+        format: OutputFormat,
+        id_strategy: IdStrategy,
+    ) -> Result<Self> {
+        Self::new_sharded(
+            peptides,
+            1,
+            ShardStrategy::Hash,
+            min_length,
+            max_length,
+            format,
+            id_strategy,
+        )
+    }
+
+    /// Create a writer that divides the peptides over `shards` files, based on their equalized
+    /// sequence. The path of every shard is derived from `peptides` with `shard_path`.
+    /// With a single shard, the peptides are written to `peptides` itself.
+    /// Peptide ids are unique across all shards, but nothing downstream reads the shards on
+    /// their own: sequence-numberer numbers a single table, since numbering shards separately
+    /// would give colliding sequence ids.
+    pub fn new_sharded(
+        peptides: &Path,
+        shards: usize,
+        shard_strategy: ShardStrategy,
+        min_length: usize,
+        max_length: usize,
+        format: OutputFormat,
+        id_strategy: IdStrategy,
+    ) -> Result<Self> {
+        let shard_paths: Vec<PathBuf> = if shards <= 1 {
+            vec![peptides.to_path_buf()]
+        } else {
+            (0..shards).map(|i| shard_path(peptides, i)).collect()
+        };
+
+        let writers = shard_paths
+            .iter()
+            .map(|pb| {
+                create_table_writer(format, pb, PeptideRow::columns(id_strategy))
+                    .with_context(|| format!("Unable to open output file {}", pb.display()))
+            })
+            .collect::<Result<Vec<Box<dyn TableWriter>>>>()?;
+
         Ok(Self {
-            peptides: open_write(peptides).context("Unable to open output file")?,
+            peptides: writers,
+            shard_rows: vec![0; shard_paths.len()],
+            shard_paths,
+            shard_strategy,
             peptide_count: 0,
+            negative_ids: false,
             min_length,
             max_length,
+            id_strategy,
         })
     }
 
-    pub fn write(&mut self, entry_id: i64, entry: Entry) -> Result<()> {
-        let go_ids = entry.go_references.into_iter();
-        let ec_ids = entry
-            .ec_references
-            .iter()
-            .filter(|x| !x.is_empty())
-            .map(|x| format!("EC:{}", x));
-        let ip_ids = entry
-            .ip_references
-            .iter()
-            .filter(|x| !x.is_empty())
-            .map(|x| format!("IPR:{}", x));
+    /// Number peptides -1, -2, ... instead of 1, 2, ..., so their ids never collide with those
+    /// of a table that is numbered the usual way
+    pub fn use_negative_ids(&mut self) {
+        self.negative_ids = true;
+    }
 
-        let summary = go_ids
-            .chain(ec_ids)
-            .chain(ip_ids)
-            .collect::<Vec<String>>()
-            .join(";");
+    pub fn write(&mut self, entry_id: i64, entry: Entry) -> Result<()> {
+        let digested = self.digester(false).digest(entry_id, &entry);
+        self.write_digested(&digested)
+    }
 
-        for sequence in calculate_entry_digest(&entry.sequence, self.min_length, self.max_length) {
-            let equated_sequence = sequence
-                .iter()
-                .map(|&x| if x == b'I' { b'L' } else { x })
-                .collect::<Vec<u8>>();
+    /// Create a PeptideDigester with the settings of this writer, so that entries can be
+    /// digested on other threads. If `prerender` is set and the output format allows it, the
+    /// digester also renders the rows of the peptides.
+    pub fn digester(&self, prerender: bool) -> PeptideDigester {
+        PeptideDigester {
+            min_length: self.min_length,
+            max_length: self.max_length,
+            id_strategy: self.id_strategy,
+            shards: self.peptides.len(),
+            shard_strategy: self.shard_strategy,
+            prerender: prerender && self.peptides.iter().all(|p| p.supports_prerendered()),
+        }
+    }
 
+    /// Write the peptides of an entry that was digested by a PeptideDigester of this writer
+    pub fn write_digested(&mut self, digested: &DigestedPeptides) -> Result<()> {
+        for peptide in &digested.peptides {
             self.peptide_count += 1;
 
-            writeln!(
-                &mut self.peptides,
-                "{}\t{}\t{}\t{}\t{}\t{}",
-                self.peptide_count,
-                String::from_utf8_lossy(&equated_sequence),
-                String::from_utf8_lossy(sequence),
-                entry_id,
-                &summary,
-                entry.taxon_id
-            )
-            .context("Error writing to TSV")?;
+            // Peptides of the same sequence share their sequence id, so rows are always numbered
+            let id = if self.negative_ids {
+                -self.peptide_count
+            } else {
+                self.peptide_count
+            };
+            self.shard_rows[peptide.shard] += 1;
+
+            let writer = &mut self.peptides[peptide.shard];
+            match &peptide.rendered {
+                Some(range) => writer.write_prerendered(id, &digested.rendered[range.clone()]),
+                None => writer.write_row(&PeptideRow {
+                    id,
+                    equalized_sequence: &peptide.equalized_sequence,
+                    original_sequence: &peptide.original_sequence,
+                    entry_id: digested.entry_id,
+                    fa: &digested.fa,
+                    taxon_id: digested.taxon_id,
+                    sequence_id: peptide.sequence_id,
+                }),
+            }
+            .context("Error writing peptide row")?;
+        }
+
+        Ok(())
+    }
+
+    /// Write a manifest that lists every shard with its path and amount of rows
+    pub fn write_manifest(&self, pb: &PathBuf) -> Result<()> {
+        let shards: Vec<Shard> = self
+            .shard_paths
+            .iter()
+            .zip(&self.shard_rows)
+            .map(|(path, &rows)| Shard {
+                path: path.clone(),
+                rows,
+            })
+            .collect();
+
+        write_manifest(pb, &shards)
+    }
+
+    pub fn finish(&mut self) -> Result<()> {
+        for peptides in self.peptides.iter_mut() {
+            peptides.finish()?;
         }
 
         Ok(())
@@ -148,26 +446,515 @@ impl PeptideTableWriter {
 }
 
 pub struct ProteomeTableWriter {
-    proteomes: BufWriter<File>,
+    proteomes: Box<dyn TableWriter>,
 }
 
 impl ProteomeTableWriter {
-    pub fn new(proteomes: &PathBuf) -> Result<Self> {
+    pub fn new(proteomes: &PathBuf, format: OutputFormat) -> Result<Self> {
         Ok(Self {
-            proteomes: open_write(proteomes).context("Unable to open output file")?,
+            proteomes: create_table_writer(format, proteomes, ProteomeRow::COLUMNS)
+                .context("Unable to open output file")?,
         })
     }
 
     pub fn write_proteomes(&mut self, entry: &Entry) -> Result<()> {
         for proteome in &entry.proteome_references {
-            writeln!(
-                &mut self.proteomes,
-                "{}\t{}",
-                proteome, entry.accession_number,
-            )
-            .context("Error writing to TSV")?;
+            self.proteomes
+                .write_row(&ProteomeRow {
+                    proteome,
+                    accession_number: &entry.accession_number,
+                })
+                .context("Error writing proteome row")?;
         }
 
         Ok(())
     }
+
+    pub fn finish(&mut self) -> Result<()> {
+        self.proteomes.finish()
+    }
+}
+
+/// Writes sequence-level properties of every written entry, keyed by the id of the entry
+pub struct ProteinStatsWriter {
+    stats: Box<dyn TableWriter>,
+    min_length: usize,
+    max_length: usize,
+}
+
+impl ProteinStatsWriter {
+    /// Tryptic peptides are only counted if their length lies between `min_length` and
+    /// `max_length` (inclusive)
+    pub fn new(
+        stats: &PathBuf,
+        format: OutputFormat,
+        min_length: usize,
+        max_length: usize,
+    ) -> Result<Self> {
+        Ok(Self {
+            stats: create_table_writer(format, stats, ProteinStatsRow::COLUMNS)
+                .context("Unable to open output file")?,
+            min_length,
+            max_length,
+        })
+    }
+
+    pub fn write(&mut self, entry_id: i64, entry: &Entry) -> Result<()> {
+        let stats = ProteinStats::calculate(&entry.sequence, self.min_length, self.max_length);
+
+        self.stats
+            .write_row(&ProteinStatsRow {
+                entry_id,
+                stats: &stats,
+            })
+            .context("Error writing protein statistics row")
+    }
+
+    pub fn finish(&mut self) -> Result<()> {
+        self.stats.finish()
+    }
+}
+
+/// Writes decoy entries to a table that is separate from the target tables, so decoys never end
+/// up in the LCA or functional analysis stages
+/// Every decoy entry gets the negated id of its target entry, so the ids of targets and decoys
+/// never collide. The peptides of the decoys are written by a separate PeptideTableWriter.
+pub struct DecoyTableWriter {
+    entries: Box<dyn TableWriter>,
+    strategy: DecoyStrategy,
+    seed: u64,
+}
+
+impl DecoyTableWriter {
+    pub fn new(
+        entries: &PathBuf,
+        strategy: DecoyStrategy,
+        seed: u64,
+        format: OutputFormat,
+    ) -> Result<Self> {
+        Ok(Self {
+            entries: create_table_writer(format, entries, EntryRow::COLUMNS)
+                .context("Unable to open decoy entries output file")?,
+            strategy,
+            seed,
+        })
+    }
+
+    /// Write the decoy of a target entry that was written with the given id
+    /// Returns the id and the decoy entry, so its peptides can be digested.
+    pub fn write(&mut self, entry_id: i64, entry: &Entry) -> Result<(i64, Entry)> {
+        let decoy = decoy_entry(entry, self.strategy, self.seed);
+        let decoy_id = -entry_id;
+
+        self.entries
+            .write_row(&EntryRow {
+                id: decoy_id,
+                accession_number: &decoy.accession_number,
+                version: &decoy.version,
+                taxon_id: decoy.taxon_id,
+                type_: &decoy.type_,
+                name: &decoy.name,
+                sequence: &decoy.sequence,
+                fa: "",
+            })
+            .context("Error writing decoy entry row")?;
+
+        Ok((decoy_id, decoy))
+    }
+
+    pub fn finish(&mut self) -> Result<()> {
+        self.entries.finish()
+    }
+}
+
+/// Digests entries into peptides for a PeptideTableWriter
+/// This is independent of the writer itself, so it can run on multiple threads at once
+#[derive(Clone)]
+pub struct PeptideDigester {
+    min_length: usize,
+    max_length: usize,
+    id_strategy: IdStrategy,
+    shards: usize,
+    shard_strategy: ShardStrategy,
+    prerender: bool,
+}
+
+/// The peptides of a single entry, ready to be written by `PeptideTableWriter::write_digested`
+pub struct DigestedPeptides {
+    entry_id: i64,
+    taxon_id: i32,
+    fa: String,
+    peptides: Vec<DigestedPeptide>,
+    /// The rendered columns of all peptides, if the digester pre-renders rows
+    rendered: Vec<u8>,
+}
+
+struct DigestedPeptide {
+    shard: usize,
+    /// Id derived from the original sequence, or 0 if ids are not stable
+    sequence_id: i64,
+    equalized_sequence: String,
+    original_sequence: String,
+    /// Range of the rendered columns of this peptide in `DigestedPeptides::rendered`
+    rendered: Option<Range<usize>>,
+}
+
+impl PeptideDigester {
+    pub fn digest(&self, entry_id: i64, entry: &Entry) -> DigestedPeptides {
+        let (summary, _) = serialize_annotations(entry);
+        let mut digested = DigestedPeptides {
+            entry_id,
+            taxon_id: entry.taxon_id,
+            fa: summary,
+            peptides: Vec::new(),
+            rendered: Vec::new(),
+        };
+
+        for sequence in calculate_entry_digest(&entry.sequence, self.min_length, self.max_length) {
+            let equated_sequence = sequence
+                .iter()
+                .map(|&x| if x == b'I' { b'L' } else { x })
+                .collect::<Vec<u8>>();
+
+            let stable_id = match self.id_strategy {
+                IdStrategy::Counter => 0,
+                IdStrategy::Stable => sequence_id(sequence),
+            };
+
+            let mut peptide = DigestedPeptide {
+                shard: shard_index(self.shard_strategy, &equated_sequence, self.shards),
+                sequence_id: stable_id,
+                equalized_sequence: String::from_utf8_lossy(&equated_sequence).into_owned(),
+                original_sequence: String::from_utf8_lossy(sequence).into_owned(),
+                rendered: None,
+            };
+
+            if self.prerender {
+                // The id is only known when the peptide is written, so rendering starts after it
+                let start = digested.rendered.len();
+                let row = PeptideRow {
+                    id: 0,
+                    equalized_sequence: &peptide.equalized_sequence,
+                    original_sequence: &peptide.original_sequence,
+                    entry_id,
+                    fa: &digested.fa,
+                    taxon_id: digested.taxon_id,
+                    sequence_id: peptide.sequence_id,
+                };
+                render_tsv_columns(
+                    &row,
+                    1,
+                    PeptideRow::columns(self.id_strategy).len(),
+                    &mut digested.rendered,
+                );
+                peptide.rendered = Some(start..digested.rendered.len());
+            }
+
+            digested.peptides.push(peptide);
+        }
+
+        digested
+    }
+}
+
+/// Serialize the valid functional annotations of an entry, and count the invalid ones
+fn serialize_annotations(entry: &Entry) -> (String, u64) {
+    let mut annotations = Vec::new();
+    let mut invalid = 0;
+
+    for annotation in entry.functional_annotations() {
+        match annotation {
+            Ok(annotation) => annotations.push(annotation),
+            Err(_) => invalid += 1,
+        }
+    }
+
+    (FunctionalAnnotation::join(&annotations), invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sharding::read_manifest;
+    use std::collections::HashSet;
+
+    fn entry(accession_number: &str, taxon_id: i32) -> Entry {
+        Entry::new(
+            "swissprot".to_string(),
+            accession_number.to_string(),
+            "MKWVTFISLLFLFSSAYSR".to_string(),
+            "Serum albumin".to_string(),
+            "1".to_string(),
+            taxon_id.to_string(),
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+        )
+        .unwrap()
+    }
+
+    fn entry_writer(dir: &Path) -> EntryTableWriter {
+        let taxa = dir.join("taxons.tsv");
+        std::fs::write(
+            &taxa,
+            "1\troot\tno rank\t1\t\u{1}\n562\tE. coli\tspecies\t1\t\u{1}\n",
+        )
+        .unwrap();
+
+        EntryTableWriter::new(
+            &taxa,
+            &TaxonFilter::default(),
+            &dir.join("uniprot_entries.tsv"),
+            OutputFormat::Tsv,
+            IdStrategy::Counter,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_dropped_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = entry_writer(dir.path());
+
+        assert_eq!(
+            writer.write_uniprot_entry(&mut entry("P1", 562)).unwrap(),
+            1
+        );
+        assert_eq!(
+            writer.write_uniprot_entry(&mut entry("P2", 999)).unwrap(),
+            -1
+        );
+        assert_eq!(
+            writer.write_uniprot_entry(&mut entry("P3", 12345)).unwrap(),
+            -1
+        );
+        assert_eq!(
+            writer.write_uniprot_entry(&mut entry("P4", 999)).unwrap(),
+            -1
+        );
+        assert_eq!(
+            writer.write_uniprot_entry(&mut entry("P5", 562)).unwrap(),
+            2
+        );
+        writer.finish().unwrap();
+
+        assert_eq!(writer.dropped_count(), 3);
+        assert_eq!(writer.dropped_fraction(), 0.6);
+
+        // The taxa that lost the most entries come first
+        let report = dir.path().join("dropped.tsv");
+        writer.write_dropped_report(&report).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&report).unwrap(),
+            "999\t2\n12345\t1\n"
+        );
+    }
+
+    #[test]
+    fn test_dropped_threshold() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = entry_writer(dir.path());
+
+        // Nothing was processed yet, so nothing was dropped
+        assert_eq!(writer.dropped_fraction(), 0.0);
+        assert!(writer.check_dropped(Some(0.0)).is_ok());
+
+        writer.write_uniprot_entry(&mut entry("P1", 562)).unwrap();
+        writer.write_uniprot_entry(&mut entry("P2", 999)).unwrap();
+
+        assert!(writer.check_dropped(None).is_ok());
+        assert!(writer.check_dropped(Some(0.5)).is_ok());
+        assert!(writer.check_dropped(Some(0.4)).is_err());
+    }
+
+    #[test]
+    fn test_merged_taxa_are_not_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = entry_writer(dir.path());
+
+        let merged = dir.path().join("merged_taxa.tsv");
+        std::fs::write(&merged, "12345\t562\n999\t\\N\n").unwrap();
+        writer.load_merged_taxa(&merged).unwrap();
+
+        let mut rescued = entry("P1", 12345);
+        assert_eq!(writer.write_uniprot_entry(&mut rescued).unwrap(), 1);
+        assert_eq!(rescued.taxon_id, 562);
+        assert_eq!(
+            writer.write_uniprot_entry(&mut entry("P2", 999)).unwrap(),
+            -1
+        );
+        assert_eq!(writer.dropped_count(), 1);
+    }
+
+    #[test]
+    fn test_merged_taxa_with_taxon_mapping() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = entry_writer(dir.path());
+
+        let merged = dir.path().join("merged_taxa.tsv");
+        std::fs::write(&merged, "12345\t562\n777\t888\n").unwrap();
+        writer.load_merged_taxa(&merged).unwrap();
+        let mapping = dir.path().join("taxon_mapping.tsv");
+        std::fs::write(&mapping, "562\t1\n").unwrap();
+        writer.load_taxon_mapping(&mapping).unwrap();
+
+        let mut rescued = entry("P1", 12345);
+        assert_eq!(writer.write_uniprot_entry(&mut rescued).unwrap(), 1);
+        assert_eq!(rescued.taxon_id, 1);
+        assert_eq!(writer.rescued_ids, HashMap::from([(12345, 1)]));
+
+        // The merged taxon has no mapped taxon, so the entry is dropped and not rescued
+        let mut dropped = entry("P2", 777);
+        assert_eq!(writer.write_uniprot_entry(&mut dropped).unwrap(), -1);
+        assert_eq!(writer.rescued_ids, HashMap::from([(12345, 1)]));
+        assert_eq!(writer.wrong_ids, HashMap::from([(777, 1)]));
+    }
+
+    #[test]
+    fn test_counter_peptide_rows_have_no_sequence_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let pb = dir.path().join("peptides.tsv");
+        let mut writer =
+            PeptideTableWriter::new(&pb, 5, 50, OutputFormat::Tsv, IdStrategy::Counter).unwrap();
+        writer.write(1, entry("P1", 562)).unwrap();
+        writer.finish().unwrap();
+
+        let output = std::fs::read_to_string(&pb).unwrap();
+        assert_eq!(
+            output,
+            "1\tWVTFLSLLFLFSSAYSR\tWVTFISLLFLFSSAYSR\t1\t\t562\n"
+        );
+    }
+
+    /// Write the peptides of a few entries to `shards` shards, and return the lines of every
+    /// shard in the order of the manifest
+    fn write_shards(dir: &Path, shards: usize, strategy: ShardStrategy) -> Vec<Vec<String>> {
+        let sequences = [
+            "MKWVTFISLLFLFSSAYSRGVFRRDAHK",
+            "SEVAHRFKDLGEENFKALVLIAFAQYLQQCPFEDHVK",
+            "LVNEVTEFAKTCVADESAENCDKSLHTLFGDK",
+            "YLYEIARRHPYFYAPELLFFAKRYK",
+        ];
+
+        let pb = dir.join("peptides.tsv");
+        let mut writer = PeptideTableWriter::new_sharded(
+            &pb,
+            shards,
+            strategy,
+            2,
+            50,
+            OutputFormat::Tsv,
+            IdStrategy::Counter,
+        )
+        .unwrap();
+        for (i, sequence) in sequences.iter().enumerate() {
+            let mut entry = entry(&format!("P{}", i), 562);
+            entry.sequence = sequence.to_string();
+            writer.write(i as i64 + 1, entry).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let manifest = dir.join("manifest.tsv");
+        writer.write_manifest(&manifest).unwrap();
+
+        read_manifest(&manifest)
+            .unwrap()
+            .iter()
+            .map(|shard| {
+                let content = std::fs::read_to_string(&shard.path).unwrap();
+                let lines: Vec<String> = content.lines().map(String::from).collect();
+                assert_eq!(lines.len() as u64, shard.rows);
+                lines
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sharded_peptides() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut unsharded = write_shards(dir.path(), 1, ShardStrategy::Hash).concat();
+        let sort = |lines: &mut Vec<String>| {
+            lines.sort_by(|a, b| {
+                let key = |l: &String| l.split('\t').nth(1).unwrap().to_string();
+                key(a).cmp(&key(b)).then(a.cmp(b))
+            })
+        };
+        sort(&mut unsharded);
+
+        // Every peptide ends up in exactly one shard, with all rows of its equalized sequence
+        let hashed = write_shards(dir.path(), 4, ShardStrategy::Hash);
+        assert_eq!(hashed.len(), 4);
+        let mut all = hashed.concat();
+        sort(&mut all);
+        assert_eq!(all, unsharded);
+        let equalized = |lines: &Vec<String>| -> HashSet<String> {
+            lines
+                .iter()
+                .map(|l| l.split('\t').nth(1).unwrap().to_string())
+                .collect()
+        };
+        for (i, a) in hashed.iter().enumerate() {
+            for b in &hashed[..i] {
+                assert!(equalized(a).is_disjoint(&equalized(b)));
+            }
+        }
+
+        // Prefix shards can be sorted on their own and concatenated in order
+        let mut prefixed = write_shards(dir.path(), 4, ShardStrategy::Prefix);
+        prefixed.iter_mut().for_each(sort);
+        assert_eq!(prefixed.concat(), unsharded);
+    }
+
+    #[test]
+    fn test_stable_peptide_rows_are_unique() {
+        let dir = tempfile::tempdir().unwrap();
+        let pb = dir.path().join("peptides.tsv");
+        let mut writer =
+            PeptideTableWriter::new(&pb, 5, 50, OutputFormat::Tsv, IdStrategy::Stable).unwrap();
+
+        // The same peptide occurs in both entries
+        writer.write(1, entry("P1", 562)).unwrap();
+        writer.write(2, entry("P2", 562)).unwrap();
+        writer.finish().unwrap();
+
+        let output = std::fs::read_to_string(&pb).unwrap();
+        let rows: Vec<Vec<&str>> = output.lines().map(|l| l.split('\t').collect()).collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0][0], "1");
+        assert_eq!(rows[1][0], "2");
+        assert_eq!(rows[0][6], sequence_id(rows[0][2].as_bytes()).to_string());
+        assert_eq!(rows[0][6], rows[1][6]);
+    }
+
+    #[test]
+    fn test_decoy_ids_are_disjoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let entries = dir.path().join("decoy_entries.tsv");
+        let peptides = dir.path().join("decoy_peptides.tsv");
+        let mut decoy_writer =
+            DecoyTableWriter::new(&entries, DecoyStrategy::Reverse, 0, OutputFormat::Tsv).unwrap();
+        let mut peptide_writer =
+            PeptideTableWriter::new(&peptides, 5, 50, OutputFormat::Tsv, IdStrategy::Counter)
+                .unwrap();
+        peptide_writer.use_negative_ids();
+
+        let (decoy_id, decoy) = decoy_writer.write(3, &entry("P1", 562)).unwrap();
+        assert_eq!(decoy_id, -3);
+        assert_eq!(decoy.accession_number, "DECOY_P1");
+        peptide_writer.write(decoy_id, decoy).unwrap();
+        decoy_writer.finish().unwrap();
+        peptide_writer.finish().unwrap();
+
+        let output = std::fs::read_to_string(&entries).unwrap();
+        assert!(output.starts_with("-3\tDECOY_P1\t"));
+
+        let output = std::fs::read_to_string(&peptides).unwrap();
+        let rows: Vec<Vec<&str>> = output.lines().map(|l| l.split('\t').collect()).collect();
+        assert!(!rows.is_empty());
+        for (i, row) in rows.iter().enumerate() {
+            assert_eq!(row[0], format!("-{}", i + 1));
+            assert_eq!(row[3], "-3");
+        }
+    }
 }
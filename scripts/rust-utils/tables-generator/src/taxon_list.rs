@@ -1,7 +1,8 @@
+use std::collections::HashMap;
 use std::io::BufRead;
 use std::path::PathBuf;
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Error, Result};
 use bit_vec::BitVec;
 use utils::open_read;
 
@@ -30,3 +31,319 @@ pub fn parse_taxon_file_basic(pb: &PathBuf) -> Result<BitVec> {
 
     Ok(entries)
 }
+
+/// Parse a merged taxa TSV-file (generated by taxdmp-parser) into a map from old to current taxon ids
+/// Deleted taxa have no current id, and are left out of the map
+pub fn parse_merged_taxa_file(pb: &PathBuf) -> Result<HashMap<i32, i32>> {
+    let mut merged = HashMap::new();
+    let reader = open_read(pb).context("Unable to open merged taxa input file")?;
+
+    for line in reader.lines() {
+        let line = line.context("Error reading line from merged taxa file")?;
+        let (old_id, new_id) = line
+            .split_once('\t')
+            .context("Unable to split merged taxa file on tabs")?;
+
+        if new_id == "\\N" {
+            continue;
+        }
+
+        let old_id: i32 = old_id
+            .parse()
+            .with_context(|| format!("Unable to parse {} as i32", old_id))?;
+        let new_id: i32 = new_id
+            .parse()
+            .with_context(|| format!("Unable to parse {} as i32", new_id))?;
+
+        merged.insert(old_id, new_id);
+    }
+
+    Ok(merged)
+}
+
+/// Restrictions on the taxa of which entries are written
+/// A taxon is allowed if it matches every restriction that is set
+#[derive(Debug, Default, Clone)]
+pub struct TaxonFilter {
+    /// Only allow taxa that taxdmp-parser marked as valid
+    pub valid_only: bool,
+    /// Only allow taxa in the subtree of one of these taxa (or in `include`)
+    pub subtrees: Vec<usize>,
+    /// Allow these taxa, even if they are not part of one of the `subtrees`
+    pub include: Vec<usize>,
+    /// Never allow these taxa
+    pub exclude: Vec<usize>,
+}
+
+impl TaxonFilter {
+    pub fn is_empty(&self) -> bool {
+        !self.valid_only
+            && self.subtrees.is_empty()
+            && self.include.is_empty()
+            && self.exclude.is_empty()
+    }
+}
+
+/// Parse a taxons TSV-file into the set of all taxa, and the set of taxa that pass the given
+/// filter (or None if the filter is empty)
+/// The file is only read once, so it can be a named pipe.
+pub fn parse_taxon_file(pb: &PathBuf, filter: &TaxonFilter) -> Result<(BitVec, Option<BitVec>)> {
+    if filter.is_empty() {
+        return Ok((parse_taxon_file_basic(pb)?, None));
+    }
+
+    let (present, allowed) = parse_taxon_file_filtered(pb, filter)?;
+    Ok((present, Some(allowed)))
+}
+
+/// Parse a taxons TSV-file (id, name, rank, parent and validity) into the set of all taxa and the
+/// set of taxa that pass the given filter
+fn parse_taxon_file_filtered(pb: &PathBuf, filter: &TaxonFilter) -> Result<(BitVec, BitVec)> {
+    let mut parents: Vec<usize> = Vec::new();
+    let mut valid = BitVec::new();
+    let mut present = BitVec::new();
+    let reader = open_read(pb).context("Unable to open taxon input file")?;
+
+    for line in reader.lines() {
+        let line = line.context("Error reading line from taxon file")?;
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 5 {
+            return Err(Error::msg(format!(
+                "Expected 5 columns in taxon file, found {}",
+                fields.len()
+            )));
+        }
+
+        let id: usize = fields[0]
+            .parse()
+            .with_context(|| format!("Unable to parse {} as usize", fields[0]))?;
+        let parent: usize = fields[3]
+            .parse()
+            .with_context(|| format!("Unable to parse {} as usize", fields[3]))?;
+
+        if present.len() <= id {
+            present.grow(id - present.len() + 1, false);
+            valid.grow(id - valid.len() + 1, false);
+            parents.resize(id + 1, 0);
+        }
+
+        present.set(id, true);
+        valid.set(id, fields[4] == "\u{0001}");
+        parents[id] = parent;
+    }
+
+    let in_subtrees = subtree_members(&parents, &present, &filter.subtrees);
+
+    let mut allowed = present.clone();
+    for id in 0..present.len() {
+        if !present[id] {
+            continue;
+        }
+
+        let mut keep = !filter.valid_only || valid[id];
+        if !filter.subtrees.is_empty() || !filter.include.is_empty() {
+            keep = keep && (in_subtrees[id] || filter.include.contains(&id));
+        }
+
+        allowed.set(id, keep);
+    }
+
+    for &id in &filter.exclude {
+        if id < allowed.len() {
+            allowed.set(id, false);
+        }
+    }
+
+    Ok((present, allowed))
+}
+
+/// Mark every taxon that is one of the `roots` or one of their descendants
+fn subtree_members(parents: &[usize], present: &BitVec, roots: &[usize]) -> BitVec {
+    // 0 = not yet known, 1 = in one of the subtrees, 2 = not in any of the subtrees
+    let mut state = vec![0u8; parents.len()];
+    for &root in roots {
+        if root < state.len() {
+            state[root] = 1;
+        }
+    }
+
+    let mut path = Vec::new();
+    for id in 0..parents.len() {
+        if !present[id] {
+            continue;
+        }
+
+        // Walk up until we reach a taxon of which the state is known, or the root of the tree
+        let mut current = id;
+        let result = loop {
+            if state[current] != 0 {
+                break state[current];
+            }
+
+            path.push(current);
+            let parent = parents[current];
+            if parent == current || parent >= parents.len() || !present[parent] {
+                break 2;
+            }
+            current = parent;
+        };
+
+        for taxon in path.drain(..) {
+            state[taxon] = result;
+        }
+    }
+
+    state.iter().map(|&s| s == 1).collect()
+}
+
+/// Parse a file with one taxon id per line, ignoring empty lines
+pub fn parse_taxon_id_file(pb: &PathBuf) -> Result<Vec<usize>> {
+    let reader = open_read(pb).context("Unable to open taxon id file")?;
+    let mut ids = Vec::new();
+
+    for line in reader.lines() {
+        let line = line.context("Error reading line from taxon id file")?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        ids.push(
+            line.parse()
+                .with_context(|| format!("Unable to parse {} as usize", line))?,
+        );
+    }
+
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 1 is the root, 2 (invalid) and 10 are its children, 3 is a child of 2 and 4 of 3
+    const TAXA: &str = "\
+1\troot\tno rank\t1\t\u{1}
+2\tBacteria\tsuperkingdom\t1\t\u{0}
+3\tProteobacteria\tphylum\t2\t\u{1}
+4\tEscherichia coli\tspecies\t3\t\u{1}
+10\tEukaryota\tsuperkingdom\t1\t\u{1}
+";
+
+    fn parse(filter: &TaxonFilter) -> (BitVec, Option<Vec<usize>>) {
+        let dir = tempfile::tempdir().unwrap();
+        let pb = dir.path().join("taxons.tsv");
+        std::fs::write(&pb, TAXA).unwrap();
+
+        let (present, allowed) = parse_taxon_file(&pb, filter).unwrap();
+        let allowed = allowed.map(|a| (0..a.len()).filter(|&id| a[id]).collect());
+        (present, allowed)
+    }
+
+    #[test]
+    fn test_taxon_filter_is_empty() {
+        assert!(TaxonFilter::default().is_empty());
+        assert!(
+            !TaxonFilter {
+                exclude: vec![2],
+                ..Default::default()
+            }
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_parse_taxon_file() {
+        let (present, allowed) = parse(&TaxonFilter::default());
+        assert_eq!(
+            (0..present.len())
+                .filter(|&id| present[id])
+                .collect::<Vec<_>>(),
+            vec![1, 2, 3, 4, 10]
+        );
+        assert_eq!(allowed, None);
+
+        // Every filter also returns the set of all taxa
+        let (filtered_present, _) = parse(&TaxonFilter {
+            valid_only: true,
+            ..Default::default()
+        });
+        assert_eq!(filtered_present, present);
+    }
+
+    #[test]
+    fn test_parse_taxon_file_filtered() {
+        let valid_only = TaxonFilter {
+            valid_only: true,
+            ..Default::default()
+        };
+        assert_eq!(parse(&valid_only).1, Some(vec![1, 3, 4, 10]));
+
+        let subtree = TaxonFilter {
+            subtrees: vec![2],
+            include: vec![10],
+            exclude: vec![4],
+            ..Default::default()
+        };
+        assert_eq!(parse(&subtree).1, Some(vec![2, 3, 10]));
+
+        // Restrictions are combined
+        let both = TaxonFilter {
+            valid_only: true,
+            ..subtree
+        };
+        assert_eq!(parse(&both).1, Some(vec![3, 10]));
+
+        // Unknown taxa in the filter are ignored
+        let unknown = TaxonFilter {
+            subtrees: vec![3, 100],
+            exclude: vec![1000],
+            ..Default::default()
+        };
+        assert_eq!(parse(&unknown).1, Some(vec![3, 4]));
+    }
+
+    #[test]
+    fn test_parse_taxon_file_filtered_missing_columns() {
+        let dir = tempfile::tempdir().unwrap();
+        let pb = dir.path().join("taxons.tsv");
+        std::fs::write(&pb, "1\troot\n").unwrap();
+
+        assert!(parse_taxon_file(&pb, &TaxonFilter::default()).is_ok());
+        let filter = TaxonFilter {
+            valid_only: true,
+            ..Default::default()
+        };
+        assert!(parse_taxon_file(&pb, &filter).is_err());
+    }
+
+    #[test]
+    fn test_subtree_members() {
+        // 0 is not a taxon, 5 refers to a parent that does not exist and 6 is its own parent
+        let parents = [0, 1, 1, 2, 3, 7, 6];
+        let present: BitVec = [false, true, true, true, true, true, true]
+            .into_iter()
+            .collect();
+
+        let members = subtree_members(&parents, &present, &[2]);
+        let members: Vec<usize> = (0..members.len()).filter(|&id| members[id]).collect();
+        assert_eq!(members, vec![2, 3, 4]);
+
+        let members = subtree_members(&parents, &present, &[1, 6]);
+        let members: Vec<usize> = (0..members.len()).filter(|&id| members[id]).collect();
+        assert_eq!(members, vec![1, 2, 3, 4, 6]);
+    }
+
+    #[test]
+    fn test_parse_merged_taxa_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let pb = dir.path().join("merged_taxa.tsv");
+        std::fs::write(&pb, "12\t14\n13\t14\n20\t\\N\n").unwrap();
+
+        let merged = parse_merged_taxa_file(&pb).unwrap();
+        assert_eq!(merged, HashMap::from([(12, 14), (13, 14)]));
+
+        std::fs::write(&pb, "12\t14\n13\n").unwrap();
+        assert!(parse_merged_taxa_file(&pb).is_err());
+    }
+}
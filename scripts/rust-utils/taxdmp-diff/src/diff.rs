@@ -0,0 +1,387 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+
+use anyhow::{Context, Result};
+use ncbi::Rank;
+use strum::{EnumCount, IntoEnumIterator};
+
+use crate::snapshot::{Snapshot, TaxonRecord};
+
+/// The ways in which a taxon can differ between two releases
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Deleted,
+    Merged,
+    Renamed,
+    Reranked,
+    Moved,
+    Validity,
+    Lineage,
+}
+
+impl ChangeKind {
+    pub const ALL: [ChangeKind; 8] = [
+        ChangeKind::Added,
+        ChangeKind::Deleted,
+        ChangeKind::Merged,
+        ChangeKind::Renamed,
+        ChangeKind::Reranked,
+        ChangeKind::Moved,
+        ChangeKind::Validity,
+        ChangeKind::Lineage,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Deleted => "deleted",
+            ChangeKind::Merged => "merged",
+            ChangeKind::Renamed => "renamed",
+            ChangeKind::Reranked => "reranked",
+            ChangeKind::Moved => "moved",
+            ChangeKind::Validity => "validity",
+            ChangeKind::Lineage => "lineage",
+        }
+    }
+}
+
+/// A single difference between two releases. The name and rank are those of the newest release
+/// in which the taxon occurs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub kind: ChangeKind,
+    pub taxon_id: usize,
+    pub name: String,
+    pub rank: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// Compare two releases and pass every change to the callback, ordered by taxon id
+/// Taxa that only occur in the old release are reported as merged if the merged taxa of the new
+/// release map them onto another taxon, and as deleted otherwise.
+pub fn diff<F>(
+    old: &Snapshot,
+    new: &Snapshot,
+    merged: &HashMap<usize, Option<usize>>,
+    mut callback: F,
+) -> Result<()>
+where
+    F: FnMut(Change) -> Result<()>,
+{
+    let mut old_taxa = old.iter().peekable();
+    let mut new_taxa = new.iter().peekable();
+
+    loop {
+        let order = match (old_taxa.peek(), new_taxa.peek()) {
+            (Some((old_id, _)), Some((new_id, _))) => old_id.cmp(new_id),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => return Ok(()),
+        };
+
+        // Safe to unwrap, the iterators that are advanced were peeked
+        match order {
+            Ordering::Equal => {
+                let (&id, old_taxon) = old_taxa.next().unwrap();
+                let (_, new_taxon) = new_taxa.next().unwrap();
+                compare(id, old_taxon, new_taxon, &mut callback)?;
+            }
+            Ordering::Less => {
+                let (&id, taxon) = old_taxa.next().unwrap();
+                let change = match merged.get(&id) {
+                    Some(Some(target)) => Change {
+                        kind: ChangeKind::Merged,
+                        old: id.to_string(),
+                        new: target.to_string(),
+                        ..change(id, taxon)
+                    },
+                    _ => Change {
+                        kind: ChangeKind::Deleted,
+                        old: taxon.name.clone(),
+                        new: "\\N".to_string(),
+                        ..change(id, taxon)
+                    },
+                };
+                callback(change)?;
+            }
+            Ordering::Greater => {
+                let (&id, taxon) = new_taxa.next().unwrap();
+                callback(Change {
+                    kind: ChangeKind::Added,
+                    old: "\\N".to_string(),
+                    new: taxon.name.clone(),
+                    ..change(id, taxon)
+                })?;
+            }
+        }
+    }
+}
+
+fn compare<F>(id: usize, old: &TaxonRecord, new: &TaxonRecord, callback: &mut F) -> Result<()>
+where
+    F: FnMut(Change) -> Result<()>,
+{
+    let mut report = |kind, old_value: String, new_value: String| {
+        callback(Change {
+            kind,
+            old: old_value,
+            new: new_value,
+            ..change(id, new)
+        })
+    };
+
+    if old.name != new.name {
+        report(ChangeKind::Renamed, old.name.clone(), new.name.clone())?;
+    }
+    if old.rank != new.rank {
+        report(ChangeKind::Reranked, old.rank.clone(), new.rank.clone())?;
+    }
+    if old.parent != new.parent {
+        report(
+            ChangeKind::Moved,
+            old.parent.to_string(),
+            new.parent.to_string(),
+        )?;
+    }
+    if old.valid != new.valid {
+        report(
+            ChangeKind::Validity,
+            (old.valid as u8).to_string(),
+            (new.valid as u8).to_string(),
+        )?;
+    }
+    if old.lineage != new.lineage {
+        let (old_lineage, new_lineage) = lineage_difference(&old.lineage, &new.lineage);
+        report(ChangeKind::Lineage, old_lineage, new_lineage)?;
+    }
+
+    Ok(())
+}
+
+/// Describe the ranks at which two lineages differ as comma separated rank=id pairs
+fn lineage_difference(old: &[i32], new: &[i32]) -> (String, String) {
+    let mut old_values = Vec::new();
+    let mut new_values = Vec::new();
+
+    let value = |rank: &Rank, tid: Option<&i32>| match tid {
+        Some(0) | None => format!("{}=\\N", rank),
+        Some(tid) => format!("{}={}", rank, tid),
+    };
+
+    for (i, rank) in Rank::iter().skip(1).enumerate() {
+        let (old_tid, new_tid) = (old.get(i), new.get(i));
+        if old_tid.copied().unwrap_or(0) != new_tid.copied().unwrap_or(0) {
+            old_values.push(value(&rank, old_tid));
+            new_values.push(value(&rank, new_tid));
+        }
+    }
+
+    (old_values.join(","), new_values.join(","))
+}
+
+fn change(id: usize, taxon: &TaxonRecord) -> Change {
+    Change {
+        kind: ChangeKind::Added,
+        taxon_id: id,
+        name: taxon.name.clone(),
+        rank: taxon.rank.clone(),
+        old: String::new(),
+        new: String::new(),
+    }
+}
+
+/// The number of changes of every kind, per rank
+#[derive(Debug, Default)]
+pub struct Summary {
+    counts: BTreeMap<(usize, String), [u64; ChangeKind::ALL.len()]>,
+}
+
+impl Summary {
+    pub fn add(&mut self, change: &Change) {
+        // Ranks are listed from the top of the taxonomy down, unknown ranks are listed last
+        let order = Rank::from_ncbi(&change.rank).map_or(Rank::COUNT, |rank| rank.index());
+        let counts = self.counts.entry((order, change.rank.clone())).or_default();
+        counts[change.kind as usize] += 1;
+    }
+
+    /// Write the summary as a table with a row per rank and a column per kind of change
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let width = self
+            .counts
+            .keys()
+            .map(|(_, rank)| rank.len())
+            .chain(std::iter::once("total".len()))
+            .max()
+            .unwrap_or_default();
+
+        write!(writer, "{:<width$}", "rank").context("Error writing summary")?;
+        for kind in ChangeKind::ALL {
+            write!(writer, " {:>9}", kind.name()).context("Error writing summary")?;
+        }
+        writeln!(writer).context("Error writing summary")?;
+
+        let mut total = [0; ChangeKind::ALL.len()];
+        for ((_, rank), counts) in &self.counts {
+            write_row(writer, rank, counts, width)?;
+            for (t, c) in total.iter_mut().zip(counts) {
+                *t += c;
+            }
+        }
+        write_row(writer, "total", &total, width)
+    }
+}
+
+fn write_row<W: Write>(writer: &mut W, label: &str, counts: &[u64], width: usize) -> Result<()> {
+    write!(writer, "{:<width$}", label).context("Error writing summary")?;
+    for count in counts {
+        write!(writer, " {:>9}", count).context("Error writing summary")?;
+    }
+    writeln!(writer).context("Error writing summary")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(name: &str, rank: &str, parent: usize, valid: bool, lineage: &[i32]) -> TaxonRecord {
+        TaxonRecord {
+            name: name.to_string(),
+            rank: rank.to_string(),
+            parent,
+            valid,
+            lineage: lineage.into(),
+        }
+    }
+
+    fn changes(
+        old: &Snapshot,
+        new: &Snapshot,
+        merged: &HashMap<usize, Option<usize>>,
+    ) -> Vec<Change> {
+        let mut changes = Vec::new();
+        diff(old, new, merged, |change| {
+            changes.push(change);
+            Ok(())
+        })
+        .unwrap();
+        changes
+    }
+
+    fn fields(change: &Change) -> (ChangeKind, usize, String, String) {
+        (
+            change.kind,
+            change.taxon_id,
+            change.old.clone(),
+            change.new.clone(),
+        )
+    }
+
+    #[test]
+    fn test_added_deleted_merged() {
+        let old = Snapshot::from([
+            (1, record("root", "no rank", 1, true, &[])),
+            (2, record("Bacteria", "domain", 1, true, &[2])),
+            (3, record("Archaea", "domain", 1, true, &[3])),
+        ]);
+        let new = Snapshot::from([
+            (1, record("root", "no rank", 1, true, &[])),
+            (4, record("Eukaryota", "domain", 1, true, &[4])),
+        ]);
+        let merged = HashMap::from([(2, Some(4)), (3, None)]);
+
+        let changes = changes(&old, &new, &merged);
+        assert_eq!(changes.len(), 3);
+        assert_eq!(
+            fields(&changes[0]),
+            (ChangeKind::Merged, 2, "2".into(), "4".into())
+        );
+        assert_eq!(
+            fields(&changes[1]),
+            (ChangeKind::Deleted, 3, "Archaea".into(), "\\N".into())
+        );
+        assert_eq!(
+            fields(&changes[2]),
+            (ChangeKind::Added, 4, "\\N".into(), "Eukaryota".into())
+        );
+    }
+
+    #[test]
+    fn test_changed_taxon() {
+        let old = Snapshot::from([(5, record("E. coli", "species", 3, true, &[2, 0, 3, 5]))]);
+        let new = Snapshot::from([(
+            5,
+            record("Escherichia coli", "clade", 4, false, &[2, 0, 4, -5]),
+        )]);
+
+        let changes = changes(&old, &new, &HashMap::new());
+        let kinds: Vec<ChangeKind> = changes.iter().map(|c| c.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ChangeKind::Renamed,
+                ChangeKind::Reranked,
+                ChangeKind::Moved,
+                ChangeKind::Validity,
+                ChangeKind::Lineage
+            ]
+        );
+        assert!(
+            changes
+                .iter()
+                .all(|c| c.name == "Escherichia coli" && c.rank == "clade")
+        );
+        assert_eq!(changes[3].old, "1");
+        assert_eq!(changes[3].new, "0");
+        assert_eq!(changes[4].old, "kingdom=3,subkingdom=5");
+        assert_eq!(changes[4].new, "kingdom=4,subkingdom=-5");
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut summary = Summary::default();
+        for (kind, rank) in [
+            (ChangeKind::Added, "species"),
+            (ChangeKind::Added, "species"),
+            (ChangeKind::Deleted, "genus"),
+            (ChangeKind::Renamed, "clade"),
+        ] {
+            summary.add(&Change {
+                kind,
+                taxon_id: 1,
+                name: String::new(),
+                rank: rank.to_string(),
+                old: String::new(),
+                new: String::new(),
+            });
+        }
+
+        let mut output = Vec::new();
+        summary.write(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let rows: Vec<Vec<&str>> = output
+            .lines()
+            .map(|l| l.split_whitespace().collect())
+            .collect();
+
+        assert_eq!(rows[0][0], "rank");
+        assert_eq!(
+            rows[1],
+            vec!["clade", "0", "0", "0", "1", "0", "0", "0", "0"]
+        );
+        assert_eq!(
+            rows[2],
+            vec!["genus", "0", "1", "0", "0", "0", "0", "0", "0"]
+        );
+        assert_eq!(
+            rows[3],
+            vec!["species", "2", "0", "0", "0", "0", "0", "0", "0"]
+        );
+        assert_eq!(
+            rows[4],
+            vec!["total", "2", "1", "0", "1", "0", "0", "0", "0"]
+        );
+    }
+}
@@ -0,0 +1,145 @@
+mod diff;
+mod snapshot;
+
+use std::collections::HashMap;
+use std::io::{Write, stdout};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use taxdmp_parser::taxon_list::TaxonList;
+use taxdmp_parser::validation_rules::ValidationRules;
+use utils::open_write;
+
+use crate::diff::{Summary, diff};
+use crate::snapshot::{Snapshot, from_tables, from_taxon_list, read_merged_taxa};
+
+fn main() -> Result<()> {
+    let args = Cli::parse();
+
+    let rules = match &args.validation_rules {
+        Some(pb) => ValidationRules::from_file(pb)?,
+        None => ValidationRules::default_rules()?,
+    };
+
+    let old = load_snapshot(
+        &args.old_names,
+        &args.old_nodes,
+        &args.old_taxa,
+        &args.old_lineages,
+        &rules,
+    )
+    .context("Failed to load old release")?;
+    let new = load_snapshot(
+        &args.new_names,
+        &args.new_nodes,
+        &args.new_taxa,
+        &args.new_lineages,
+        &rules,
+    )
+    .context("Failed to load new release")?;
+
+    let merged = match &args.merged_taxa {
+        Some(pb) => read_merged_taxa(pb).context("Failed to read merged taxa")?,
+        None => HashMap::new(),
+    };
+
+    let mut writer = open_write(&args.output).context("Unable to open output file")?;
+    writeln!(&mut writer, "change\ttaxon_id\tname\trank\told\tnew")
+        .context("Error writing to output file")?;
+
+    let mut summary = Summary::default();
+    diff(&old, &new, &merged, |change| {
+        summary.add(&change);
+        writeln!(
+            &mut writer,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            change.kind.name(),
+            change.taxon_id,
+            change.name,
+            change.rank,
+            change.old,
+            change.new
+        )
+        .context("Error writing to output file")
+    })?;
+    writer.flush().context("Error writing to output file")?;
+
+    summary.write(&mut stdout().lock())
+}
+
+/// Load a release either from the NCBI dumps, which are validated with the given rules, or from
+/// the tables generated by taxdmp-parser
+fn load_snapshot(
+    names: &Option<PathBuf>,
+    nodes: &Option<PathBuf>,
+    taxa: &Option<PathBuf>,
+    lineages: &Option<PathBuf>,
+    rules: &ValidationRules,
+) -> Result<Snapshot> {
+    match (names, nodes, taxa, lineages) {
+        (Some(names), Some(nodes), _, _) => {
+            let mut taxon_list = TaxonList::from_dumps(names, nodes)
+                .context("Failed to parse TaxonList from dumps")?;
+            taxon_list
+                .invalidate(rules)
+                .context("Failed to validate TaxonList")?;
+            from_taxon_list(&taxon_list)
+        }
+        (_, _, Some(taxa), Some(lineages)) => from_tables(taxa, lineages),
+        // Clap makes sure that one of both pairs is given
+        _ => unreachable!(),
+    }
+}
+
+#[derive(Parser, Debug)]
+struct Cli {
+    /// Path to the names.dmp file of the old release
+    #[clap(long, required_unless_present = "old_taxa", requires = "old_nodes")]
+    old_names: Option<PathBuf>,
+
+    /// Path to the nodes.dmp file of the old release
+    #[clap(long, requires = "old_names")]
+    old_nodes: Option<PathBuf>,
+
+    /// Path to the taxa file of the old release (generated by taxdmp-parser), used instead of the
+    /// dumps
+    #[clap(long, conflicts_with = "old_names", requires = "old_lineages")]
+    old_taxa: Option<PathBuf>,
+
+    /// Path to the lineages file of the old release (generated by taxdmp-parser)
+    #[clap(long, requires = "old_taxa")]
+    old_lineages: Option<PathBuf>,
+
+    /// Path to the names.dmp file of the new release
+    #[clap(long, required_unless_present = "new_taxa", requires = "new_nodes")]
+    new_names: Option<PathBuf>,
+
+    /// Path to the nodes.dmp file of the new release
+    #[clap(long, requires = "new_names")]
+    new_nodes: Option<PathBuf>,
+
+    /// Path to the taxa file of the new release (generated by taxdmp-parser), used instead of the
+    /// dumps
+    #[clap(long, conflicts_with = "new_names", requires = "new_lineages")]
+    new_taxa: Option<PathBuf>,
+
+    /// Path to the lineages file of the new release (generated by taxdmp-parser)
+    #[clap(long, requires = "new_taxa")]
+    new_lineages: Option<PathBuf>,
+
+    /// Path to the merged taxa file of the new release (generated by taxdmp-parser), used to tell
+    /// merged taxa apart from deleted ones
+    #[clap(long)]
+    merged_taxa: Option<PathBuf>,
+
+    /// Path to a TOML file with the rules that invalidate taxa loaded from the dumps, instead of
+    /// the default rules
+    #[clap(long)]
+    validation_rules: Option<PathBuf>,
+
+    /// Path to the output file that lists every change (change, taxon id, name, rank, old value,
+    /// new value)
+    #[clap(long)]
+    output: PathBuf,
+}
@@ -0,0 +1,114 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::BufRead;
+use std::path::PathBuf;
+
+use anyhow::{Context, Error, Result};
+use taxdmp_parser::taxon_list::TaxonList;
+use utils::open_read;
+
+/// A taxon as it occurs in one release of the taxonomy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaxonRecord {
+    pub name: String,
+    pub rank: String,
+    pub parent: usize,
+    pub valid: bool,
+    /// The ancestor at every rank, as in the lineages table (0 if there is none)
+    pub lineage: Box<[i32]>,
+}
+
+/// All taxa of one release of the taxonomy, sorted by id
+pub type Snapshot = BTreeMap<usize, TaxonRecord>;
+
+/// Create a snapshot of a TaxonList that was parsed from the dumps and validated
+pub fn from_taxon_list(taxa: &TaxonList) -> Result<Snapshot> {
+    let mut snapshot = Snapshot::new();
+
+    for id in taxa.ids() {
+        // Safe to unwrap, ids only returns existing taxa
+        let taxon = taxa.get(id).unwrap();
+        snapshot.insert(
+            id,
+            TaxonRecord {
+                name: taxon.name.clone(),
+                rank: taxon.rank_name.clone(),
+                parent: taxon.parent,
+                valid: taxon.valid,
+                lineage: taxa.lineage(id)?.into_boxed_slice(),
+            },
+        );
+    }
+
+    Ok(snapshot)
+}
+
+/// Read a snapshot from the taxons and lineages tables (generated by taxdmp-parser)
+pub fn from_tables(taxa_pb: &PathBuf, lineages_pb: &PathBuf) -> Result<Snapshot> {
+    let mut snapshot = Snapshot::new();
+
+    let reader = open_read(taxa_pb).context("Unable to open taxa file")?;
+    for line in reader.lines() {
+        let line = line.context("Error reading line from taxa file")?;
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 5 {
+            return Err(Error::msg(format!("Malformed line in taxa file: {}", line)));
+        }
+
+        snapshot.insert(
+            parse(fields[0])?,
+            TaxonRecord {
+                name: fields[1].to_string(),
+                rank: fields[2].to_string(),
+                parent: parse(fields[3])?,
+                valid: fields[4] == "\u{0001}",
+                lineage: Box::new([]),
+            },
+        );
+    }
+
+    let reader = open_read(lineages_pb).context("Unable to open lineages file")?;
+    for line in reader.lines() {
+        let line = line.context("Error reading line from lineages file")?;
+        let mut fields = line.split('\t');
+        // Safe to unwrap, split always returns at least one element
+        let id: usize = parse(fields.next().unwrap())?;
+
+        if let Some(taxon) = snapshot.get_mut(&id) {
+            taxon.lineage = fields
+                .map(|v| if v == "\\N" { Ok(0) } else { parse(v) })
+                .collect::<Result<Vec<i32>>>()?
+                .into_boxed_slice();
+        }
+    }
+
+    Ok(snapshot)
+}
+
+/// Read a merged taxa file (generated by taxdmp-parser), which maps old taxon ids onto their
+/// current id, or None if the taxon was deleted
+pub fn read_merged_taxa(pb: &PathBuf) -> Result<HashMap<usize, Option<usize>>> {
+    let mut merged = HashMap::new();
+
+    let reader = open_read(pb).context("Unable to open merged taxa file")?;
+    for line in reader.lines() {
+        let line = line.context("Error reading line from merged taxa file")?;
+        let (old_id, new_id) = line
+            .split_once('\t')
+            .with_context(|| format!("Malformed line in merged taxa file: {}", line))?;
+
+        let new_id = if new_id == "\\N" {
+            None
+        } else {
+            Some(parse(new_id)?)
+        };
+        merged.insert(parse(old_id)?, new_id);
+    }
+
+    Ok(merged)
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> Result<T> {
+    value
+        .parse()
+        .map_err(|_| Error::msg(format!("Unable to parse {} as a number", value)))
+}
@@ -0,0 +1,300 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::{Context, Error, Result};
+use ncbi::{Rank, Taxon};
+use utils::open_write;
+
+use crate::taxon_list::TaxonList;
+
+/// Which part of the taxonomy is exported
+#[derive(Debug, Clone, Copy)]
+pub struct ExportOptions {
+    /// Only the subtree of this taxon is exported
+    pub root: usize,
+    /// Leave out invalid taxa (and thus their descendants)
+    pub valid_only: bool,
+    /// Leave out taxa without rank, their children are attached to their closest ranked ancestor
+    pub ranked_only: bool,
+}
+
+/// The (pruned) subtree of a taxonomy that is exported
+pub struct TaxonTree<'a> {
+    taxa: &'a TaxonList,
+    root: usize,
+    /// The children of every taxon in the tree, indexed by taxon id
+    children: Vec<Vec<usize>>,
+}
+
+impl<'a> TaxonTree<'a> {
+    pub fn new(taxa: &'a TaxonList, options: ExportOptions) -> Result<Self> {
+        if taxa.get(options.root).is_none() {
+            return Err(Error::msg(format!(
+                "Export root {} does not exist",
+                options.root
+            )));
+        }
+
+        let included = |taxon: &Taxon| {
+            (!options.valid_only || taxon.valid)
+                && (!options.ranked_only || taxon.rank != Rank::NoRank)
+        };
+
+        let mut children: Vec<Vec<usize>> = Vec::new();
+        for id in taxa.ids() {
+            // Safe to unwrap, ids only returns existing taxa
+            if id == options.root || !included(taxa.get(id).unwrap()) {
+                continue;
+            }
+
+            if let Some(parent) = closest_included_ancestor(taxa, id, options.root, &included) {
+                if children.len() <= parent {
+                    children.resize(parent + 1, Vec::new());
+                }
+                children[parent].push(id);
+            }
+        }
+
+        Ok(TaxonTree {
+            taxa,
+            root: options.root,
+            children,
+        })
+    }
+
+    fn children(&self, id: usize) -> &[usize] {
+        self.children.get(id).map_or(&[], |c| c.as_slice())
+    }
+
+    fn taxon(&self, id: usize) -> &Taxon {
+        // Only taxa that exist are part of the tree
+        self.taxa.get(id).unwrap()
+    }
+
+    /// Write the tree in Newick format, with the quoted names of the taxa as labels
+    pub fn write_newick(&self, pb: &PathBuf) -> Result<()> {
+        let mut writer = open_write(pb).context("Unable to open Newick output file")?;
+        self.newick(&mut writer, self.root)?;
+        writeln!(&mut writer, ";").context("Error writing to Newick file")
+    }
+
+    fn newick<W: Write>(&self, writer: &mut W, id: usize) -> Result<()> {
+        let children = self.children(id);
+        if !children.is_empty() {
+            write!(writer, "(").context("Error writing to Newick file")?;
+            for (i, &child) in children.iter().enumerate() {
+                if i > 0 {
+                    write!(writer, ",").context("Error writing to Newick file")?;
+                }
+                self.newick(writer, child)?;
+            }
+            write!(writer, ")").context("Error writing to Newick file")?;
+        }
+
+        write!(writer, "'{}'", self.taxon(id).name.replace('\'', "''"))
+            .context("Error writing to Newick file")
+    }
+
+    /// Write the tree as nested JSON objects with the id, name, rank, validity and children of
+    /// every taxon
+    pub fn write_json(&self, pb: &PathBuf) -> Result<()> {
+        let mut writer = open_write(pb).context("Unable to open JSON output file")?;
+        self.json(&mut writer, self.root)?;
+        writeln!(&mut writer).context("Error writing to JSON file")
+    }
+
+    fn json<W: Write>(&self, writer: &mut W, id: usize) -> Result<()> {
+        let taxon = self.taxon(id);
+        write!(
+            writer,
+            "{{\"id\":{},\"name\":\"{}\",\"rank\":\"{}\",\"valid\":{},\"children\":[",
+            id,
+            escape_json(&taxon.name),
+            escape_json(&taxon.rank_name),
+            taxon.valid
+        )
+        .context("Error writing to JSON file")?;
+
+        for (i, &child) in self.children(id).iter().enumerate() {
+            if i > 0 {
+                write!(writer, ",").context("Error writing to JSON file")?;
+            }
+            self.json(writer, child)?;
+        }
+
+        write!(writer, "]}}").context("Error writing to JSON file")
+    }
+
+    /// Write the tree in the taxonomy.tab format of Krona: id, depth, parent id, rank and name
+    /// The root is its own parent.
+    pub fn write_krona(&self, pb: &PathBuf) -> Result<()> {
+        let mut writer = open_write(pb).context("Unable to open Krona output file")?;
+        self.krona(&mut writer, self.root, 0, self.root)
+    }
+
+    fn krona<W: Write>(
+        &self,
+        writer: &mut W,
+        id: usize,
+        depth: usize,
+        parent: usize,
+    ) -> Result<()> {
+        let taxon = self.taxon(id);
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}",
+            id, depth, parent, taxon.rank_name, taxon.name
+        )
+        .context("Error writing to Krona file")?;
+
+        for &child in self.children(id) {
+            self.krona(writer, child, depth + 1, id)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Find the closest ancestor of a taxon that is part of the exported tree, or None if the taxon
+/// is not a descendant of the root
+fn closest_included_ancestor<F>(
+    taxa: &TaxonList,
+    id: usize,
+    root: usize,
+    included: &F,
+) -> Option<usize>
+where
+    F: Fn(&Taxon) -> bool,
+{
+    let mut closest = None;
+    let mut current = id;
+
+    loop {
+        let parent = taxa.parent(current)?;
+        if parent == current {
+            // We reached the top of the taxonomy without passing the root
+            return None;
+        }
+
+        if closest.is_none() && (parent == root || included(taxa.get(parent)?)) {
+            closest = Some(parent);
+        }
+        if parent == root {
+            return closest;
+        }
+
+        current = parent;
+    }
+}
+
+fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // root (1) > cellular organisms (2, no rank) > Bacteria (3) > Escherichia (4) > E. coli (5)
+    //                                                            > uncultured bacterium (6)
+    fn taxa() -> TaxonList {
+        let taxon = |name: &str, rank: Rank, parent, valid| {
+            Some(Taxon::new(
+                name.to_string(),
+                rank,
+                rank.to_string(),
+                parent,
+                valid,
+            ))
+        };
+
+        TaxonList::from_taxa(vec![
+            None,
+            taxon("root", Rank::NoRank, 1, true),
+            taxon("cellular organisms", Rank::NoRank, 1, true),
+            taxon("Bacteria", Rank::Domain, 2, true),
+            taxon("Escherichia", Rank::Genus, 3, true),
+            taxon("Escherichia coli", Rank::Species, 4, true),
+            taxon("uncultured \"bacterium\"", Rank::Species, 3, false),
+        ])
+    }
+
+    fn options(root: usize, valid_only: bool, ranked_only: bool) -> ExportOptions {
+        ExportOptions {
+            root,
+            valid_only,
+            ranked_only,
+        }
+    }
+
+    fn newick(tree: &TaxonTree) -> String {
+        let mut output = Vec::new();
+        tree.newick(&mut output, tree.root).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_newick() {
+        let taxa = taxa();
+
+        let full = TaxonTree::new(&taxa, options(1, false, false)).unwrap();
+        assert_eq!(
+            newick(&full),
+            "(((('Escherichia coli')'Escherichia','uncultured \"bacterium\"')'Bacteria')'cellular organisms')'root'"
+        );
+
+        let pruned = TaxonTree::new(&taxa, options(1, true, true)).unwrap();
+        assert_eq!(
+            newick(&pruned),
+            "((('Escherichia coli')'Escherichia')'Bacteria')'root'"
+        );
+
+        let subtree = TaxonTree::new(&taxa, options(4, false, false)).unwrap();
+        assert_eq!(newick(&subtree), "('Escherichia coli')'Escherichia'");
+
+        assert!(TaxonTree::new(&taxa, options(7, false, false)).is_err());
+    }
+
+    #[test]
+    fn test_json() {
+        let taxa = taxa();
+        let tree = TaxonTree::new(&taxa, options(3, false, false)).unwrap();
+
+        let mut output = Vec::new();
+        tree.json(&mut output, tree.root).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"id\":3,\"name\":\"Bacteria\",\"rank\":\"domain\",\"valid\":true,\"children\":[\
+             {\"id\":4,\"name\":\"Escherichia\",\"rank\":\"genus\",\"valid\":true,\"children\":[\
+             {\"id\":5,\"name\":\"Escherichia coli\",\"rank\":\"species\",\"valid\":true,\"children\":[]}]},\
+             {\"id\":6,\"name\":\"uncultured \\\"bacterium\\\"\",\"rank\":\"species\",\"valid\":false,\"children\":[]}]}"
+        );
+    }
+
+    #[test]
+    fn test_krona() {
+        let taxa = taxa();
+        let tree = TaxonTree::new(&taxa, options(1, true, true)).unwrap();
+
+        let mut output = Vec::new();
+        tree.krona(&mut output, tree.root, 0, tree.root).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "1\t0\t1\tno rank\troot\n\
+             3\t1\t1\tdomain\tBacteria\n\
+             4\t2\t3\tgenus\tEscherichia\n\
+             5\t3\t4\tspecies\tEscherichia coli\n"
+        );
+    }
+}
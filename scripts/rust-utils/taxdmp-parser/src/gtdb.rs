@@ -0,0 +1,362 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+
+use anyhow::{Context, Error, Result};
+use ncbi::{Rank, Taxon};
+use utils::{open_read, open_write};
+
+use crate::taxon_list::{TaxonList, parse_id};
+
+/// Prefixes of the ranks in a GTDB taxonomy string and the ranks they are mapped onto
+const GTDB_RANKS: [(&str, Rank); 7] = [
+    ("d__", Rank::Domain),
+    ("p__", Rank::Phylum),
+    ("c__", Rank::Class),
+    ("o__", Rank::Order),
+    ("f__", Rank::Family),
+    ("g__", Rank::Genus),
+    ("s__", Rank::Species),
+];
+
+/// GTDB taxa are identified by their rank and name, since a name can occur at multiple ranks
+type TaxonKey = (Rank, String);
+
+/// The genomes of the GTDB taxonomy, which link GTDB taxa to NCBI taxa
+pub struct GtdbTaxonomy {
+    /// Maps genome accessions onto the id of the most specific taxon they are classified in
+    genomes: HashMap<String, usize>,
+}
+
+impl GtdbTaxonomy {
+    /// Parse GTDB taxonomy files (e.g. bac120_taxonomy.tsv and ar53_taxonomy.tsv) into a
+    /// taxonomy with synthetic ids, where the root has id 1.
+    /// Taxa that occur in the taxons file of a previous run keep their id. All other taxa get a
+    /// new id, in order of rank and name, so the ids never depend on the order of the input.
+    pub fn from_files(
+        taxonomy_pbs: &[PathBuf],
+        previous_taxa: Option<&PathBuf>,
+    ) -> Result<(TaxonList, Self)> {
+        let mut readers = Vec::new();
+        for pb in taxonomy_pbs {
+            readers.push(open_read(pb).context("Unable to open GTDB taxonomy file")?);
+        }
+
+        let previous_ids = match previous_taxa {
+            Some(pb) => {
+                read_previous_ids(open_read(pb).context("Unable to open previous taxons file")?)?
+            }
+            None => HashMap::new(),
+        };
+
+        Self::from_readers(readers, previous_ids)
+    }
+
+    fn from_readers<R: BufRead>(
+        readers: Vec<R>,
+        previous_ids: HashMap<TaxonKey, usize>,
+    ) -> Result<(TaxonList, Self)> {
+        let mut parents: HashMap<TaxonKey, Option<TaxonKey>> = HashMap::new();
+        let mut genome_taxa: Vec<(String, TaxonKey)> = Vec::new();
+
+        for reader in readers {
+            for line in reader.lines() {
+                let line = line.context("Error reading line from GTDB taxonomy file")?;
+                let (accession, taxonomy) = line
+                    .split_once('\t')
+                    .with_context(|| format!("Malformed line in GTDB taxonomy file: {}", line))?;
+
+                let mut parent: Option<TaxonKey> = None;
+                for key in parse_lineage(taxonomy)? {
+                    if let Some(existing) = parents.get(&key)
+                        && *existing != parent
+                    {
+                        return Err(Error::msg(format!(
+                            "GTDB taxon {} has multiple parents",
+                            key.1
+                        )));
+                    }
+
+                    parents.insert(key.clone(), parent);
+                    parent = Some(key);
+                }
+
+                if let Some(key) = parent {
+                    genome_taxa.push((accession.to_string(), key));
+                }
+            }
+        }
+
+        let ids = assign_ids(parents.keys(), previous_ids);
+        let max_id = ids.values().copied().max().unwrap_or(1);
+
+        let mut entries: Vec<Option<Taxon>> = (0..=max_id).map(|_| None).collect();
+        entries[1] = Some(Taxon::new(
+            "root".to_string(),
+            Rank::NoRank,
+            Rank::NoRank.to_string(),
+            1,
+            true,
+        ));
+        for ((rank, name), parent) in parents {
+            let id = ids[&(rank, name.clone())];
+            let parent_id = parent.map_or(1, |p| ids[&p]);
+            entries[id] = Some(Taxon::new(name, rank, rank.to_string(), parent_id, true));
+        }
+
+        let genomes = genome_taxa
+            .into_iter()
+            .map(|(accession, key)| (accession, ids[&key]))
+            .collect();
+
+        Ok((TaxonList::from_taxa(entries), GtdbTaxonomy { genomes }))
+    }
+
+    /// Write a TSV file that maps NCBI taxon ids onto GTDB taxon ids, in the same layout as the
+    /// merged taxa file. The GTDB metadata files (e.g. bac120_metadata.tsv) link every genome to
+    /// its NCBI taxon and species. NCBI taxa with genomes in multiple GTDB taxa are mapped onto
+    /// the lowest common ancestor of those taxa.
+    pub fn write_ncbi_mapping(
+        &self,
+        taxa: &TaxonList,
+        metadata_pbs: &[PathBuf],
+        pb: &PathBuf,
+    ) -> Result<()> {
+        let mut mapping: HashMap<usize, usize> = HashMap::new();
+        for metadata_pb in metadata_pbs {
+            let reader = open_read(metadata_pb).context("Unable to open GTDB metadata file")?;
+            self.add_ncbi_mapping(taxa, reader, &mut mapping)?;
+        }
+
+        let mut ncbi_ids: Vec<&usize> = mapping.keys().collect();
+        ncbi_ids.sort_unstable();
+
+        let mut writer = open_write(pb).context("Unable to open GTDB mapping output file")?;
+        for ncbi_id in ncbi_ids {
+            writeln!(&mut writer, "{}\t{}", ncbi_id, mapping[ncbi_id])
+                .context("Error writing to GTDB mapping TSV file")?;
+        }
+
+        Ok(())
+    }
+
+    fn add_ncbi_mapping<R: BufRead>(
+        &self,
+        taxa: &TaxonList,
+        reader: R,
+        mapping: &mut HashMap<usize, usize>,
+    ) -> Result<()> {
+        let mut lines = reader.lines();
+        let header = lines
+            .next()
+            .context("GTDB metadata file is empty")?
+            .context("Error reading header of GTDB metadata file")?;
+        let columns: Vec<&str> = header.split('\t').collect();
+        let column = |name: &str| {
+            columns
+                .iter()
+                .position(|&c| c == name)
+                .with_context(|| format!("GTDB metadata file has no {} column", name))
+        };
+
+        let accession_column = column("accession")?;
+        let ncbi_columns = [column("ncbi_taxid")?, column("ncbi_species_taxid")?];
+
+        for line in lines {
+            let line = line.context("Error reading line from GTDB metadata file")?;
+            let fields: Vec<&str> = line.split('\t').collect();
+
+            let gtdb_id = match fields
+                .get(accession_column)
+                .and_then(|a| self.genomes.get(*a))
+            {
+                Some(&id) => id,
+                None => continue,
+            };
+
+            for &ncbi_column in &ncbi_columns {
+                // Genomes without NCBI taxon have "none" in these columns
+                let ncbi_id = match fields.get(ncbi_column).map(|v| v.parse::<usize>()) {
+                    Some(Ok(id)) => id,
+                    _ => continue,
+                };
+
+                mapping
+                    .entry(ncbi_id)
+                    .and_modify(|id| *id = lca(taxa, *id, gtdb_id))
+                    .or_insert(gtdb_id);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn lca(taxa: &TaxonList, first: usize, second: usize) -> usize {
+    let mut ancestors = HashSet::new();
+    let mut id = first;
+    while ancestors.insert(id) {
+        id = taxa.parent(id).unwrap_or(1);
+    }
+
+    let mut id = second;
+    while !ancestors.contains(&id) {
+        id = taxa.parent(id).unwrap_or(1);
+    }
+
+    id
+}
+
+/// Parse a GTDB taxonomy string (e.g. "d__Bacteria;p__Pseudomonadota;...;s__Escherichia coli")
+/// Ranks without a name are skipped.
+fn parse_lineage(taxonomy: &str) -> Result<Vec<TaxonKey>> {
+    let mut lineage = Vec::new();
+
+    for part in taxonomy.trim().split(';') {
+        let part = part.trim();
+        let &(prefix, rank) = GTDB_RANKS
+            .iter()
+            .find(|(prefix, _)| part.starts_with(prefix))
+            .with_context(|| format!("Unknown GTDB rank in {}", part))?;
+
+        let name = &part[prefix.len()..];
+        if !name.is_empty() {
+            lineage.push((rank, name.to_string()));
+        }
+    }
+
+    Ok(lineage)
+}
+
+/// Read the ids of the GTDB taxa in the taxons file of a previous run
+fn read_previous_ids<R: BufRead>(reader: R) -> Result<HashMap<TaxonKey, usize>> {
+    let mut ids = HashMap::new();
+
+    for line in reader.lines() {
+        let line = line.context("Error reading line from previous taxons file")?;
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 3 {
+            return Err(Error::msg(format!(
+                "Malformed line in previous taxons file: {}",
+                line
+            )));
+        }
+
+        let id = parse_id(fields[0])?;
+        match Rank::from_ncbi(fields[2]) {
+            Some(rank) if id != 1 => {
+                ids.insert((rank, fields[1].to_string()), id);
+            }
+            _ => continue,
+        }
+    }
+
+    Ok(ids)
+}
+
+/// Keep the previous id of every taxon that still exists, and number the new taxa after the
+/// highest previous id
+fn assign_ids<'a, I>(keys: I, previous_ids: HashMap<TaxonKey, usize>) -> HashMap<TaxonKey, usize>
+where
+    I: Iterator<Item = &'a TaxonKey>,
+{
+    let mut next_id = previous_ids.values().copied().max().unwrap_or(1) + 1;
+    let mut ids = HashMap::new();
+    let mut new_keys = Vec::new();
+
+    for key in keys {
+        match previous_ids.get(key) {
+            Some(&id) => {
+                ids.insert(key.clone(), id);
+            }
+            None => new_keys.push(key),
+        }
+    }
+
+    new_keys.sort_unstable_by(|a, b| (a.0.index(), &a.1).cmp(&(b.0.index(), &b.1)));
+    for key in new_keys {
+        ids.insert(key.clone(), next_id);
+        next_id += 1;
+    }
+
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TAXONOMY: &str = "\
+RS_GCF_000005845.2\td__Bacteria;p__Pseudomonadota;c__Gammaproteobacteria;o__Enterobacterales;f__Enterobacteriaceae;g__Escherichia;s__Escherichia coli
+RS_GCF_000008865.2\td__Bacteria;p__Pseudomonadota;c__Gammaproteobacteria;o__Enterobacterales;f__Enterobacteriaceae;g__Escherichia;s__Escherichia fergusonii
+GB_GCA_000010225.1\td__Archaea;p__Methanobacteriota;c__Methanobacteria;o__Methanobacteriales;f__Methanobacteriaceae;g__Methanobrevibacter;s__
+";
+
+    fn taxonomy(previous_ids: HashMap<TaxonKey, usize>) -> (TaxonList, GtdbTaxonomy) {
+        GtdbTaxonomy::from_readers(vec![TAXONOMY.as_bytes()], previous_ids).unwrap()
+    }
+
+    fn key(rank: Rank, name: &str) -> TaxonKey {
+        (rank, name.to_string())
+    }
+
+    #[test]
+    fn test_ids_are_independent_of_order() {
+        let lines: Vec<&str> = TAXONOMY.lines().rev().collect();
+        let reversed = lines.join("\n");
+        let (taxa, first) = taxonomy(HashMap::new());
+        let (_, second) =
+            GtdbTaxonomy::from_readers(vec![reversed.as_bytes()], HashMap::new()).unwrap();
+
+        assert_eq!(first.genomes, second.genomes);
+        // Domains get the first ids, in alphabetical order
+        assert_eq!(taxa.parent(2), Some(1));
+        assert_eq!(taxa.parent(3), Some(1));
+    }
+
+    #[test]
+    fn test_previous_ids_are_kept() {
+        let previous = HashMap::from([(key(Rank::Species, "Escherichia coli"), 500)]);
+        let (_, gtdb) = taxonomy(previous);
+
+        assert_eq!(gtdb.genomes["RS_GCF_000005845.2"], 500);
+        // New taxa are numbered after the highest previous id
+        assert!(gtdb.genomes["RS_GCF_000008865.2"] > 500);
+    }
+
+    #[test]
+    fn test_ncbi_mapping() {
+        let (taxa, gtdb) = taxonomy(HashMap::new());
+        let metadata = "\
+accession\tncbi_species_taxid\tncbi_taxid
+RS_GCF_000005845.2\t562\t511145
+RS_GCF_000008865.2\t562\t585054
+GB_GCA_000010225.1\t2173\tnone
+";
+        let mut mapping = HashMap::new();
+        gtdb.add_ncbi_mapping(&taxa, metadata.as_bytes(), &mut mapping)
+            .unwrap();
+
+        let coli = gtdb.genomes["RS_GCF_000005845.2"];
+        let fergusonii = gtdb.genomes["RS_GCF_000008865.2"];
+        let escherichia = taxa.parent(coli).unwrap();
+
+        assert_eq!(mapping[&511145], coli);
+        assert_eq!(mapping[&585054], fergusonii);
+        assert_eq!(mapping[&562], escherichia);
+        assert_eq!(mapping[&2173], gtdb.genomes["GB_GCA_000010225.1"]);
+        assert_eq!(mapping.len(), 4);
+    }
+
+    #[test]
+    fn test_parse_lineage() {
+        assert_eq!(
+            parse_lineage("d__Archaea;p__Halobacteriota;s__").unwrap(),
+            vec![
+                key(Rank::Domain, "Archaea"),
+                key(Rank::Phylum, "Halobacteriota")
+            ]
+        );
+        assert!(parse_lineage("x__Nope").is_err());
+    }
+}
@@ -0,0 +1,6 @@
+pub mod export;
+pub mod gtdb;
+pub mod merged_taxa;
+pub mod taxon_list;
+pub mod taxon_names;
+pub mod validation_rules;
@@ -1,18 +1,45 @@
-use crate::taxon_list::TaxonList;
 use anyhow::{Context, Result};
 use clap::Parser;
 use std::path::PathBuf;
-
-mod taxon_list;
+use taxdmp_parser::export::{ExportOptions, TaxonTree};
+use taxdmp_parser::gtdb::GtdbTaxonomy;
+use taxdmp_parser::merged_taxa::MergedTaxa;
+use taxdmp_parser::taxon_list::TaxonList;
+use taxdmp_parser::taxon_names::write_taxon_names;
+use taxdmp_parser::validation_rules::ValidationRules;
 
 fn main() -> Result<()> {
     let args = Cli::parse();
 
-    let mut taxon_list = TaxonList::from_dumps(&args.names, &args.nodes)
-        .context("Failed to parse TaxonList from dumps")?;
+    let mut gtdb = None;
+    let mut taxon_list = match (&args.names, &args.nodes) {
+        (Some(names), Some(nodes)) => {
+            TaxonList::from_dumps(names, nodes).context("Failed to parse TaxonList from dumps")?
+        }
+        _ => {
+            let (taxa, genomes) =
+                GtdbTaxonomy::from_files(&args.gtdb_taxonomy, args.gtdb_previous_taxa.as_ref())
+                    .context("Failed to parse GTDB taxonomy")?;
+            gtdb = Some(genomes);
+            taxa
+        }
+    };
+
+    // The default rules are tailored to the NCBI naming conventions
+    let rules = match &args.validation_rules {
+        Some(pb) => ValidationRules::from_file(pb)?,
+        None if gtdb.is_some() => ValidationRules::none(),
+        None => ValidationRules::default_rules()?,
+    };
+
     taxon_list
-        .invalidate()
+        .invalidate(&rules)
         .context("Failed to validate TaxonList")?;
+    if let Some(report) = &args.validation_report {
+        taxon_list
+            .write_validation_report(report, &rules)
+            .context("Failed to write validation report")?;
+    }
     taxon_list
         .write_taxons(&args.taxa)
         .context("Failed to write TaxonList")?;
@@ -20,18 +47,80 @@ fn main() -> Result<()> {
         .write_lineages(&args.lineages)
         .context("Failed to write lineages")?;
 
+    if args.newick.is_some() || args.json_tree.is_some() || args.krona.is_some() {
+        let tree = TaxonTree::new(
+            &taxon_list,
+            ExportOptions {
+                root: args.export_root,
+                valid_only: args.export_valid_only,
+                ranked_only: args.export_ranked_only,
+            },
+        )
+        .context("Failed to build taxonomy tree for export")?;
+
+        if let Some(newick) = &args.newick {
+            tree.write_newick(newick)
+                .context("Failed to write Newick tree")?;
+        }
+        if let Some(json_tree) = &args.json_tree {
+            tree.write_json(json_tree)
+                .context("Failed to write JSON tree")?;
+        }
+        if let Some(krona) = &args.krona {
+            tree.write_krona(krona)
+                .context("Failed to write Krona taxonomy")?;
+        }
+    }
+
+    if let (Some(taxon_names), Some(names)) = (&args.taxon_names, &args.names) {
+        write_taxon_names(names, taxon_names).context("Failed to write taxon names")?;
+    }
+
+    if let (Some(gtdb), Some(mapping)) = (&gtdb, &args.gtdb_ncbi_mapping) {
+        gtdb.write_ncbi_mapping(&taxon_list, &args.gtdb_metadata, mapping)
+            .context("Failed to write mapping from NCBI onto GTDB taxa")?;
+    }
+
+    if let (Some(merged), Some(delnodes), Some(merged_taxa)) =
+        (&args.merged, &args.delnodes, &args.merged_taxa)
+    {
+        MergedTaxa::from_dumps(merged, delnodes)
+            .context("Failed to parse merged and deleted taxa from dumps")?
+            .write(merged_taxa)
+            .context("Failed to write merged taxa")?;
+    }
+
     Ok(())
 }
 
 #[derive(Parser, Debug)]
 struct Cli {
     /// Path to the names.dmp file
-    #[clap(long)]
-    names: PathBuf,
+    #[clap(long, required_unless_present = "gtdb_taxonomy", requires = "nodes")]
+    names: Option<PathBuf>,
 
     /// Path to the nodes.dmp file
-    #[clap(long)]
-    nodes: PathBuf,
+    #[clap(long, required_unless_present = "gtdb_taxonomy", requires = "names")]
+    nodes: Option<PathBuf>,
+
+    /// Path to a GTDB taxonomy file (e.g. bac120_taxonomy.tsv), used instead of the NCBI dumps.
+    /// Can be given multiple times
+    #[clap(long, conflicts_with_all = ["names", "nodes", "taxon_names", "merged"])]
+    gtdb_taxonomy: Vec<PathBuf>,
+
+    /// Path to the taxa file of a previous run on the GTDB taxonomy. GTDB taxa that occur in it
+    /// keep their id, so ids remain stable across GTDB releases
+    #[clap(long, requires = "gtdb_taxonomy")]
+    gtdb_previous_taxa: Option<PathBuf>,
+
+    /// Path to a GTDB metadata file (e.g. bac120_metadata.tsv), used to map NCBI taxa onto GTDB
+    /// taxa. Can be given multiple times
+    #[clap(long, requires_all = ["gtdb_taxonomy", "gtdb_ncbi_mapping"])]
+    gtdb_metadata: Vec<PathBuf>,
+
+    /// Path to the output file that maps NCBI taxon ids onto GTDB taxon ids
+    #[clap(long, requires = "gtdb_metadata")]
+    gtdb_ncbi_mapping: Option<PathBuf>,
 
     /// Path to the output taxa file
     #[clap(long)]
@@ -40,4 +129,58 @@ struct Cli {
     /// Path to the output lineages file
     #[clap(long)]
     lineages: PathBuf,
+
+    /// Path to a TOML file with the rules that invalidate taxa, instead of the default rules.
+    /// No taxa are invalidated by default when using the GTDB taxonomy
+    #[clap(long)]
+    validation_rules: Option<PathBuf>,
+
+    /// Path to the output file that lists every invalid taxon (id, name, rank), the rule that
+    /// invalidated it and the ancestor it inherited this from (or \N)
+    #[clap(long)]
+    validation_report: Option<PathBuf>,
+
+    /// Path to the output file for the taxonomy tree in Newick format
+    #[clap(long)]
+    newick: Option<PathBuf>,
+
+    /// Path to the output file for the taxonomy tree as nested JSON objects with the id, name,
+    /// rank, validity and children of every taxon
+    #[clap(long)]
+    json_tree: Option<PathBuf>,
+
+    /// Path to the output file for the taxonomy in the taxonomy.tab format of Krona (id, depth,
+    /// parent, rank and name)
+    #[clap(long)]
+    krona: Option<PathBuf>,
+
+    /// Id of the taxon of which the subtree is exported to Newick, JSON and Krona
+    #[clap(long, default_value_t = 1)]
+    export_root: usize,
+
+    /// Leave invalid taxa out of the exported tree
+    #[clap(long)]
+    export_valid_only: bool,
+
+    /// Leave taxa without rank out of the exported tree, their children are attached to their
+    /// closest ranked ancestor
+    #[clap(long)]
+    export_ranked_only: bool,
+
+    /// Path to the output file with all names of every taxon (taxon id, name, name class and
+    /// unique name), including synonyms and common names
+    #[clap(long)]
+    taxon_names: Option<PathBuf>,
+
+    /// Path to the merged.dmp file
+    #[clap(long, requires_all = ["delnodes", "merged_taxa"])]
+    merged: Option<PathBuf>,
+
+    /// Path to the delnodes.dmp file
+    #[clap(long, requires_all = ["merged", "merged_taxa"])]
+    delnodes: Option<PathBuf>,
+
+    /// Path to the output file that maps merged and deleted taxa onto their current id
+    #[clap(long, requires_all = ["merged", "delnodes"])]
+    merged_taxa: Option<PathBuf>,
 }
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use utils::{open_read, open_write};
+
+use crate::taxon_list::parse_id;
+
+/// Taxon ids that NCBI has merged into another node or deleted altogether
+pub struct MergedTaxa {
+    merged: HashMap<usize, usize>,
+    deleted: Vec<usize>,
+}
+
+impl MergedTaxa {
+    /// Parse the merged.dmp and delnodes.dmp files from a taxonomy dump
+    pub fn from_dumps(merged_pb: &PathBuf, delnodes_pb: &PathBuf) -> Result<Self> {
+        let merged = open_read(merged_pb).context("Unable to open merged dump file")?;
+        let delnodes = open_read(delnodes_pb).context("Unable to open delnodes dump file")?;
+
+        Self::from_readers(merged, delnodes)
+    }
+
+    fn from_readers<M: BufRead, D: BufRead>(merged_reader: M, delnodes_reader: D) -> Result<Self> {
+        let mut merged = HashMap::new();
+        let mut deleted = Vec::new();
+
+        for line in merged_reader.lines() {
+            let line = line.context("Error reading line from merged dump file")?;
+            let row: Vec<&str> = line.split('|').collect();
+            let target = row
+                .get(1)
+                .with_context(|| format!("Malformed line in merged dump file: {}", line))?;
+
+            merged.insert(
+                parse_id(row[0])
+                    .with_context(|| format!("Malformed line in merged dump file: {}", line))?,
+                parse_id(target)
+                    .with_context(|| format!("Malformed line in merged dump file: {}", line))?,
+            );
+        }
+
+        for line in delnodes_reader.lines() {
+            let line = line.context("Error reading line from delnodes dump file")?;
+            let id = line.split('|').next().unwrap_or_default();
+
+            deleted.push(
+                parse_id(id)
+                    .with_context(|| format!("Malformed line in delnodes dump file: {}", line))?,
+            );
+        }
+        deleted.sort_unstable();
+
+        Ok(MergedTaxa { merged, deleted })
+    }
+
+    /// Follow a chain of merges until we end up at a taxon that was not merged itself
+    /// Returns None if that taxon has been deleted.
+    fn resolve(&self, mut id: usize) -> Option<usize> {
+        // Bound the amount of steps, in case the dump would ever contain a cycle
+        for _ in 0..self.merged.len() {
+            match self.merged.get(&id) {
+                Some(&target) if target != id => id = target,
+                _ => break,
+            }
+        }
+
+        if self.deleted.binary_search(&id).is_ok() {
+            return None;
+        }
+
+        Some(id)
+    }
+
+    /// Write a TSV file mapping every old taxon id onto its current target.
+    /// Deleted taxa, and taxa that were merged into a deleted taxon, are mapped onto \N
+    pub fn write(&self, pb: &PathBuf) -> Result<()> {
+        let mut writer = open_write(pb).context("Unable to open merged taxa output file")?;
+
+        let mut old_ids: Vec<&usize> = self.merged.keys().collect();
+        old_ids.sort_unstable();
+
+        for &old_id in old_ids {
+            match self.resolve(old_id) {
+                Some(target) => writeln!(&mut writer, "{}\t{}", old_id, target),
+                None => writeln!(&mut writer, "{}\t\\N", old_id),
+            }
+            .context("Error writing to merged taxa TSV file")?;
+        }
+
+        for old_id in &self.deleted {
+            writeln!(&mut writer, "{}\t\\N", old_id)
+                .context("Error writing to merged taxa TSV file")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merged_taxa(merged: &str, delnodes: &str) -> Result<MergedTaxa> {
+        MergedTaxa::from_readers(merged.as_bytes(), delnodes.as_bytes())
+    }
+
+    #[test]
+    fn test_resolve_merge_chains() {
+        // 12 was merged into 13, which was merged into 14 later on
+        let taxa = merged_taxa(
+            "12\t|\t13\t|\n13\t|\t14\t|\n20\t|\t21\t|\n30\t|\t31\t|\n",
+            "21\t|\n99\t|\n",
+        )
+        .unwrap();
+
+        assert_eq!(taxa.resolve(12), Some(14));
+        assert_eq!(taxa.resolve(13), Some(14));
+        assert_eq!(taxa.resolve(14), Some(14));
+        assert_eq!(taxa.resolve(30), Some(31));
+        // Merged into a taxon that was deleted afterwards
+        assert_eq!(taxa.resolve(20), None);
+        assert_eq!(taxa.resolve(99), None);
+    }
+
+    #[test]
+    fn test_resolve_cycle() {
+        let taxa = merged_taxa("1\t|\t2\t|\n2\t|\t1\t|\n", "").unwrap();
+        assert!(taxa.resolve(1).is_some());
+    }
+
+    #[test]
+    fn test_malformed_lines() {
+        assert!(merged_taxa("12\t|\t13\t|\n\n", "").is_err());
+        assert!(merged_taxa("12\n", "").is_err());
+        assert!(merged_taxa("12\t|\tx\t|\n", "").is_err());
+        assert!(merged_taxa("", "21\t|\n\n").is_err());
+    }
+
+    #[test]
+    fn test_write() {
+        let taxa = merged_taxa("13\t|\t14\t|\n12\t|\t13\t|\n20\t|\t21\t|\n", "21\t|\n").unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let pb = dir.path().join("merged_taxa.tsv");
+        taxa.write(&pb).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&pb).unwrap(),
+            "12\t14\n13\t14\n20\t\\N\n21\t\\N\n"
+        );
+    }
+}
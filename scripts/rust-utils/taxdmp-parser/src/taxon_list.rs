@@ -1,114 +1,132 @@
-use std::io::{BufRead, Read, Write};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
 use std::path::PathBuf;
-use std::str::FromStr;
 
 use anyhow::{Context, Error, Result};
 use ncbi::{Rank, Taxon};
-use regex::Regex;
 use strum::IntoEnumIterator;
 use utils::{open_read, open_write};
 
+use crate::validation_rules::ValidationRules;
+
+const DUMP_SEPARATOR: &str = "|";
+const SCIENTIFIC_NAME: &str = "scientific name";
+/// Maximum amount of taxa without a scientific name that are listed in the warning
+const MAX_REPORTED_TAXA: usize = 20;
+
 pub struct TaxonList {
     entries: Vec<Option<Taxon>>,
-    validation_regex: Regex,
+    invalid_causes: HashMap<usize, InvalidCause>,
+}
+
+/// Why a taxon was marked as invalid
+#[derive(Clone, Copy)]
+enum InvalidCause {
+    /// The taxon matched the validation rule with this index
+    Rule(usize),
+    /// This ancestor matched a validation rule
+    Ancestor(usize),
+    /// This ancestor does not occur in the nodes dump
+    MissingAncestor(usize),
 }
 
 impl TaxonList {
     /// Parse a list of Taxons from the names and nodes dumps
+    /// Both dumps may be in any order. Taxa without a scientific name are kept with an empty
+    /// name, so their descendants still have a complete lineage, and are reported at the end.
+    /// Unknown ranks are reported as well, and treated as "no rank".
     pub fn from_dumps(names_pb: &PathBuf, nodes_pb: &PathBuf) -> Result<Self> {
-        let scientific_name = "scientific name";
-        let pattern = "|";
-
-        let mut entries = vec![];
+        let names = open_read(names_pb).context("Unable to open names dump file")?;
+        let mut scientific_names = read_scientific_names(names)?;
 
-        let mut names = open_read(names_pb).context("Unable to open names dump file")?;
         let nodes = open_read(nodes_pb).context("Unable to open nodes dump file")?;
+        let mut entries = vec![];
+        let mut unnamed = vec![];
+        let mut unknown_ranks: HashMap<String, usize> = HashMap::new();
 
         for node_line in nodes.lines() {
             let node_line = node_line.context("Error reading line from nodes dump file")?;
-            let node_row: Vec<&str> = node_line.split(pattern).collect();
+            let node_row: Vec<&str> = node_line.split(DUMP_SEPARATOR).collect();
 
             let taxon_id = parse_id(node_row[0])?;
             let parent_id = parse_id(node_row[1])?;
 
-            let rank = Rank::from_str(node_row[2].trim())
-                .context("Unable to parse Taxon Rank".to_owned() + node_row[2])?;
-
-            let mut name = String::new();
-            let mut clas = String::new();
-            let mut taxon_id2 = usize::MAX;
+            let rank_name = node_row[2].trim().to_string();
+            let rank = Rank::from_ncbi(&rank_name).unwrap_or_else(|| {
+                *unknown_ranks.entry(rank_name.clone()).or_default() += 1;
+                Rank::NoRank
+            });
 
-            for name_line in names.by_ref().lines() {
-                let name_line = name_line.context("Error reading line from names dump file")?;
-                let name_row: Vec<&str> = name_line.split(pattern).collect();
-                taxon_id2 = parse_id(name_row[0])?;
-                name = name_row[1].trim().to_string();
-                clas = name_row[3].trim().to_string();
+            let name = scientific_names.remove(&taxon_id).unwrap_or_else(|| {
+                unnamed.push(taxon_id);
+                String::new()
+            });
 
-                if clas == scientific_name {
-                    break;
-                }
+            while entries.len() <= taxon_id {
+                entries.push(None);
             }
 
-            if clas == scientific_name && taxon_id == taxon_id2 {
-                while entries.len() <= taxon_id {
-                    entries.push(None);
-                }
-
-                entries[taxon_id] = Some(Taxon::new(name, rank, parent_id, true));
-            } else {
-                return Err(Error::msg(format!(
-                    "Taxon {} did not have a scientific name",
-                    taxon_id
-                )));
-            }
+            entries[taxon_id] = Some(Taxon::new(name, rank, rank_name, parent_id, true));
         }
 
-        Ok(TaxonList {
+        report_unnamed_taxa(&mut unnamed);
+        report_unknown_ranks(&unknown_ranks);
+
+        Ok(Self::from_taxa(entries))
+    }
+
+    /// Create a TaxonList from taxa that are indexed by their id
+    pub fn from_taxa(entries: Vec<Option<Taxon>>) -> Self {
+        TaxonList {
             entries,
-            validation_regex: Regex::new(r".*\d.*").context("Failed to initialize regex")?,
-        })
+            invalid_causes: HashMap::new(),
+        }
     }
 
-    pub fn invalidate(&mut self) -> Result<()> {
+    /// Get the taxon with the given id, or None if it does not exist
+    pub fn get(&self, id: usize) -> Option<&Taxon> {
+        self.entries.get(id)?.as_ref()
+    }
+
+    /// The ids of all taxa, in increasing order
+    pub fn ids(&self) -> impl Iterator<Item = usize> + '_ {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(id, taxon)| taxon.as_ref().map(|_| id))
+    }
+
+    /// Get the id of the parent of a taxon, or None if the taxon does not exist
+    pub fn parent(&self, id: usize) -> Option<usize> {
+        self.entries.get(id)?.as_ref().map(|t| t.parent)
+    }
+
+    /// Mark the taxa that match one of the validation rules as invalid, together with all of
+    /// their descendants
+    pub fn invalidate(&mut self, rules: &ValidationRules) -> Result<()> {
         for i in 0..self.entries.len() {
-            self.validate(i)?;
+            self.validate(i, rules)?;
         }
 
         Ok(())
     }
 
-    fn validate(&mut self, id: usize) -> Result<bool> {
+    fn validate(&mut self, id: usize, rules: &ValidationRules) -> Result<bool> {
         let taxon = self
             .entries
-            .get_mut(id)
+            .get(id)
             .with_context(|| format!("Missing Taxon with id {}", id))?;
         let taxon = match taxon {
             Some(t) => t,
             None => return Ok(false),
         };
 
-        if !taxon.valid
-            || (taxon.rank == Rank::Species
-                && ((self.validation_regex.is_match(taxon.name.as_str())
-                    && !taxon.name.contains("virus"))
-                    || taxon.name.ends_with(" sp.")
-                    || taxon.name.ends_with(" genomosp.")
-                    || taxon.name.contains(" bacterium")))
-            || taxon.name.contains("enrichment culture")
-            || taxon.name.contains("mixed culture")
-            || taxon.name.contains("uncultured")
-            || taxon.name.contains("unidentified")
-            || taxon.name.contains("unspecified")
-            || taxon.name.contains("undetermined")
-            || taxon.name.contains("sample")
-            || taxon.name.ends_with("metagenome")
-            || taxon.name.ends_with("library")
-            || id == 28384
-            || id == 48479
-            || id == 1869227
-        {
-            taxon.valid = false;
+        if !taxon.valid {
+            return Ok(false);
+        }
+
+        if let Some(rule) = rules.matching_rule(id, taxon, |tid| self.parent(tid)) {
+            self.mark_invalid(id, InvalidCause::Rule(rule));
             return Ok(false);
         }
 
@@ -117,25 +135,60 @@ impl TaxonList {
         }
 
         let parent = taxon.parent;
-        let parent_valid = self.validate(parent)?;
+        if self.validate(parent, rules)? {
+            return Ok(true);
+        }
 
-        // I don't like this duplication but we have to do it because of the borrow checker
-        // Otherwise, the recursive call above ^ will cause two mutable references at the same time
-        // And we need one to mark the taxon as invalid
-        let taxon = self
-            .entries
-            .get_mut(id)
-            .with_context(|| format!("Missing taxon with id {}", id))?;
-        let taxon = match taxon {
-            Some(t) => t,
-            None => return Ok(false),
+        let cause = match self.invalid_causes.get(&parent) {
+            Some(InvalidCause::Rule(_)) => InvalidCause::Ancestor(parent),
+            Some(&cause) => cause,
+            None => InvalidCause::MissingAncestor(parent),
         };
+        self.mark_invalid(id, cause);
+
+        Ok(false)
+    }
 
-        if !parent_valid {
+    fn mark_invalid(&mut self, id: usize, cause: InvalidCause) {
+        if let Some(Some(taxon)) = self.entries.get_mut(id) {
             taxon.valid = false;
         }
+        self.invalid_causes.insert(id, cause);
+    }
+
+    /// Write a TSV file with every invalid taxon (id, name and rank), the rule that invalidated
+    /// it and, if the taxon is invalid because of one of its ancestors, the id of that ancestor
+    pub fn write_validation_report(&self, pb: &PathBuf, rules: &ValidationRules) -> Result<()> {
+        let mut writer = open_write(pb).context("Unable to open validation report file")?;
 
-        Ok(taxon.valid)
+        for (id, taxon) in self.entries.iter().enumerate() {
+            let (taxon, cause) = match (taxon, self.invalid_causes.get(&id)) {
+                (Some(t), Some(cause)) => (t, cause),
+                _ => continue,
+            };
+
+            let (rule, ancestor) = match *cause {
+                InvalidCause::Rule(rule) => (rules.rule_name(rule), "\\N".to_string()),
+                InvalidCause::Ancestor(ancestor) => match self.invalid_causes.get(&ancestor) {
+                    Some(&InvalidCause::Rule(rule)) => {
+                        (rules.rule_name(rule), ancestor.to_string())
+                    }
+                    _ => ("\\N", ancestor.to_string()),
+                },
+                InvalidCause::MissingAncestor(ancestor) => {
+                    ("missing ancestor", ancestor.to_string())
+                }
+            };
+
+            writeln!(
+                &mut writer,
+                "{}\t{}\t{}\t{}\t{}",
+                id, taxon.name, taxon.rank_name, rule, ancestor
+            )
+            .context("Error writing to validation report")?;
+        }
+
+        Ok(())
     }
 
     pub fn write_taxons(&self, pb: &PathBuf) -> Result<()> {
@@ -153,7 +206,7 @@ impl TaxonList {
             writeln!(
                 &mut writer,
                 "{}\t{}\t{}\t{}\t{}",
-                id, taxon.name, taxon.rank, taxon.parent, valid
+                id, taxon.name, taxon.rank_name, taxon.parent, valid
             )
             .context("Error writing to taxon TSV file")?;
         }
@@ -163,42 +216,52 @@ impl TaxonList {
 
     pub fn write_lineages(&self, pb: &PathBuf) -> Result<()> {
         let mut writer = open_write(pb).context("Unable to open lineage output file")?;
-        let n_ranks = Rank::iter().count();
-
-        for (i, taxon) in self.entries.iter().enumerate() {
-            if taxon.is_none() {
-                continue;
-            }
-
-            let mut lineage: Vec<String> = vec![String::from("\\N"); n_ranks];
-            lineage[0] = i.to_string();
-
-            let mut tid = self.ranked_ancestor(i)?;
-            let mut taxon = self.get_taxon_some(tid)?;
-            let mut valid = taxon.valid;
 
-            for j in (1..=(n_ranks - 1)).rev() {
-                if j > taxon.rank.index() {
-                    lineage[j] = if valid {
+        for id in self.ids() {
+            let lineage: Vec<String> = self
+                .lineage(id)?
+                .iter()
+                .map(|&tid| {
+                    if tid == 0 {
                         "\\N".to_string()
                     } else {
-                        "-1".to_string()
-                    };
-                } else {
-                    valid = taxon.valid;
-                    lineage[j] = (if valid { 1 } else { -1 } * (tid as i32)).to_string();
-                    tid = self.ranked_ancestor(taxon.parent)?;
-                    taxon = self.get_taxon_some(tid)?;
-                }
-            }
+                        tid.to_string()
+                    }
+                })
+                .collect();
 
-            writeln!(&mut writer, "{}", lineage.join("\t"))
+            writeln!(&mut writer, "{}\t{}", id, lineage.join("\t"))
                 .context("Error writing to lineage TSV file")?;
         }
 
         Ok(())
     }
 
+    /// Get the ancestor of a taxon at every rank except "no rank", or 0 if it has none at that
+    /// rank. Invalid ancestors are negated, and ranks without ancestor below an invalid ancestor
+    /// are -1.
+    pub fn lineage(&self, id: usize) -> Result<Vec<i32>> {
+        let n_ranks = Rank::iter().count();
+        let mut lineage = vec![0; n_ranks - 1];
+
+        let mut tid = self.ranked_ancestor(id)?;
+        let mut taxon = self.get_taxon_some(tid)?;
+        let mut valid = taxon.valid;
+
+        for j in (1..=(n_ranks - 1)).rev() {
+            if j > taxon.rank.index() {
+                lineage[j - 1] = if valid { 0 } else { -1 };
+            } else {
+                valid = taxon.valid;
+                lineage[j - 1] = if valid { 1 } else { -1 } * (tid as i32);
+                tid = self.ranked_ancestor(taxon.parent)?;
+                taxon = self.get_taxon_some(tid)?;
+            }
+        }
+
+        Ok(lineage)
+    }
+
     fn ranked_ancestor(&self, mut tid: usize) -> Result<usize> {
         let mut taxon = self.get_taxon(tid)?;
         let mut pid = usize::MAX;
@@ -234,8 +297,93 @@ impl TaxonList {
     }
 }
 
-fn parse_id(v: &str) -> Result<usize> {
+/// Build an index of the scientific name of every taxon in the names dump
+fn read_scientific_names<R: BufRead>(reader: R) -> Result<HashMap<usize, String>> {
+    let mut names = HashMap::new();
+
+    for name_line in reader.lines() {
+        let name_line = name_line.context("Error reading line from names dump file")?;
+        let name_row: Vec<&str> = name_line.split(DUMP_SEPARATOR).collect();
+
+        let class = name_row
+            .get(3)
+            .with_context(|| format!("Malformed line in names dump file: {}", name_line))?;
+        if class.trim() != SCIENTIFIC_NAME {
+            continue;
+        }
+
+        // NCBI guarantees a single scientific name per taxon, keep the first one otherwise
+        names
+            .entry(parse_id(name_row[0])?)
+            .or_insert_with(|| name_row[1].trim().to_string());
+    }
+
+    Ok(names)
+}
+
+fn report_unnamed_taxa(unnamed: &mut [usize]) {
+    if unnamed.is_empty() {
+        return;
+    }
+
+    unnamed.sort_unstable();
+    let shown: Vec<String> = unnamed
+        .iter()
+        .take(MAX_REPORTED_TAXA)
+        .map(|id| id.to_string())
+        .collect();
+    let more = if unnamed.len() > MAX_REPORTED_TAXA {
+        format!(" and {} more", unnamed.len() - MAX_REPORTED_TAXA)
+    } else {
+        String::new()
+    };
+
+    eprintln!(
+        "Warning: {} taxa do not have a scientific name in the names dump: {}{}",
+        unnamed.len(),
+        shown.join(", "),
+        more
+    );
+}
+
+fn report_unknown_ranks(unknown_ranks: &HashMap<String, usize>) {
+    let mut ranks: Vec<(&String, &usize)> = unknown_ranks.iter().collect();
+    ranks.sort_unstable();
+
+    for (rank, count) in ranks {
+        eprintln!(
+            "Warning: unknown rank \"{}\" of {} taxa is treated as \"no rank\"",
+            rank, count
+        );
+    }
+}
+
+pub(crate) fn parse_id(v: &str) -> Result<usize> {
     v.trim()
         .parse::<usize>()
         .with_context(|| format!("Unable to parse {} as usize", v))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_scientific_names_in_any_order() {
+        let names = "\
+9606\t|\thuman\t|\t\t|\tgenbank common name\t|
+2\t|\tBacteria\t|\tBacteria <bacteria>\t|\tscientific name\t|
+1\t|\troot\t|\t\t|\tscientific name\t|
+9606\t|\tHomo sapiens\t|\t\t|\tscientific name\t|
+2\t|\teubacteria\t|\t\t|\tgenbank common name\t|
+562\t|\tBacterium coli\t|\t\t|\tsynonym\t|
+";
+        let names = read_scientific_names(names.as_bytes()).unwrap();
+
+        assert_eq!(names.len(), 3);
+        assert_eq!(names[&1], "root");
+        assert_eq!(names[&2], "Bacteria");
+        assert_eq!(names[&9606], "Homo sapiens");
+        assert!(!names.contains_key(&562));
+    }
+}
@@ -0,0 +1,67 @@
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+
+use anyhow::{Context, Error, Result};
+use utils::{open_read, open_write};
+
+use crate::taxon_list::parse_id;
+
+/// Write every name from the names dump (scientific names, synonyms, common names, authorities,
+/// misspellings, ...) as a TSV file with the taxon id, name, name class and unique name.
+/// Unique names are only filled in by NCBI if the name is shared by multiple taxa, otherwise \N
+pub fn write_taxon_names(names_pb: &PathBuf, pb: &PathBuf) -> Result<()> {
+    let reader = open_read(names_pb).context("Unable to open names dump file")?;
+    let mut writer = open_write(pb).context("Unable to open taxon names output file")?;
+
+    convert_names(reader, &mut writer)
+}
+
+fn convert_names<R: BufRead, W: Write>(reader: R, writer: &mut W) -> Result<()> {
+    for line in reader.lines() {
+        let line = line.context("Error reading line from names dump file")?;
+        let row: Vec<&str> = line.split('|').map(str::trim).collect();
+        if row.len() < 4 {
+            return Err(Error::msg(format!(
+                "Malformed line in names dump file: {}",
+                line
+            )));
+        }
+
+        let unique_name = if row[2].is_empty() { "\\N" } else { row[2] };
+
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}",
+            parse_id(row[0])?,
+            row[1],
+            row[3],
+            unique_name
+        )
+        .context("Error writing to taxon names TSV file")?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_names() {
+        let names = "\
+562\t|\tEscherichia coli\t|\t\t|\tscientific name\t|
+562\t|\tE. coli\t|\t\t|\tcommon name\t|
+9606\t|\thuman\t|\thuman <Homo sapiens>\t|\tgenbank common name\t|
+";
+        let mut output = Vec::new();
+        convert_names(names.as_bytes(), &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "562\tEscherichia coli\tscientific name\t\\N\n\
+             562\tE. coli\tcommon name\t\\N\n\
+             9606\thuman\tgenbank common name\thuman <Homo sapiens>\n"
+        );
+    }
+}
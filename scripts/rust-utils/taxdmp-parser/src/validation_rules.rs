@@ -0,0 +1,263 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Error, Result};
+use ncbi::{Rank, Taxon};
+use regex::Regex;
+use serde::Deserialize;
+
+/// The rules that are used if no rules file is given
+const DEFAULT_RULES: &str = include_str!("../validation_rules.toml");
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    #[serde(default)]
+    rule: Vec<RuleConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+    name: String,
+    pattern: Option<String>,
+    #[serde(default)]
+    ranks: Vec<String>,
+    #[serde(default)]
+    ids: Vec<usize>,
+    #[serde(default)]
+    subtrees: Vec<usize>,
+    allow_pattern: Option<String>,
+    #[serde(default)]
+    allow_ids: Vec<usize>,
+    #[serde(default)]
+    allow_subtrees: Vec<usize>,
+}
+
+/// A single rule that invalidates taxa that satisfy all of its conditions
+struct Rule {
+    name: String,
+    pattern: Option<Regex>,
+    ranks: Vec<String>,
+    ids: HashSet<usize>,
+    subtrees: HashSet<usize>,
+    allow_pattern: Option<Regex>,
+    allow_ids: HashSet<usize>,
+    allow_subtrees: HashSet<usize>,
+}
+
+pub struct ValidationRules {
+    rules: Vec<Rule>,
+}
+
+impl ValidationRules {
+    /// Read the rules from a TOML file
+    pub fn from_file(pb: &PathBuf) -> Result<Self> {
+        let content = fs::read_to_string(pb).context("Unable to read validation rules file")?;
+        Self::parse(&content).with_context(|| format!("Invalid validation rules in {:?}", pb))
+    }
+
+    /// The rules Unipept has always used to invalidate taxa
+    pub fn default_rules() -> Result<Self> {
+        Self::parse(DEFAULT_RULES).context("Invalid default validation rules")
+    }
+
+    /// No rules at all, so every taxon is valid
+    pub fn none() -> Self {
+        ValidationRules { rules: Vec::new() }
+    }
+
+    fn parse(content: &str) -> Result<Self> {
+        let file: RulesFile = toml::from_str(content).context("Unable to parse rules")?;
+        let rules = file
+            .rule
+            .into_iter()
+            .map(Rule::compile)
+            .collect::<Result<Vec<Rule>>>()?;
+
+        Ok(ValidationRules { rules })
+    }
+
+    /// Find the first rule that invalidates the given taxon and return its index
+    /// `parent` is used to walk up the tree for subtree conditions.
+    pub fn matching_rule<F>(&self, id: usize, taxon: &Taxon, parent: F) -> Option<usize>
+    where
+        F: Fn(usize) -> Option<usize>,
+    {
+        self.rules
+            .iter()
+            .position(|rule| rule.matches(id, taxon, &parent))
+    }
+
+    pub fn rule_name(&self, index: usize) -> &str {
+        &self.rules[index].name
+    }
+}
+
+impl Rule {
+    fn compile(config: RuleConfig) -> Result<Self> {
+        let regex = |pattern: Option<String>| -> Result<Option<Regex>> {
+            pattern
+                .map(|p| {
+                    Regex::new(&p)
+                        .with_context(|| format!("Invalid pattern {} in rule {}", p, config.name))
+                })
+                .transpose()
+        };
+
+        let pattern = regex(config.pattern.clone())?;
+        let allow_pattern = regex(config.allow_pattern.clone())?;
+        if let Some(rank) = config.ranks.iter().find(|r| Rank::from_ncbi(r).is_none()) {
+            return Err(Error::msg(format!(
+                "Unknown rank {} in rule {}",
+                rank, config.name
+            )));
+        }
+
+        if pattern.is_none()
+            && config.ranks.is_empty()
+            && config.ids.is_empty()
+            && config.subtrees.is_empty()
+        {
+            return Err(Error::msg(format!(
+                "Rule {} does not have any conditions",
+                config.name
+            )));
+        }
+
+        Ok(Rule {
+            name: config.name,
+            pattern,
+            ranks: config.ranks,
+            ids: config.ids.into_iter().collect(),
+            subtrees: config.subtrees.into_iter().collect(),
+            allow_pattern,
+            allow_ids: config.allow_ids.into_iter().collect(),
+            allow_subtrees: config.allow_subtrees.into_iter().collect(),
+        })
+    }
+
+    fn matches<F>(&self, id: usize, taxon: &Taxon, parent: &F) -> bool
+    where
+        F: Fn(usize) -> Option<usize>,
+    {
+        let matches = self
+            .pattern
+            .as_ref()
+            .is_none_or(|p| p.is_match(&taxon.name))
+            && (self.ranks.is_empty() || self.ranks.contains(&taxon.rank_name))
+            && (self.ids.is_empty() || self.ids.contains(&id))
+            && (self.subtrees.is_empty() || in_subtree(id, &self.subtrees, parent));
+
+        let allowed = self
+            .allow_pattern
+            .as_ref()
+            .is_some_and(|p| p.is_match(&taxon.name))
+            || self.allow_ids.contains(&id)
+            || (!self.allow_subtrees.is_empty() && in_subtree(id, &self.allow_subtrees, parent));
+
+        matches && !allowed
+    }
+}
+
+/// Check whether a taxon is one of the given roots, or a descendant of one of them
+fn in_subtree<F>(mut id: usize, roots: &HashSet<usize>, parent: &F) -> bool
+where
+    F: Fn(usize) -> Option<usize>,
+{
+    loop {
+        if roots.contains(&id) {
+            return true;
+        }
+
+        match parent(id) {
+            Some(p) if p != id => id = p,
+            _ => return false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn taxon(name: &str, rank: Rank, parent: usize) -> Taxon {
+        Taxon::new(name.to_string(), rank, rank.to_string(), parent, true)
+    }
+
+    // 1 is the root, 2 a genus and 3 and 4 are species in that genus
+    fn parent(id: usize) -> Option<usize> {
+        match id {
+            1 | 2 => Some(1),
+            3 | 4 => Some(2),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_default_rules() {
+        let rules = ValidationRules::default_rules().unwrap();
+        let name = |id, taxon: &Taxon| {
+            rules
+                .matching_rule(id, taxon, parent)
+                .map(|i| rules.rule_name(i).to_string())
+        };
+
+        let digit = taxon("Escherichia coli O157", Rank::Species, 2);
+        assert_eq!(name(3, &digit).as_deref(), Some("species-with-digit"));
+
+        let virus = taxon("Human alphaherpesvirus 1", Rank::Species, 2);
+        assert_eq!(name(3, &virus), None);
+
+        let genus_digit = taxon("Genus 1", Rank::Genus, 1);
+        assert_eq!(name(2, &genus_digit), None);
+
+        let sp = taxon("Bacillus sp.", Rank::Species, 2);
+        assert_eq!(name(3, &sp).as_deref(), Some("unclassified-species"));
+
+        let metagenome = taxon("soil metagenome", Rank::Species, 2);
+        assert_eq!(
+            name(3, &metagenome).as_deref(),
+            Some("environmental-sample")
+        );
+
+        let other = taxon("other sequences", Rank::NoRank, 1);
+        assert_eq!(name(28384, &other).as_deref(), Some("excluded-taxa"));
+
+        let human = taxon("Homo sapiens", Rank::Species, 2);
+        assert_eq!(name(3, &human), None);
+    }
+
+    #[test]
+    fn test_subtrees_and_allow_overrides() {
+        let rules = ValidationRules::parse(
+            r#"
+            [[rule]]
+            name = "genus-2"
+            subtrees = [2]
+            allow_ids = [4]
+
+            [[rule]]
+            name = "allowed-everywhere"
+            ranks = ["species"]
+            allow_subtrees = [1]
+            "#,
+        )
+        .unwrap();
+
+        let species = taxon("Homo sapiens", Rank::Species, 2);
+        assert_eq!(rules.matching_rule(3, &species, parent), Some(0));
+        assert_eq!(rules.matching_rule(4, &species, parent), None);
+
+        let genus = taxon("Homo", Rank::Genus, 1);
+        assert_eq!(rules.matching_rule(2, &genus, parent), Some(0));
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        assert!(ValidationRules::parse("[[rule]]\nname = \"empty\"").is_err());
+        assert!(ValidationRules::parse("[[rule]]\nname = \"x\"\nranks = [\"nope\"]").is_err());
+        assert!(ValidationRules::parse("[[rule]]\nname = \"x\"\npattern = \"(\"").is_err());
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::thread;
+use std::thread::JoinHandle;
+
+use anyhow::{Context, Error, Result};
+use crossbeam_channel::{Receiver, Sender, bounded};
+use tables_generator::models::Entry;
+use tables_generator::table_writer::{DigestedPeptides, PeptideTableWriter};
+
+/// Digests entries into peptides on a pool of worker threads, and writes those peptides on a
+/// single writer thread in the order in which the entries were submitted.
+/// The workers also render the peptide rows if the output format allows it, so the writer thread
+/// only has to copy pre-rendered buffers into the output files.
+pub struct DigestPipeline {
+    s_entries: Option<Sender<(u64, i64, Entry)>>,
+    workers: Vec<JoinHandle<()>>,
+    writer: Option<JoinHandle<Result<PeptideTableWriter>>>,
+    submitted: u64,
+}
+
+impl DigestPipeline {
+    /// Start a pipeline with `threads` worker threads that writes to `peptide_writer`
+    /// Passing 0 as the amount of threads uses the amount of (virtual) CPUs available
+    pub fn start(peptide_writer: PeptideTableWriter, mut threads: usize) -> Self {
+        if threads == 0 {
+            threads = thread::available_parallelism().map_or(1, |n| n.get());
+        }
+
+        let (s_entries, r_entries) = bounded::<(u64, i64, Entry)>(threads * 2);
+        let (s_digested, r_digested) = bounded::<(u64, DigestedPeptides)>(threads * 2);
+
+        let digester = peptide_writer.digester(true);
+        let workers = (0..threads)
+            .map(|_| {
+                let r_entries = r_entries.clone();
+                let s_digested = s_digested.clone();
+                let digester = digester.clone();
+
+                thread::spawn(move || {
+                    for (index, entry_id, entry) in r_entries {
+                        // The writer thread only stops receiving when it failed
+                        if s_digested
+                            .send((index, digester.digest(entry_id, &entry)))
+                            .is_err()
+                        {
+                            break;
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        let writer = thread::spawn(move || write_in_order(peptide_writer, r_digested));
+
+        Self {
+            s_entries: Some(s_entries),
+            workers,
+            writer: Some(writer),
+            submitted: 0,
+        }
+    }
+
+    /// Submit an entry that was written to the UniProt table with the given id
+    pub fn submit(&mut self, entry_id: i64, entry: Entry) -> Result<()> {
+        let sent = match &self.s_entries {
+            Some(sender) => sender.send((self.submitted, entry_id, entry)).is_ok(),
+            None => false,
+        };
+
+        if !sent {
+            // The workers only stop when the writer thread failed, so report its error
+            self.finish()?;
+            return Err(Error::msg("Peptide digestion stopped unexpectedly"));
+        }
+
+        self.submitted += 1;
+        Ok(())
+    }
+
+    /// Wait until all submitted entries are written, and return the peptide writer
+    pub fn finish(&mut self) -> Result<PeptideTableWriter> {
+        // Dropping the sender lets the workers, and then the writer, run out of input
+        self.s_entries.take();
+
+        for worker in self.workers.drain(..) {
+            worker
+                .join()
+                .map_err(|_| Error::msg("Peptide digestion thread panicked"))?;
+        }
+
+        self.writer
+            .take()
+            .context("Peptide digestion was already finished")?
+            .join()
+            .map_err(|_| Error::msg("Peptide writer thread panicked"))?
+    }
+}
+
+/// Write digested entries in order of their index, buffering the ones that arrive early
+fn write_in_order(
+    mut peptide_writer: PeptideTableWriter,
+    r_digested: Receiver<(u64, DigestedPeptides)>,
+) -> Result<PeptideTableWriter> {
+    let mut pending: HashMap<u64, DigestedPeptides> = HashMap::new();
+    let mut next: u64 = 0;
+
+    for (index, digested) in r_digested {
+        pending.insert(index, digested);
+
+        while let Some(digested) = pending.remove(&next) {
+            peptide_writer
+                .write_digested(&digested)
+                .context("Failed to store peptide")?;
+            next += 1;
+        }
+    }
+
+    if !pending.is_empty() {
+        return Err(Error::msg(format!(
+            "{} digested entries were never written",
+            pending.len()
+        )));
+    }
+
+    Ok(peptide_writer)
+}
@@ -1,51 +1,224 @@
+mod digest_pipeline;
+
+use crate::digest_pipeline::DigestPipeline;
 use anyhow::{Context, Result};
 use clap::Parser;
 use dat_parser::uniprot_dat_parser;
 use std::path::PathBuf;
+use tables_generator::decoys::DecoyStrategy;
+use tables_generator::identifiers::IdStrategy;
 use tables_generator::models::Entry;
-use tables_generator::table_writer::{EntryTableWriter, PeptideTableWriter};
+use tables_generator::sharding::ShardStrategy;
+use tables_generator::table_format::OutputFormat;
+use tables_generator::table_writer::{
+    DecoyTableWriter, EntryTableWriter, PeptideTableWriter, ProteinStatsWriter, ProteomeTableWriter,
+};
+use tables_generator::taxon_list::{TaxonFilter, parse_taxon_id_file};
 use utils::open_sin;
 
 fn main() -> Result<()> {
     let args = Cli::parse();
 
     let reader = open_sin();
-    let mut entry_writer = EntryTableWriter::new(&args.taxa, &args.uniprot_entries)
-        .context("Unable to instantiate TableWriter")?;
-    let mut peptide_writer =
-        PeptideTableWriter::new(&args.peptides, args.peptide_min, args.peptide_max)
+    let mut entry_writer = EntryTableWriter::new(
+        &args.taxa,
+        &taxon_filter(&args)?,
+        &args.uniprot_entries,
+        args.output_format,
+        args.id_strategy,
+    )
+    .context("Unable to instantiate TableWriter")?;
+    if let Some(merged_taxa) = &args.merged_taxa {
+        entry_writer.load_merged_taxa(merged_taxa)?;
+    }
+    if let Some(taxon_mapping) = &args.taxon_mapping {
+        entry_writer.load_taxon_mapping(taxon_mapping)?;
+    }
+    let peptide_writer = PeptideTableWriter::new_sharded(
+        &args.peptides,
+        args.peptide_shards,
+        args.shard_strategy,
+        args.peptide_min,
+        args.peptide_max,
+        args.output_format,
+        args.id_strategy,
+    )
+    .context("Unable to instantiate TableWriter")?;
+
+    let mut proteome_writer = match &args.proteomes {
+        Some(pb) => Some(
+            ProteomeTableWriter::new(pb, args.output_format)
+                .context("Unable to instantiate ProteomeTableWriter")?,
+        ),
+        None => None,
+    };
+    let mut stats_writer = match &args.protein_stats {
+        Some(pb) => Some(
+            ProteinStatsWriter::new(pb, args.output_format, args.peptide_min, args.peptide_max)
+                .context("Unable to instantiate ProteinStatsWriter")?,
+        ),
+        None => None,
+    };
+
+    // Decoy peptides are digested by a pipeline of their own, as they go to a separate table
+    let mut decoy_writer = match (
+        &args.decoy_strategy,
+        &args.decoy_entries,
+        &args.decoy_peptides,
+    ) {
+        (Some(strategy), Some(entries), Some(peptides)) => {
+            let writer =
+                DecoyTableWriter::new(entries, *strategy, args.decoy_seed, args.output_format)
+                    .context("Unable to instantiate DecoyTableWriter")?;
+            let mut peptide_writer = PeptideTableWriter::new(
+                peptides,
+                args.peptide_min,
+                args.peptide_max,
+                args.output_format,
+                args.id_strategy,
+            )
             .context("Unable to instantiate TableWriter")?;
+            peptide_writer.use_negative_ids();
+
+            Some((
+                writer,
+                DigestPipeline::start(peptide_writer, args.digest_threads),
+            ))
+        }
+        _ => None,
+    };
+
+    let mut digest_pipeline = DigestPipeline::start(peptide_writer, args.digest_threads);
 
     //write_header();
     let parser = uniprot_dat_parser(reader, args.threads);
 
     for entry in parser {
-        let parsed_entry: Entry = entry.context("Failed to parse entry")?.into();
+        let mut parsed_entry: Entry = entry.context("Failed to parse entry")?.into();
 
         let entry_id = entry_writer
-            .write_uniprot_entry(&parsed_entry)
+            .write_uniprot_entry(&mut parsed_entry)
             .context("Failed to store entry")?;
 
         if entry_id != -1 {
-            peptide_writer
-                .write(entry_id, parsed_entry)
-                .context("Failed to store peptide")?;
+            if let Some(proteome_writer) = &mut proteome_writer {
+                proteome_writer
+                    .write_proteomes(&parsed_entry)
+                    .context("Failed to store proteome references")?;
+            }
+            if let Some(stats_writer) = &mut stats_writer {
+                stats_writer
+                    .write(entry_id, &parsed_entry)
+                    .context("Failed to store protein statistics")?;
+            }
+            if let Some((decoy_writer, decoy_pipeline)) = &mut decoy_writer {
+                let (decoy_id, decoy) = decoy_writer
+                    .write(entry_id, &parsed_entry)
+                    .context("Failed to store decoy entry")?;
+                decoy_pipeline.submit(decoy_id, decoy)?;
+            }
+            digest_pipeline.submit(entry_id, parsed_entry)?;
         }
     }
 
+    let mut peptide_writer = digest_pipeline.finish()?;
+
+    entry_writer
+        .finish()
+        .context("Failed to finish UniProt output file")?;
+    peptide_writer
+        .finish()
+        .context("Failed to finish peptides output file")?;
+    if let Some(proteome_writer) = &mut proteome_writer {
+        proteome_writer
+            .finish()
+            .context("Failed to finish proteomes output file")?;
+    }
+    if let Some(stats_writer) = &mut stats_writer {
+        stats_writer
+            .finish()
+            .context("Failed to finish protein statistics output file")?;
+    }
+    if let Some((decoy_writer, decoy_pipeline)) = &mut decoy_writer {
+        decoy_writer
+            .finish()
+            .context("Failed to finish decoy entries output file")?;
+        decoy_pipeline
+            .finish()?
+            .finish()
+            .context("Failed to finish decoy peptides output file")?;
+    }
+    if let Some(manifest) = &args.shard_manifest {
+        peptide_writer
+            .write_manifest(manifest)
+            .context("Failed to write shard manifest")?;
+    }
+
+    if let Some(report) = &args.dropped_report {
+        entry_writer
+            .write_dropped_report(report)
+            .context("Failed to write dropped entries report")?;
+    }
+    entry_writer.check_dropped(args.max_dropped_fraction)?;
+
     Ok(())
 }
 
+fn taxon_filter(args: &Cli) -> Result<TaxonFilter> {
+    Ok(TaxonFilter {
+        valid_only: args.valid_taxa_only,
+        subtrees: args.taxon_subtree.clone(),
+        include: match &args.include_taxa {
+            Some(pb) => parse_taxon_id_file(pb).context("Unable to read included taxa")?,
+            None => Vec::new(),
+        },
+        exclude: match &args.exclude_taxa {
+            Some(pb) => parse_taxon_id_file(pb).context("Unable to read excluded taxa")?,
+            None => Vec::new(),
+        },
+    })
+}
+
 #[derive(Parser, Debug)]
 struct Cli {
     /// Path to the taxa.tsv file (generated by taxdmp-parser)
     #[clap(long)]
     taxa: PathBuf,
 
+    /// Path to the merged_taxa.tsv file (generated by taxdmp-parser)
+    #[clap(long)]
+    merged_taxa: Option<PathBuf>,
+
+    /// Path to a file that maps NCBI taxon ids onto the ids in the taxa file, if the taxa file
+    /// is not based on the NCBI taxonomy (e.g. the GTDB mapping generated by taxdmp-parser)
+    #[clap(long)]
+    taxon_mapping: Option<PathBuf>,
+
+    /// Only write entries of taxa that are marked as valid
+    #[clap(long)]
+    valid_taxa_only: bool,
+
+    /// Only write entries in the subtree of these taxa (comma-separated taxon ids)
+    #[clap(long, value_delimiter = ',')]
+    taxon_subtree: Vec<usize>,
+
+    /// Path to a file with taxon ids (one per line) of which entries are written as well,
+    /// even if they are not part of one of the subtrees
+    #[clap(long)]
+    include_taxa: Option<PathBuf>,
+
+    /// Path to a file with taxon ids (one per line) of which entries are never written
+    #[clap(long)]
+    exclude_taxa: Option<PathBuf>,
+
     /// Path to the UniProt output file
     #[clap(long)]
     uniprot_entries: PathBuf,
 
+    /// Path to an output file that links the written entries to their proteomes
+    #[clap(long)]
+    proteomes: Option<PathBuf>,
+
     /// Path to the peptides output file
     #[clap(long)]
     peptides: PathBuf,
@@ -58,7 +231,73 @@ struct Cli {
     #[clap(long)]
     peptide_max: usize,
 
+    /// Path to an output file with sequence-level properties of every entry, counting the
+    /// tryptic peptides of which the length lies between the minimum and maximum peptide length
+    #[clap(long)]
+    protein_stats: Option<PathBuf>,
+
+    /// Also generate a decoy protein for every entry: "reverse" reverses the sequence,
+    /// "pseudo-reverse" reverses it but keeps the K/R cleavage sites in place, "shuffle" shuffles
+    /// it with a fixed seed
+    #[clap(long, requires_all = ["decoy_entries", "decoy_peptides"])]
+    decoy_strategy: Option<DecoyStrategy>,
+
+    /// Seed used to shuffle the decoy sequences
+    #[clap(long, default_value_t = 42)]
+    decoy_seed: u64,
+
+    /// Path to the decoy entries output file (accession numbers are prefixed with DECOY_ and ids
+    /// are the negated ids of the target entries)
+    #[clap(long, requires = "decoy_strategy")]
+    decoy_entries: Option<PathBuf>,
+
+    /// Path to the decoy peptides output file, which is kept separate from the target peptides
+    /// (its rows are numbered -1, -2, ...)
+    #[clap(long, requires = "decoy_strategy")]
+    decoy_peptides: Option<PathBuf>,
+
+    /// Divide the peptides over this many files, of which the names are derived from the
+    /// peptides output path (e.g. peptides.0003.tsv). Only the output is sharded: the other
+    /// tools (and generate_umgap_tables.sh) still expect a single peptide table, so the shards
+    /// have to be sorted and concatenated in the order of the manifest before they are numbered
+    /// by sequence-numberer. This only yields a sorted table with the "prefix" strategy.
+    #[clap(long, default_value_t = 1)]
+    peptide_shards: usize,
+
+    /// How peptides are divided over the shards: "hash" spreads them evenly, "prefix" gives
+    /// every shard a range of equalized sequences
+    #[clap(long, default_value = "hash")]
+    shard_strategy: ShardStrategy,
+
+    /// Path to an output file that lists every peptide shard with its amount of rows
+    #[clap(long)]
+    shard_manifest: Option<PathBuf>,
+
     /// Amount of threads to use for parsing
     #[clap(long, default_value_t = 0)]
     threads: usize,
+
+    /// Amount of threads to use for digesting entries into peptides (decoys get a pool of the
+    /// same size)
+    #[clap(long, default_value_t = 0)]
+    digest_threads: usize,
+
+    /// Format of the output tables (tsv, parquet or arrow)
+    #[clap(long, default_value = "tsv")]
+    output_format: OutputFormat,
+
+    /// How entry ids are assigned: "counter" numbers entries in output order, "stable" derives
+    /// them from the accession number. Peptide rows are always numbered in output order, but
+    /// "stable" also adds a sequence_id column with a hash of the original sequence (ids that
+    /// are shared by distinct sequences are reported by sequence-numberer)
+    #[clap(long, default_value = "counter")]
+    id_strategy: IdStrategy,
+
+    /// Path to an output file that lists the amount of dropped entries per unknown taxon id
+    #[clap(long)]
+    dropped_report: Option<PathBuf>,
+
+    /// Fail if the fraction of entries dropped because of unknown taxa exceeds this value
+    #[clap(long)]
+    max_dropped_fraction: Option<f64>,
 }
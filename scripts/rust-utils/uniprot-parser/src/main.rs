@@ -2,52 +2,194 @@ use anyhow::{Context, Result};
 use clap::Parser;
 use dat_parser::uniprot_dat_parser;
 use std::path::PathBuf;
+use tables_generator::identifiers::IdStrategy;
 use tables_generator::models::Entry;
-use tables_generator::table_writer::{EntryTableWriter, ProteomeTableWriter};
+use tables_generator::table_format::OutputFormat;
+use tables_generator::table_writer::{EntryTableWriter, ProteinStatsWriter, ProteomeTableWriter};
+use tables_generator::taxon_list::{TaxonFilter, parse_taxon_id_file};
 use utils::open_sin;
 
 fn main() -> Result<()> {
     let args = Cli::parse();
 
     let reader = open_sin();
-    let mut entries_writer = EntryTableWriter::new(&args.taxa, &args.uniprot_entries)
-        .context("Unable to instantiate EntryTableWriter")?;
-    let mut proteome_writer = ProteomeTableWriter::new(&args.proteomes)
+    let mut entries_writer = EntryTableWriter::new(
+        &args.taxa,
+        &taxon_filter(&args)?,
+        &args.uniprot_entries,
+        args.output_format,
+        args.id_strategy,
+    )
+    .context("Unable to instantiate EntryTableWriter")?;
+    if let Some(merged_taxa) = &args.merged_taxa {
+        entries_writer.load_merged_taxa(merged_taxa)?;
+    }
+    if let Some(taxon_mapping) = &args.taxon_mapping {
+        entries_writer.load_taxon_mapping(taxon_mapping)?;
+    }
+    if let (Some(text), Some(offsets)) = (&args.protein_text, &args.protein_offsets) {
+        entries_writer
+            .write_protein_text(text, offsets, args.equate_il)
+            .context("Unable to open protein text output")?;
+    }
+    let mut proteome_writer = ProteomeTableWriter::new(&args.proteomes, args.output_format)
         .context("Unable to instantiate ProteomeTableWriter")?;
+    let mut stats_writer = match &args.protein_stats {
+        Some(pb) => Some(
+            ProteinStatsWriter::new(
+                pb,
+                args.output_format,
+                args.stats_peptide_min,
+                args.stats_peptide_max,
+            )
+            .context("Unable to instantiate ProteinStatsWriter")?,
+        ),
+        None => None,
+    };
 
     //write_header();
     let parser = uniprot_dat_parser(reader, args.threads);
 
     for entry in parser {
-        let parsed_entry: Entry = entry.context("Failed to parse entry")?.into();
+        let mut parsed_entry: Entry = entry.context("Failed to parse entry")?.into();
 
         proteome_writer
             .write_proteomes(&parsed_entry)
             .context("Failed to store proteome references")?;
 
-        entries_writer
-            .write(parsed_entry)
+        let entry_id = entries_writer
+            .write_uniprot_entry(&mut parsed_entry)
             .context("Failed to store entry")?;
+
+        if let Some(stats_writer) = &mut stats_writer
+            && entry_id != -1
+        {
+            stats_writer
+                .write(entry_id, &parsed_entry)
+                .context("Failed to store protein statistics")?;
+        }
+    }
+
+    entries_writer
+        .finish()
+        .context("Failed to finish UniProt output file")?;
+    proteome_writer
+        .finish()
+        .context("Failed to finish proteomes output file")?;
+    if let Some(stats_writer) = &mut stats_writer {
+        stats_writer
+            .finish()
+            .context("Failed to finish protein statistics output file")?;
     }
 
+    if let Some(report) = &args.dropped_report {
+        entries_writer
+            .write_dropped_report(report)
+            .context("Failed to write dropped entries report")?;
+    }
+    entries_writer.check_dropped(args.max_dropped_fraction)?;
+
     Ok(())
 }
 
+fn taxon_filter(args: &Cli) -> Result<TaxonFilter> {
+    Ok(TaxonFilter {
+        valid_only: args.valid_taxa_only,
+        subtrees: args.taxon_subtree.clone(),
+        include: match &args.include_taxa {
+            Some(pb) => parse_taxon_id_file(pb).context("Unable to read included taxa")?,
+            None => Vec::new(),
+        },
+        exclude: match &args.exclude_taxa {
+            Some(pb) => parse_taxon_id_file(pb).context("Unable to read excluded taxa")?,
+            None => Vec::new(),
+        },
+    })
+}
+
 #[derive(Parser, Debug)]
 struct Cli {
     /// Path to the taxa.tsv file (generated by taxdmp-parser)
     #[clap(long)]
     taxa: PathBuf,
 
+    /// Path to the merged_taxa.tsv file (generated by taxdmp-parser)
+    #[clap(long)]
+    merged_taxa: Option<PathBuf>,
+
+    /// Path to a file that maps NCBI taxon ids onto the ids in the taxa file, if the taxa file
+    /// is not based on the NCBI taxonomy (e.g. the GTDB mapping generated by taxdmp-parser)
+    #[clap(long)]
+    taxon_mapping: Option<PathBuf>,
+
+    /// Only write entries of taxa that are marked as valid
+    #[clap(long)]
+    valid_taxa_only: bool,
+
+    /// Only write entries in the subtree of these taxa (comma-separated taxon ids)
+    #[clap(long, value_delimiter = ',')]
+    taxon_subtree: Vec<usize>,
+
+    /// Path to a file with taxon ids (one per line) of which entries are written as well,
+    /// even if they are not part of one of the subtrees
+    #[clap(long)]
+    include_taxa: Option<PathBuf>,
+
+    /// Path to a file with taxon ids (one per line) of which entries are never written
+    #[clap(long)]
+    exclude_taxa: Option<PathBuf>,
+
     /// Path to the UniProt output file
     #[clap(long)]
     uniprot_entries: PathBuf,
 
+    /// Path to an output file with the sequences of all entries, separated by '-' and terminated
+    /// by '$', that serves as input for the suffix array
+    #[clap(long, requires = "protein_offsets")]
+    protein_text: Option<PathBuf>,
+
+    /// Path to a binary output file that maps positions in the protein text onto entry ids
+    #[clap(long, requires = "protein_text")]
+    protein_offsets: Option<PathBuf>,
+
+    /// Replace every isoleucine by a leucine in the protein text
+    #[clap(long, requires = "protein_text")]
+    equate_il: bool,
+
     /// Path to the proteomes output file
     #[clap(long)]
     proteomes: PathBuf,
 
+    /// Path to an output file with sequence-level properties of every entry
+    #[clap(long)]
+    protein_stats: Option<PathBuf>,
+
+    /// Minimum length of the tryptic peptides that are counted in the protein statistics
+    #[clap(long, default_value_t = 5)]
+    stats_peptide_min: usize,
+
+    /// Maximum length of the tryptic peptides that are counted in the protein statistics
+    #[clap(long, default_value_t = 50)]
+    stats_peptide_max: usize,
+
     /// Amount of threads to use for parsing
     #[clap(long, default_value_t = 0)]
     threads: usize,
+
+    /// Format of the output tables (tsv, parquet or arrow)
+    #[clap(long, default_value = "tsv")]
+    output_format: OutputFormat,
+
+    /// How entry ids are assigned: "counter" numbers entries in output order, "stable" derives
+    /// them from the accession number
+    #[clap(long, default_value = "counter")]
+    id_strategy: IdStrategy,
+
+    /// Path to an output file that lists the amount of dropped entries per unknown taxon id
+    #[clap(long)]
+    dropped_report: Option<PathBuf>,
+
+    /// Fail if the fraction of entries dropped because of unknown taxa exceeds this value
+    #[clap(long)]
+    max_dropped_fraction: Option<f64>,
 }
@@ -20,9 +20,12 @@ pub fn open_read(pb: &PathBuf) -> Result<BufReader<File>> {
 }
 
 /// Create a BufWriter that writes to a file denoted by its PathBuf
+/// The file is created if it does not exist yet, and truncated otherwise
 pub fn open_write(pb: &PathBuf) -> Result<BufWriter<File>> {
     let file = OpenOptions::new()
         .write(true)
+        .create(true)
+        .truncate(true)
         .open(pb)
         .with_context(|| format!("Failed to open file \"{}\" for writing", pb.display()))?;
     Ok(BufWriter::new(file))
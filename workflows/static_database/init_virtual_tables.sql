@@ -12,3 +12,18 @@ CREATE VIRTUAL TABLE `virtual_taxons` USING fts5 (
 
 -- Now, populate this table using the data that's already present in the taxons table.
 INSERT INTO `virtual_taxons` SELECT id, name, rank as rank_name FROM `taxons`;
+
+-- The synonyms, common names, ... of every taxon are kept in a separate table, so that searching for "E. coli" or
+-- "human" finds the right taxon without changing the shape of `virtual_taxons`. The scientific names are already
+-- present in `virtual_taxons`, so these are skipped here.
+CREATE VIRTUAL TABLE `virtual_taxon_names` USING fts5 (
+    taxon_id,
+    name,
+    rank_name,
+    name_class UNINDEXED
+);
+
+INSERT INTO `virtual_taxon_names`
+SELECT taxon_names.taxon_id, taxon_names.name, taxons.rank as rank_name, taxon_names.name_class
+FROM `taxon_names` INNER JOIN `taxons` ON taxons.id = taxon_names.taxon_id
+WHERE taxon_names.name_class != 'scientific name';
@@ -36,6 +36,15 @@ CREATE TABLE IF NOT EXISTS `taxons` (
   PRIMARY KEY (`id`)
 );
 
+CREATE TABLE IF NOT EXISTS `taxon_names` (
+  `taxon_id` INT UNSIGNED NOT NULL ,
+  `name` TEXT NOT NULL ,
+  `name_class` TEXT NOT NULL ,
+  `unique_name` TEXT NULL DEFAULT NULL
+);
+
+CREATE INDEX idx_taxon_names_taxon_id ON taxon_names(taxon_id);
+
 CREATE TABLE `lineages` (
   `taxon_id` INT NOT NULL ,
   `domain` INT NULL DEFAULT NULL ,